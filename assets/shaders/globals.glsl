@@ -0,0 +1,19 @@
+// Uniform blocks shared by every program, filled once per frame by the
+// renderer. Member order and types must match `renderer::uniform_buffer`.
+
+layout(std140) uniform FrameGlobals {
+    mat4 uView;
+    mat4 uProjection;
+    vec3 uEyePos;
+};
+
+layout(std140) uniform Lighting {
+    vec3 uLightPos;
+    float uAmbientStrength;
+    vec3 uFogColor;
+    float uSpecularStrength;
+    float uFogNear;
+    float uFogFar;
+    bool uEnabledLighting;
+    bool uEnabledFog;
+};
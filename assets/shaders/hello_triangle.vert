@@ -0,0 +1,15 @@
+#version 410 core
+
+#include "globals.glsl"
+
+uniform mat4 model;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 color;
+
+out vec3 v_color;  // goes to the fragment shader
+
+void main() {
+    gl_Position = uProjection * uView * model * vec4(position, 1.0);
+    v_color = color;
+}
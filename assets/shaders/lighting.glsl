@@ -0,0 +1,32 @@
+// Phong lighting and linear distance fog shared by the lit entities.
+
+#include "globals.glsl"
+
+vec3 applyLighting(vec3 albedo, vec3 fragNorm, vec3 fragPos) {
+    if (!uEnabledLighting) {
+        return albedo;
+    }
+
+    vec3 norm = normalize(fragNorm);
+
+    vec3 lightDir = normalize(uLightPos - fragPos);
+    float diffuse = max(dot(norm, lightDir), 0.0);
+
+    vec3 viewDir = normalize(uEyePos - fragPos);
+    vec3 reflectDir = reflect(-lightDir, norm);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
+    float specular = uSpecularStrength * spec;
+
+    return albedo * (uAmbientStrength + diffuse + specular);
+}
+
+vec3 applyFog(vec3 rgb, vec3 fragPos) {
+    if (!uEnabledFog) {
+        return rgb;
+    }
+
+    float d = length(uEyePos - fragPos);
+    float f = clamp((uFogFar - d) / (uFogFar - uFogNear), 0.0, 1.0);
+
+    return mix(uFogColor, rgb, f);
+}
@@ -0,0 +1,27 @@
+#version 410 core
+
+#include "lighting.glsl"
+
+layout(location = 0) out vec4 FragColor;
+
+uniform sampler2D tex;
+
+in vec3 fragNorm;
+in vec3 fragPos;
+
+in vec2 TexCoord;
+flat in vec4 UvRect;
+// Tints the texture of each cube
+flat in vec3 Color;
+
+void main() {
+    vec2 atlasCoord = UvRect.xy + fract(TexCoord) * UvRect.zw;
+    vec2 dx = dFdx(TexCoord) * UvRect.zw;
+    vec2 dy = dFdy(TexCoord) * UvRect.zw;
+    vec4 albedo = textureGrad(tex, atlasCoord, dx, dy) * vec4(Color, 1.0);
+
+    vec3 finalRgb = applyLighting(albedo.rgb, fragNorm, fragPos);
+    finalRgb = applyFog(finalRgb, fragPos);
+
+    FragColor = vec4(finalRgb, 1.0);
+}
@@ -0,0 +1,30 @@
+#version 410 core
+
+#include "globals.glsl"
+
+uniform mat4 model;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 textureCoord;
+layout(location = 2) in vec3 normal;
+// Atlas tile of the square: min corner, then size
+layout(location = 3) in vec4 uvRect;
+// Per cube
+layout(location = 4) in mat4 instanceModel;
+layout(location = 8) in vec3 instanceColor;
+
+out vec2 TexCoord;
+flat out vec4 UvRect;
+flat out vec3 Color;
+out vec3 fragNorm;
+out vec3 fragPos;
+
+void main() {
+    mat4 cubeModel = model * instanceModel;
+    gl_Position = uProjection * uView * cubeModel * vec4(position, 1.0);
+    TexCoord = textureCoord;
+    UvRect = uvRect;
+    Color = instanceColor;
+    fragPos = vec3(cubeModel * vec4(position, 1.0));
+    fragNorm = mat3(transpose(inverse(cubeModel))) * normal;
+}
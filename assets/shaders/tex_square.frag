@@ -0,0 +1,33 @@
+#version 410 core
+
+#include "lighting.glsl"
+
+layout(location = 0) out vec4 FragColor;
+
+uniform sampler2D tex;
+// Light sources are drawn as is, neither lit nor fogged
+uniform bool uEmissive;
+
+in vec3 fragNorm;
+in vec3 fragPos;
+
+in vec2 TexCoord;
+flat in vec4 UvRect;
+
+void main() {
+    // Greedy meshed squares repeat their texture, wrap inside the atlas tile
+    vec2 atlasCoord = UvRect.xy + fract(TexCoord) * UvRect.zw;
+    // fract jumps on every repeat, pick the mipmap from the continuous UVs
+    vec2 dx = dFdx(TexCoord) * UvRect.zw;
+    vec2 dy = dFdy(TexCoord) * UvRect.zw;
+    vec4 albedo = textureGrad(tex, atlasCoord, dx, dy);
+    if (uEmissive) {
+        FragColor = vec4(albedo.rgb, 1.0);
+        return;
+    }
+
+    vec3 finalRgb = applyLighting(albedo.rgb, fragNorm, fragPos);
+    finalRgb = applyFog(finalRgb, fragPos);
+
+    FragColor = vec4(finalRgb, 1.0);
+}
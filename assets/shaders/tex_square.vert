@@ -0,0 +1,24 @@
+#version 410 core
+
+#include "globals.glsl"
+
+uniform mat4 model;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 textureCoord;
+layout(location = 2) in vec3 normal;
+// Atlas tile of the square: min corner, then size
+layout(location = 3) in vec4 uvRect;
+
+out vec2 TexCoord;
+flat out vec4 UvRect;
+out vec3 fragNorm;
+out vec3 fragPos;
+
+void main() {
+    gl_Position = uProjection * uView * model * vec4(position, 1.0);
+    TexCoord = textureCoord;
+    UvRect = uvRect;
+    fragPos = vec3(model * vec4(position, 1.0));
+    fragNorm = mat3(transpose(inverse(model))) * normal;
+}
@@ -0,0 +1,18 @@
+#version 410 core
+
+#include "lighting.glsl"
+
+layout(location = 0) out vec4 FragColor;
+
+in vec3 fragNorm;
+in vec3 fragPos;
+flat in vec3 fragColor;
+
+void main() {
+    vec4 albedo = vec4(fragColor, 1.0);
+
+    vec3 finalRgb = applyLighting(albedo.rgb, fragNorm, fragPos);
+    finalRgb = applyFog(finalRgb, fragPos);
+
+    FragColor = vec4(finalRgb, 1.0);
+}
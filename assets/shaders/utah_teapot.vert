@@ -0,0 +1,24 @@
+#version 410 core
+
+#include "globals.glsl"
+
+uniform mat4 model;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+// Per teapot
+layout(location = 2) in mat4 instanceModel;
+layout(location = 6) in vec3 instanceColor;
+
+out vec3 fragNorm;
+out vec3 fragPos;
+flat out vec3 fragColor;
+
+void main() {
+    mat4 teapotModel = model * instanceModel;
+    gl_Position = uProjection * uView * teapotModel * vec4(position, 1.0);
+    fragPos = vec3(teapotModel * vec4(position, 1.0));
+    // Use the upper 3x3 of the model matrix for rotation/scaling
+    fragNorm = mat3(transpose(inverse(teapotModel))) * normal;
+    fragColor = instanceColor;
+}
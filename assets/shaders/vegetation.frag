@@ -0,0 +1,16 @@
+#version 410 core
+
+#include "lighting.glsl"
+
+layout(location = 0) out vec4 FragColor;
+
+in vec3 fragNorm;
+in vec3 fragPos;
+in vec3 fragColor;
+
+void main() {
+    vec3 finalRgb = applyLighting(fragColor, fragNorm, fragPos);
+    finalRgb = applyFog(finalRgb, fragPos);
+
+    FragColor = vec4(finalRgb, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 410 core
+
+#include "globals.glsl"
+
+uniform mat4 model;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 color;
+// Per instance, the model matrix columns take locations 3 to 6
+layout(location = 3) in mat4 instanceModel;
+layout(location = 7) in vec3 instanceColor;
+
+out vec3 fragNorm;
+out vec3 fragPos;
+out vec3 fragColor;
+
+void main() {
+    mat4 world = model * instanceModel;
+    gl_Position = uProjection * uView * world * vec4(position, 1.0);
+    fragPos = vec3(world * vec4(position, 1.0));
+    fragNorm = mat3(transpose(inverse(world))) * normal;
+    fragColor = color * instanceColor;
+}
@@ -0,0 +1,54 @@
+#version 410 core
+
+#include "lighting.glsl"
+
+layout(location = 0) out vec4 FragColor;
+
+// The world mirrored by the water, and the world under it
+uniform sampler2D reflectionTex;
+uniform sampler2D refractionTex;
+// Tileable ripples, x and z in red and green, up in blue
+uniform sampler2D normalMap;
+uniform float uTime;
+uniform vec3 uWaterColor;
+
+in vec4 clipPos;
+in vec3 fragPos;
+
+// World units covered by one tile of the normal map
+const float TILE = 8.0;
+// How far the ripples shift the reflected and refracted images, in screen UVs
+const float DISTORTION = 0.02;
+
+vec3 rippleNormal(vec2 uv) {
+    vec3 n = texture(normalMap, uv).rgb * 2.0 - 1.0;
+    return vec3(n.r, n.b, n.g);
+}
+
+void main() {
+    // Two layers scrolling apart, so the ripples never repeat the same way
+    vec2 uv = fragPos.xz / TILE;
+    vec3 normal = normalize(
+        rippleNormal(uv + vec2(uTime * 0.03, 0.0)) +
+        rippleNormal(uv * 0.7 + vec2(0.0, uTime * 0.02))
+    );
+
+    // Where the fragment is on screen, and so in the reflection and refraction
+    vec2 screen = clipPos.xy / clipPos.w * 0.5 + 0.5;
+    vec2 offset = normal.xz * DISTORTION;
+    vec3 reflection = texture(reflectionTex, screen + offset).rgb;
+    vec3 refraction = mix(texture(refractionTex, screen + offset).rgb, uWaterColor, 0.4);
+
+    // Schlick's approximation, water reflects 2% of the light head on
+    vec3 viewDir = normalize(uEyePos - fragPos);
+    float fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(viewDir, normal), 0.0), 5.0);
+    vec3 rgb = mix(refraction, reflection, fresnel);
+
+    if (uEnabledLighting) {
+        vec3 lightDir = normalize(uLightPos - fragPos);
+        float spec = pow(max(dot(viewDir, reflect(-lightDir, normal)), 0.0), 64.0);
+        rgb += vec3(uSpecularStrength * spec);
+    }
+
+    FragColor = vec4(applyFog(rgb, fragPos), 1.0);
+}
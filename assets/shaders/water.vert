@@ -0,0 +1,16 @@
+#version 410 core
+
+#include "globals.glsl"
+
+uniform mat4 model;
+
+layout(location = 0) in vec3 position;
+
+out vec4 clipPos;
+out vec3 fragPos;
+
+void main() {
+    fragPos = vec3(model * vec4(position, 1.0));
+    clipPos = uProjection * uView * vec4(fragPos, 1.0);
+    gl_Position = clipPos;
+}
@@ -1,4 +1,3 @@
-use glam::Vec3;
 use glutin::context::{ContextApi, ContextAttributesBuilder, Version};
 use glutin::display::GetGlDisplay;
 use glutin::{config::GetGlConfig, context::NotCurrentContext};
@@ -6,10 +5,9 @@ use glutin_winit::GlWindow;
 use raw_window_handle::HasWindowHandle;
 use std::ffi::CString;
 use std::rc::Rc;
-use std::time::Instant;
-use std::vec;
+use std::time::{Duration, Instant};
 use std::{error::Error, num::NonZeroU32};
-use winit::event::ElementState;
+use winit::event::{ElementState, MouseButton};
 use winit::keyboard::PhysicalKey;
 use winit::window::Window;
 
@@ -26,42 +24,26 @@ use winit::{
     keyboard::{Key, NamedKey},
 };
 
-use crate::camera::{Camera, CameraMovement};
-use crate::entities::Entity;
-use crate::entities::hello_triangle::HelloTriangle;
-use crate::entities::sun::Sun;
-use crate::entities::tex_cube::TexCube;
-use crate::entities::utah_teapot::UtahTeapot;
-// use crate::entities::utah_teapot::UtahTeapot;
+use crate::camera::CameraMovement;
 use crate::gl::{self};
-use crate::helpers::{FpsCounter, GlPosition, Mat3DUpdate, RendererControl};
-use crate::renderer::shader::GlslPass;
-use crate::renderer::shader::uniform::{
-    EnabledFog, EnabledLighting, EyePos, Fog, LightPos, Lighting, Uniform,
-};
-use crate::terrain_builder;
+use crate::helpers::{FpsCounter, RendererControl, WorldControl};
+use crate::scene::{CLEAR_COLOR, FloorKind, Scene, WORLD_SAVE_PATH};
 use crate::{GlDisplayCreationState, renderer::Renderer, window_attributes};
 use glutin::surface::{Surface, SwapInterval, WindowSurface};
 
 const DEFAULT_WINDOW_WIDTH: usize = 800;
 const DEFAULT_WINDOW_HEIGHT: usize = 600;
-
-const CLEAR_COLOR: glam::Vec3 = glam::Vec3 {
-    x: 0.1,
-    y: 0.1,
-    z: 0.1,
-};
+/// How often shader files are checked for changes.
+const SHADER_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 struct AppState {
     gl_surface: Surface<WindowSurface>,
     // NOTE: Window should be dropped after all resources created using its
     // raw-window-handle.
     window: Window,
-    entities: Vec<Box<dyn Entity>>,
-    next_frame_entities_uniforms: Vec<Box<dyn Uniform>>,
-    sun: Sun,
-    camera: Camera,
+    scene: Scene,
     last_frame: Instant,
+    last_shader_poll: Instant,
 }
 
 pub struct App {
@@ -72,6 +54,8 @@ pub struct App {
     fps_counter: FpsCounter,
     gl_context: Option<PossiblyCurrentContext>,
     gl_display: GlDisplayCreationState,
+    /// What the scene built on resume stands on
+    pub floor: FloorKind,
     pub exit_state: Result<(), Box<dyn Error>>,
 }
 
@@ -85,8 +69,14 @@ impl App {
             gl_context: None,
             state: None,
             renderer: None,
+            floor: FloorKind::default(),
         }
     }
+
+    pub fn with_floor(mut self, floor: FloorKind) -> Self {
+        self.floor = floor;
+        self
+    }
 }
 
 impl ApplicationHandler for App {
@@ -182,93 +172,23 @@ impl ApplicationHandler for App {
             log::error!("Error setting vsync: {res:?}");
         }
 
-        const FLOOR_SIDE: usize = 50;
-        const HEIGHT: usize = 4;
-        const CS: f32 = 1.0;
-
-        let tb = terrain_builder::terrain_builder(123, HEIGHT);
-
-        let mut cubes_floor = vec![];
-        for x in 0..FLOOR_SIDE {
-            for z in 0..FLOOR_SIDE {
-                for y in 0..=tb(x, z) {
-                    cubes_floor.push(Vec3::new(x as f32, y as f32, z as f32))
-                }
+        let scene = match Scene::with_floor(gl_fns, self.floor) {
+            Ok(scene) => scene,
+            Err(err) => {
+                self.exit_state = Err(err.into());
+                event_loop.exit();
+                return;
             }
-        }
-
-        const MIDDLE: f32 = FLOOR_SIDE as f32 * CS / 2.0;
-
-        let utahs: Vec<Box<UtahTeapot>> = [
-            glam::Vec2::new(3.0 + MIDDLE, 5.0 + MIDDLE),
-            glam::Vec2::new(MIDDLE - 5.0, MIDDLE + 2.0),
-            glam::Vec2::new(MIDDLE, MIDDLE),
-        ]
-        .iter()
-        .map(|utah| {
-            Box::new(UtahTeapot::new(
-                GlPosition::new(
-                    utah.x,
-                    tb(utah.x as usize, utah.y as usize) as f32 + 0.5,
-                    utah.y,
-                ),
-                Vec3::new(1.0, 0.0, 0.0),
-            ))
-        })
-        .collect();
-
-        let mut entities: Vec<Box<dyn Entity>> = vec![
-            Box::new(HelloTriangle::new((
-                GlPosition::new(MIDDLE + 3.0, HEIGHT as f32 + 1.0, MIDDLE + 3.0),
-                CS,
-            ))),
-            Box::new(TexCube::new(
-                cubes_floor,
-                CS,
-                // Dirt cubes floor
-                Some("./assets/dirt.webp".into()),
-            )),
-        ];
-
-        for utah in utahs {
-            entities.push(utah);
-        }
-
-        let mut sun = Sun::new(GlPosition::new(MIDDLE, HEIGHT as f32 + 10.0, MIDDLE));
-
-        let dimensions = self
-            .renderer
-            .as_ref()
-            .expect("Set before")
-            .get_window_dimensions();
-
-        let dimensions = glam::Vec2::new(dimensions.x as f32, dimensions.y as f32);
-
-        let entities_transformations_3d = Mat3DUpdate::default_from_dimensions(&dimensions);
-
-        let init_uniforms: Vec<Box<dyn Uniform>> = vec![
-            Box::new(Lighting::new()),
-            Box::new(Fog::new(CLEAR_COLOR)),
-            Box::new(EnabledFog::enabled()),
-            Box::new(EnabledLighting::enabled()),
-        ];
-
-        // Init Glsl for drawables
-        for entity in entities.iter_mut() {
-            entity.init(gl_fns.clone(), entities_transformations_3d, &init_uniforms);
-        }
-        sun.init(gl_fns, entities_transformations_3d, &[]);
+        };
 
         assert!(
             self.state
                 .replace(AppState {
                     last_frame: Instant::now(),
-                    entities,
+                    last_shader_poll: Instant::now(),
                     gl_surface,
                     window,
-                    camera: Camera::from_pos(GlPosition::new(MIDDLE, HEIGHT as f32 + 1.0, MIDDLE)),
-                    sun,
-                    next_frame_entities_uniforms: vec![]
+                    scene,
                 })
                 .is_none()
         );
@@ -304,7 +224,7 @@ impl ApplicationHandler for App {
             return;
         };
         if let Some(state) = self.state.as_mut() {
-            state.camera.mouse_moved(dx as f32, -dy as f32)
+            state.scene.camera.mouse_moved(dx as f32, -dy as f32)
         }
     }
 
@@ -354,22 +274,52 @@ impl ApplicationHandler for App {
                 Some(state) => {
                     if let Some(movement) = CameraMovement::from_keycode(code) {
                         match key_state {
-                            ElementState::Pressed => state.camera.want_move(movement),
-                            ElementState::Released => state.camera.stop_move(movement),
+                            ElementState::Pressed => state.scene.camera.want_move(movement),
+                            ElementState::Released => state.scene.camera.stop_move(movement),
                         }
                     }
                     if let Some(control) = RendererControl::from_keycode(code) {
-                        let uniform: Box<dyn Uniform> = match control {
-                            RendererControl::EnableLight => Box::new(EnabledLighting::enabled()),
-                            RendererControl::DisableLight => Box::new(EnabledLighting::default()),
-                            RendererControl::EnableFog => Box::new(EnabledFog::enabled()),
-                            RendererControl::DisableFog => Box::new(EnabledFog::default()),
+                        let lighting = &mut state.scene.lighting;
+                        match control {
+                            RendererControl::EnableLight => lighting.enabled_lighting = true,
+                            RendererControl::DisableLight => lighting.enabled_lighting = false,
+                            RendererControl::EnableFog => lighting.enabled_fog = true,
+                            RendererControl::DisableFog => lighting.enabled_fog = false,
+                        }
+                    }
+                    if let Some(control) = WorldControl::from_keycode(code)
+                        && key_state == ElementState::Pressed
+                    {
+                        let (result, done) = match control {
+                            WorldControl::Save => {
+                                (state.scene.save_world(WORLD_SAVE_PATH), "saved to")
+                            }
+                            WorldControl::Load => {
+                                (state.scene.load_world(WORLD_SAVE_PATH), "loaded from")
+                            }
                         };
-                        state.next_frame_entities_uniforms.push(uniform);
+                        match result {
+                            Ok(()) => log::info!("World {done} {WORLD_SAVE_PATH}"),
+                            Err(err) => log::error!("{err}"),
+                        }
                     }
                 }
                 None => log::warn!("Key pressed before state init"),
             },
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } => {
+                let Some(state) = self.state.as_mut() else {
+                    return;
+                };
+                match button {
+                    MouseButton::Left => state.scene.break_block(),
+                    MouseButton::Right => state.scene.place_block(),
+                    _ => (),
+                }
+            }
 
             _ => (),
         }
@@ -395,12 +345,10 @@ impl ApplicationHandler for App {
     fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
         if let Some(AppState {
             last_frame,
+            last_shader_poll,
             gl_surface,
             window,
-            entities,
-            camera,
-            sun,
-            next_frame_entities_uniforms,
+            scene,
         }) = self.state.as_mut()
         {
             let renderer = self.renderer.as_mut().unwrap();
@@ -408,37 +356,20 @@ impl ApplicationHandler for App {
             let dt = last_frame.elapsed();
             *last_frame = Instant::now();
 
-            if let Some(fps) = self.fps_counter.tick() {
-                log::info!("FPS: {fps}");
-                log::info!("Sun position: {:?}", sun.get_pos());
+            if last_shader_poll.elapsed() >= SHADER_POLL_INTERVAL {
+                *last_shader_poll = Instant::now();
+                scene.reload_shaders();
             }
 
             let gl_context = self.gl_context.as_ref().unwrap();
 
-            let renderer_refs = entities.iter_mut().map(|e| e.as_mut() as &mut dyn GlslPass);
-
-            camera.update(&dt);
-
-            let mat3d = Mat3DUpdate {
-                view: Some(camera.as_view()),
-                ..Default::default()
-            };
+            scene.render(renderer, &dt);
+            let stats = renderer.take_stats();
 
-            let base_frame_update_uniforms = [
-                Box::new(LightPos::new(sun.get_pos())) as Box<dyn Uniform>,
-                Box::new(EyePos::new(camera.pos)),
-            ];
-
-            renderer.clear();
-            renderer.draw(
-                [sun as &mut dyn GlslPass].into_iter(),
-                mat3d,
-                &base_frame_update_uniforms,
-            );
-            next_frame_entities_uniforms.extend(base_frame_update_uniforms);
-            renderer.draw(renderer_refs, mat3d, next_frame_entities_uniforms);
-
-            next_frame_entities_uniforms.clear();
+            if let Some(fps) = self.fps_counter.tick() {
+                log::info!("FPS: {fps}, {stats}");
+                log::info!("Sun position: {:?}", scene.sun.get_pos());
+            }
 
             window.request_redraw();
 
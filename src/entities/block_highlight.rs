@@ -0,0 +1,235 @@
+use std::rc::Rc;
+
+use glam::{IVec3, Vec3};
+
+use crate::{
+    entities::{Entity, hello_triangle},
+    gl,
+    helpers::Mat3DUpdate,
+    renderer::{
+        backend::GlBackend,
+        culling::Aabb,
+        shader::{
+            Array, Drawable, GlslPass, Shader, hot_reload::ShaderFiles, program::ShaderError,
+            uniform::Uniform,
+        },
+        stats::RenderStats,
+    },
+};
+
+const COLOR: Vec3 = Vec3::splat(0.95);
+/// Slightly larger than a voxel, so the lines are not hidden by its faces
+const INFLATE: f32 = 1.01;
+
+/// The edges of the voxel the camera looks at, drawn as lines.
+pub struct BlockHighlight {
+    side_len: f32,
+    target: Option<IVec3>,
+    shader: Option<Shader>,
+}
+
+impl BlockHighlight {
+    /// For voxels of side `side_len`, like the terrain ones.
+    pub fn new(side_len: f32) -> Self {
+        Self {
+            side_len,
+            target: None,
+            shader: None,
+        }
+    }
+
+    /// Outlines the voxel `target`, nothing when `None`.
+    pub fn set_target(&mut self, target: Option<IVec3>) {
+        self.target = target;
+        if let (Some(target), Some(shader)) = (target, &mut self.shader) {
+            shader.model_transform = glam::Mat4::from_scale_rotation_translation(
+                Vec3::splat(self.side_len * INFLATE),
+                glam::Quat::IDENTITY,
+                target.as_vec3() * self.side_len,
+            );
+        }
+    }
+
+    pub fn target(&self) -> Option<IVec3> {
+        self.target
+    }
+}
+
+/// The 12 edges of the unit cube centered on the origin, as line endpoints.
+fn cube_edges() -> Vec<Vec3> {
+    let corner = |i: u32| Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32) - 0.5;
+    // Corners one bit apart share an edge
+    (0..8u32)
+        .flat_map(|a| {
+            [1, 2, 4]
+                .into_iter()
+                .filter(move |bit| a & bit == 0)
+                .flat_map(move |bit| [corner(a), corner(a | bit)])
+        })
+        .collect()
+}
+
+impl GlslPass for BlockHighlight {
+    fn init(
+        &mut self,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) = ShaderFiles::build_program(
+            gl_fns.as_ref(),
+            hello_triangle::VERTEX_SHADER_PATH,
+            hello_triangle::FRAGMENT_SHADER_PATH,
+        )?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let edges = cube_edges();
+        let vertex_data: Vec<f32> = edges
+            .iter()
+            .flat_map(|p| [p.x, p.y, p.z, COLOR.x, COLOR.y, COLOR.z])
+            .collect();
+
+        let mut vao;
+        let mut vbo;
+        unsafe {
+            vao = std::mem::zeroed();
+            gl_fns.GenVertexArrays(1, &mut vao);
+            gl_fns.BindVertexArray(vao);
+
+            vbo = std::mem::zeroed();
+            gl_fns.GenBuffers(1, &mut vbo);
+            gl_fns.BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl_fns.BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertex_data.as_slice()) as gl::types::GLsizeiptr,
+                vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+
+            let stride = 6 * std::mem::size_of::<f32>() as gl::types::GLsizei;
+            let pos_attrib = gl_fns.GetAttribLocation(program, c"position".as_ptr() as *const _);
+            gl_fns.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint,
+                3,
+                gl::FLOAT,
+                0,
+                stride,
+                std::ptr::null(),
+            );
+            let color_attrib = gl_fns.GetAttribLocation(program, c"color".as_ptr() as *const _);
+            gl_fns.VertexAttribPointer(
+                color_attrib as gl::types::GLuint,
+                3,
+                gl::FLOAT,
+                0,
+                stride,
+                (3 * std::mem::size_of::<f32>()) as *const () as *const _,
+            );
+            gl_fns.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
+            gl_fns.EnableVertexAttribArray(color_attrib as gl::types::GLuint);
+        }
+
+        let drawables = vec![Drawable::Array(Array {
+            vao,
+            vbo,
+            len: 1,
+            offset: 0,
+            count: edges.len(),
+            bounds: Aabb::from_points(edges.iter().copied()),
+        })];
+
+        self.shader = Some(Shader::new(
+            gl_fns, program, uniforms, files, model, drawables,
+        ));
+        // Place the box on a target set before init
+        self.set_target(self.target);
+
+        Ok(())
+    }
+
+    fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+        if let Some(shader) = &mut self.shader {
+            // The model follows the target unless overridden
+            let model = mat3d.model.unwrap_or(shader.model_transform);
+            Mat3DUpdate { model: Some(model) }
+                .set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
+
+            for uniform in to_set_uniforms {
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
+            }
+        }
+    }
+
+    /// Draws the edges as lines, only while there is a target.
+    unsafe fn draw(&self) -> RenderStats {
+        let Some(shader) = &self.shader else {
+            log::warn!("Tried to render BlockHighlight before init");
+            return RenderStats::default();
+        };
+        if self.target.is_none() {
+            return RenderStats::default();
+        }
+        let gl = &shader.gl_fns;
+        let mut stats = RenderStats::default();
+        for drawable in &shader.drawables {
+            if let Drawable::Array(array) = drawable {
+                gl.BindVertexArray(array.vao);
+                gl.BindBuffer(gl::ARRAY_BUFFER, array.vbo);
+                gl.DrawArrays(gl::LINES, 0, array.count as i32);
+                // Lines, no triangles
+                stats += RenderStats::call(0);
+            }
+        }
+        stats
+    }
+
+    fn get_shader(&self) -> Option<&Shader> {
+        self.shader.as_ref()
+    }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
+    }
+}
+
+impl Entity for BlockHighlight {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    #[test]
+    fn edges_are_the_twelve_cube_edges() {
+        let edges = cube_edges();
+        assert_eq!(edges.len(), 24);
+        for edge in edges.chunks(2) {
+            let d = (edge[1] - edge[0]).abs();
+            assert_eq!(d.element_sum(), 1.0, "axis aligned, one side long");
+            assert!(edge.iter().all(|p| p.abs() == Vec3::splat(0.5)));
+        }
+    }
+
+    #[test]
+    fn draws_lines_only_with_a_target() {
+        let gl = Rc::new(MockGl::default());
+        let mut highlight = BlockHighlight::new(0.5);
+        highlight
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        let lines = |gl: &MockGl| {
+            gl.count(
+                |c| matches!(c, GlCall::DrawArrays { mode, count: 24, .. } if *mode == gl::LINES),
+            )
+        };
+
+        highlight.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!(lines(&gl), 0);
+
+        highlight.set_target(Some(IVec3::new(2, -1, 4)));
+        highlight.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!(lines(&gl), 1);
+        let center = highlight.get_shader().unwrap().model_transform.w_axis;
+        assert_eq!(center.truncate(), Vec3::new(1.0, -0.5, 2.0));
+    }
+}
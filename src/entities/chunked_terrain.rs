@@ -0,0 +1,537 @@
+use std::{collections::HashMap, rc::Rc, sync::Arc, time::Duration};
+
+use glam::{IVec3, Vec3};
+
+use crate::{
+    entities::{
+        Entity,
+        tex_square::{self, upload_texture, upload_vertex_data},
+    },
+    helpers::{GlPosition, Mat3DUpdate},
+    renderer::{
+        backend::GlBackend,
+        shader::{
+            Drawable, GlslPass, Shader, hot_reload::ShaderFiles, program::ShaderError,
+            registry::UniformRegistry, uniform::Uniform,
+        },
+    },
+    voxel::{
+        AIR, BlockId,
+        blocks::BlockRegistry,
+        raycast::{RayHit, raycast},
+        workers::{MeshJob, MeshWorkers, MeshedChunk, RecvError},
+        world::{ChunkCoord, ChunkWorld},
+    },
+};
+
+/// Chunks uploaded per frame by default.
+pub const DEFAULT_UPLOAD_BUDGET: usize = 4;
+
+/// A [`ChunkWorld`] drawn as textured voxels, one vertex buffer per loaded
+/// chunk, streamed around a point with [`ChunkedTerrain::stream_around`].
+/// Blocks are textured from the atlas of a [`BlockRegistry`].
+///
+/// Chunks are generated and meshed on [`MeshWorkers`] threads, the render
+/// thread only uploads the results.
+pub struct ChunkedTerrain {
+    world: ChunkWorld,
+    workers: MeshWorkers,
+    side_len: f32,
+    /// Most chunk meshes uploaded by one [`ChunkedTerrain::stream_around`]
+    pub upload_budget: usize,
+    blocks: Arc<BlockRegistry>,
+    meshes: HashMap<ChunkCoord, Drawable>,
+    shader: Option<Shader>,
+}
+
+impl ChunkedTerrain {
+    /// Voxels are cubes of side `side_len`, voxel `(x, y, z)` centered on
+    /// `(x, y, z) * side_len`.
+    pub fn new(world: ChunkWorld, side_len: f32, blocks: Arc<BlockRegistry>) -> Self {
+        let workers = MeshWorkers::with_available_parallelism(
+            world.generator().clone(),
+            blocks.clone(),
+            side_len,
+        );
+        Self::with_workers(world, workers, side_len, blocks)
+    }
+
+    /// Like [`ChunkedTerrain::new`], meshing on `workers`, which should use
+    /// the same `blocks`.
+    pub fn with_workers(
+        world: ChunkWorld,
+        workers: MeshWorkers,
+        side_len: f32,
+        blocks: Arc<BlockRegistry>,
+    ) -> Self {
+        Self {
+            world,
+            workers,
+            side_len,
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            blocks,
+            meshes: HashMap::new(),
+            shader: None,
+        }
+    }
+
+    pub fn world(&self) -> &ChunkWorld {
+        &self.world
+    }
+
+    /// Edits go through [`ChunkWorld::set`], and show up after the next
+    /// [`ChunkedTerrain::stream_around`] calls.
+    pub fn world_mut(&mut self) -> &mut ChunkWorld {
+        &mut self.world
+    }
+
+    /// Voxel `(x, y, z)` is centered on `(x, y, z) * side_len`.
+    pub fn side_len(&self) -> f32 {
+        self.side_len
+    }
+
+    /// The first block along the ray from `origin` along `direction`, at most
+    /// `max_distance` away, in world units like the distance of the hit.
+    pub fn raycast(
+        &self,
+        origin: GlPosition,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        let hit = raycast(
+            origin / self.side_len,
+            direction,
+            max_distance / self.side_len,
+            |voxel| self.world.get(voxel),
+        )?;
+        Some(RayHit {
+            distance: hit.distance * self.side_len,
+            ..hit
+        })
+    }
+
+    /// Removes the block hit by `hit`. Returns `false` when it is not loaded.
+    pub fn break_block(&mut self, hit: &RayHit) -> bool {
+        self.world.set(hit.voxel, AIR)
+    }
+
+    /// Puts `block` against the face hit by `hit`. Returns `false` when there
+    /// is no room for it: the ray started inside a block, or the voxel is out
+    /// of the loaded world.
+    pub fn place_block(&mut self, hit: &RayHit, block: BlockId) -> bool {
+        hit.normal != IVec3::ZERO
+            && self.world.get(hit.voxel + hit.normal) == AIR
+            && self.world.set(hit.voxel + hit.normal, block)
+    }
+
+    /// The voxel containing `pos`, in world units.
+    pub fn voxel_at(&self, pos: GlPosition) -> IVec3 {
+        (pos / self.side_len).round().as_ivec3()
+    }
+
+    /// Whether chunks are still being generated or meshed.
+    pub fn is_streaming(&self) -> bool {
+        self.workers.pending() > 0
+    }
+
+    /// Requests the chunks around `pos` and the remeshing of the changed ones,
+    /// frees the buffers of the chunks now out of range, and uploads at most
+    /// `upload_budget` finished meshes. Does nothing before `init`.
+    pub fn stream_around(&mut self, pos: GlPosition) {
+        self.request_around(pos);
+        let mut budget = self.upload_budget;
+        while budget > 0 {
+            match self.workers.try_recv() {
+                Ok(meshed) => self.upload(meshed),
+                Err(err @ RecvError::Failed(_)) => log::error!("{err}, it stays unmeshed"),
+                Err(_) => break,
+            }
+            budget -= 1;
+        }
+    }
+
+    /// Streams around `pos` until every chunk in range is loaded and meshed,
+    /// ignoring the upload budget.
+    pub fn finish_streaming(&mut self, pos: GlPosition) {
+        self.request_around(pos);
+        while self.is_streaming() {
+            match self.workers.recv_timeout(Duration::from_secs(1)) {
+                Ok(meshed) => self.upload(meshed),
+                Err(RecvError::Timeout) => {
+                    log::warn!("Still waiting for {} chunks", self.workers.pending())
+                }
+                Err(err @ RecvError::Failed(_)) => log::error!("{err}, it stays unmeshed"),
+                Err(err @ RecvError::Disconnected) => {
+                    log::error!("{err}, giving up streaming");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn request_around(&mut self, pos: GlPosition) {
+        let Some(shader) = &mut self.shader else {
+            log::warn!("Tried to stream a ChunkedTerrain before init");
+            return;
+        };
+
+        let voxel = (pos / self.side_len).round().as_ivec3();
+        let streamed = self.world.stream_around(ChunkWorld::chunk_of(voxel));
+        for chunk in &streamed.unloaded {
+            self.workers.cancel(*chunk);
+            if let Some(mesh) = self.meshes.remove(chunk) {
+                unsafe { mesh.delete(shader.gl_fns.as_ref()) };
+            }
+        }
+        if !streamed.unloaded.is_empty() {
+            shader.drawables = self.meshes.values().cloned().collect();
+        }
+
+        for chunk in streamed.requested {
+            self.workers.submit(MeshJob {
+                chunk,
+                grid: None,
+                neighbours: self.world.neighbours(chunk),
+            });
+        }
+        for chunk in self.world.take_dirty() {
+            self.workers.submit(MeshJob {
+                chunk,
+                grid: self.world.chunk(chunk).cloned(),
+                neighbours: self.world.neighbours(chunk),
+            });
+        }
+    }
+
+    fn upload(&mut self, meshed: MeshedChunk) {
+        let Some(shader) = &mut self.shader else {
+            return;
+        };
+        if let Some(grid) = meshed.generated
+            && !self.world.insert(meshed.chunk, grid)
+        {
+            // Out of range since
+            return;
+        }
+
+        let gl = shader.gl_fns.as_ref();
+        let mesh = (!meshed.vertex_data.is_empty())
+            .then(|| upload_vertex_data(gl, shader.program, &meshed.vertex_data));
+        let old = match mesh {
+            Some(mesh) => self.meshes.insert(meshed.chunk, mesh),
+            None => self.meshes.remove(&meshed.chunk),
+        };
+        if let Some(old) = old {
+            unsafe { old.delete(gl) };
+        }
+
+        // The shader draws, and frees on drop, what is currently loaded
+        shader.drawables = self.meshes.values().cloned().collect();
+    }
+}
+
+impl GlslPass for ChunkedTerrain {
+    fn init(
+        &mut self,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) = ShaderFiles::build_program(
+            gl_fns.as_ref(),
+            tex_square::VERTEX_SHADER_PATH,
+            tex_square::FRAGMENT_SHADER_PATH,
+        )?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let atlas = self
+            .blocks
+            .build_atlas()
+            .unwrap_or_else(|err| panic!("block textures should load: {err}"));
+        let tex = Some(upload_texture(gl_fns.as_ref(), &atlas));
+
+        // Meshes are uploaded as chunks stream in
+        self.meshes.clear();
+        self.shader =
+            Some(Shader::new(gl_fns, program, uniforms, files, model, vec![]).with_tex(tex));
+
+        Ok(())
+    }
+
+    fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+        if let Some(shader) = &mut self.shader {
+            if let Some(model_updated) = mat3d.model {
+                shader.model_transform = model_updated;
+            }
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
+
+            for uniform in to_set_uniforms {
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
+            }
+        }
+    }
+
+    fn set_own_uniforms(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+        uniforms.set_sampler(gl, "tex", 0);
+        uniforms.set_bool(gl, "uEmissive", false);
+    }
+
+    fn get_shader(&self) -> Option<&Shader> {
+        self.shader.as_ref()
+    }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
+    }
+}
+
+impl Entity for ChunkedTerrain {}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec2, Mat4};
+
+    use super::*;
+    use crate::{
+        gl,
+        renderer::{
+            backend::mock::{GlCall, MockGl},
+            culling::Frustum,
+        },
+        voxel::{
+            DIRT,
+            world::{CHUNK_SIDE, Generator},
+        },
+    };
+
+    const FAR: Vec3 = Vec3::new(CHUNK_SIDE as f32 * 10.0, 0.0, 0.0);
+
+    fn terrain(gl: &Rc<MockGl>) -> ChunkedTerrain {
+        terrain_of(gl, Generator::new(|_, _| 2, 8))
+    }
+
+    fn terrain_of(gl: &Rc<MockGl>, generator: Generator) -> ChunkedTerrain {
+        // Textureless blocks, their atlas is blank
+        let blocks = Arc::new(BlockRegistry::default());
+        let workers = MeshWorkers::new(2, generator.clone(), blocks.clone(), 1.0);
+        let mut terrain =
+            ChunkedTerrain::with_workers(ChunkWorld::new(generator, 1), workers, 1.0, blocks);
+        terrain
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        terrain
+    }
+
+    /// Vertex buffers uploaded, one per meshed chunk
+    fn uploads(gl: &MockGl) -> usize {
+        gl.count(|c| {
+            matches!(
+                c,
+                GlCall::BufferData {
+                    target: gl::ARRAY_BUFFER,
+                    ..
+                }
+            )
+        })
+    }
+
+    fn vbos(terrain: &ChunkedTerrain) -> Vec<u32> {
+        let mut vbos: Vec<_> = terrain
+            .get_shader()
+            .unwrap()
+            .drawables
+            .iter()
+            .map(|drawable| match drawable {
+                Drawable::Array(array) => array.vbo,
+                Drawable::Indexed(indexed) => indexed.vbo,
+                Drawable::InstancedArrays(instanced) => instanced.vbo,
+            })
+            .collect();
+        vbos.sort();
+        vbos
+    }
+
+    /// The vertex and index buffers of every chunk.
+    fn buffers(terrain: &ChunkedTerrain) -> Vec<u32> {
+        let mut buffers: Vec<_> = terrain
+            .get_shader()
+            .unwrap()
+            .drawables
+            .iter()
+            .flat_map(|drawable| match drawable {
+                Drawable::Indexed(indexed) => vec![indexed.vbo, indexed.ebo],
+                _ => panic!("chunks are indexed"),
+            })
+            .collect();
+        buffers.sort();
+        buffers
+    }
+
+    #[test]
+    fn streaming_uploads_new_chunks_and_frees_the_unloaded_ones() {
+        let gl = Rc::new(MockGl::default());
+        let mut terrain = terrain(&gl);
+
+        terrain.finish_streaming(Vec3::ZERO);
+        assert_eq!(vbos(&terrain).len(), 5);
+        let first = buffers(&terrain);
+
+        gl.clear_calls();
+        terrain.finish_streaming(FAR);
+
+        assert_eq!(vbos(&terrain).len(), 5);
+        let mut deleted: Vec<_> = gl
+            .calls()
+            .into_iter()
+            .filter_map(|c| match c {
+                GlCall::DeleteBuffers(buffers) => Some(buffers),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        deleted.sort();
+        assert_eq!(deleted, first);
+    }
+
+    #[test]
+    fn every_chunk_is_drawn_in_one_call() {
+        let gl = Rc::new(MockGl::default());
+        // A checkerboard, greedy meshing can't merge its squares
+        let mut terrain = terrain_of(
+            &gl,
+            Generator::new(|x, z| 2 + (x + z).rem_euclid(2) as usize, 8),
+        );
+        terrain.finish_streaming(Vec3::ZERO);
+
+        let stats = terrain.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!(stats.draw_calls, 5, "one per chunk");
+        // Squares of 2 triangles, far more than the chunks
+        assert!(stats.triangles > 100 * stats.draw_calls, "{stats}");
+        assert_eq!(stats.triangles % 2, 0);
+    }
+
+    #[test]
+    fn streaming_finishes_despite_chunks_failing_to_mesh() {
+        let gl = Rc::new(MockGl::default());
+        // Fails west of the origin chunk, past its border
+        let generator = Generator::new(
+            |x, _| {
+                assert!(x > -CHUNK_SIDE - 1, "broken generator");
+                2
+            },
+            8,
+        );
+        let mut terrain = terrain_of(&gl, generator);
+
+        terrain.finish_streaming(Vec3::ZERO);
+
+        assert!(!terrain.is_streaming());
+        assert_eq!(vbos(&terrain).len(), 4, "all but the chunk west");
+    }
+
+    #[test]
+    fn chunks_out_of_view_are_not_drawn() {
+        let gl = Rc::new(MockGl::default());
+        let mut terrain = terrain(&gl);
+        terrain.finish_streaming(Vec3::ZERO);
+
+        // Right above the middle of the chunk east of the origin, looking
+        // down through a view too narrow to reach its neighbours
+        let eye = Vec3::new(1.5 * CHUNK_SIDE as f32, 30.0, 0.5 * CHUNK_SIDE as f32);
+        let view = Mat4::look_at_rh(eye, eye - Vec3::Y * 30.0, Vec3::Z);
+        let projection = Mat4::perspective_rh_gl(20f32.to_radians(), 1.0, 0.1, 100.0);
+        terrain.get_shader_mut().unwrap().cull = Some(Frustum::from_matrix(projection * view));
+
+        let stats = terrain.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!(stats.draw_calls, 1);
+        assert_eq!(stats.culled, 4);
+    }
+
+    #[test]
+    fn uploads_stay_within_the_frame_budget() {
+        let gl = Rc::new(MockGl::default());
+        let mut terrain = terrain(&gl);
+        terrain.upload_budget = 2;
+
+        let mut frames = 0;
+        while terrain.is_streaming() || frames == 0 {
+            gl.clear_calls();
+            terrain.stream_around(Vec3::ZERO);
+            assert!(uploads(&gl) <= 2);
+            frames += 1;
+            assert!(frames < 10_000, "chunks never finished streaming");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(vbos(&terrain).len(), 5);
+    }
+
+    #[test]
+    fn chunks_out_of_range_before_upload_are_never_uploaded() {
+        let gl = Rc::new(MockGl::default());
+        let mut terrain = terrain(&gl);
+
+        terrain.upload_budget = 0;
+        terrain.stream_around(Vec3::ZERO);
+        terrain.upload_budget = DEFAULT_UPLOAD_BUDGET;
+        terrain.finish_streaming(FAR);
+
+        assert_eq!(uploads(&gl), 5);
+        assert!(!terrain.world().is_loaded(IVec2::ZERO));
+    }
+
+    #[test]
+    fn only_changed_chunks_are_remeshed() {
+        let gl = Rc::new(MockGl::default());
+        let mut terrain = terrain(&gl);
+        terrain.finish_streaming(Vec3::ZERO);
+
+        gl.clear_calls();
+        terrain.finish_streaming(Vec3::new(0.4, 3.0, 0.4));
+        assert_eq!(uploads(&gl), 0);
+
+        assert!(terrain.world_mut().set(IVec3::new(5, 3, 5), DIRT));
+        let before = vbos(&terrain);
+        terrain.finish_streaming(Vec3::ZERO);
+
+        assert_eq!(uploads(&gl), 1);
+        let after = vbos(&terrain);
+        assert_eq!(
+            after.iter().filter(|vbo| !before.contains(vbo)).count(),
+            1,
+            "only the edited chunk got a new buffer"
+        );
+    }
+
+    #[test]
+    fn picked_blocks_break_and_place_in_world_units() {
+        let gl = Rc::new(MockGl::default());
+        let generator = Generator::new(|_, _| 2, 8);
+        let blocks = Arc::new(BlockRegistry::default());
+        let workers = MeshWorkers::new(1, generator.clone(), blocks.clone(), 0.5);
+        let mut terrain =
+            ChunkedTerrain::with_workers(ChunkWorld::new(generator, 1), workers, 0.5, blocks);
+        terrain
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        terrain.finish_streaming(Vec3::ZERO);
+
+        // The surface voxels at y = 2 reach up to 2.5 voxels, 1.25 units
+        let eye = Vec3::new(1.0, 3.0, 1.0);
+        let hit = terrain.raycast(eye, Vec3::NEG_Y, 10.0).unwrap();
+        assert_eq!((hit.voxel, hit.normal), (IVec3::new(2, 2, 2), IVec3::Y));
+        assert!((hit.distance - 1.75).abs() < 1e-5);
+        assert!(terrain.raycast(eye, Vec3::NEG_Y, 1.7).is_none());
+
+        assert!(terrain.break_block(&hit));
+        let below = terrain.raycast(eye, Vec3::NEG_Y, 10.0).unwrap();
+        assert_eq!(below.voxel, IVec3::new(2, 1, 2));
+
+        assert!(terrain.place_block(&below, DIRT));
+        assert_eq!(terrain.raycast(eye, Vec3::NEG_Y, 10.0), Some(hit));
+        assert!(!terrain.place_block(&below, DIRT), "already filled");
+
+        gl.clear_calls();
+        terrain.finish_streaming(Vec3::ZERO);
+        assert_eq!(uploads(&gl), 1, "only the edited chunk is remeshed");
+    }
+}
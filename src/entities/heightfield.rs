@@ -0,0 +1,340 @@
+use std::rc::Rc;
+
+use glam::{UVec2, Vec2, Vec3};
+
+use crate::{
+    entities::{
+        Entity,
+        tex_square::{self, SquareVertex, set_vertex_attributes, upload_texture},
+    },
+    gl,
+    helpers::Mat3DUpdate,
+    renderer::{
+        atlas::UvRect,
+        backend::GlBackend,
+        culling::Aabb,
+        shader::{
+            Drawable, GlslPass, IndexedElements, Shader,
+            hot_reload::ShaderFiles,
+            program::ShaderError,
+            registry::UniformRegistry,
+            uniform::{ProgramId, Uniform},
+        },
+    },
+    voxel::blocks::TextureSource,
+};
+
+/// A regular grid of square cells on the x, z plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeightfieldGrid {
+    /// Corner of the first cell, at the lowest x and z
+    pub origin: Vec2,
+    /// Cells along x and z
+    pub cells: UVec2,
+    pub cell_size: f32,
+}
+
+impl HeightfieldGrid {
+    /// Vertices along x and z, one more than cells.
+    pub fn vertices(&self) -> UVec2 {
+        self.cells + 1
+    }
+
+    /// Where vertex `(i, j)` is on the x, z plane.
+    pub fn position(&self, i: u32, j: u32) -> Vec2 {
+        self.origin + UVec2::new(i, j).as_vec2() * self.cell_size
+    }
+}
+
+/// A triangle mesh of a heightfield, drawn with `DrawElements`.
+pub struct HeightfieldMesh {
+    /// Row by row along x, `grid.vertices().x` per row
+    pub vertices: Vec<SquareVertex>,
+    /// Two triangles per cell, winding counterclockwise seen from above
+    pub indices: Vec<u32>,
+}
+
+impl HeightfieldMesh {
+    /// Samples `height_at` on every vertex of `grid`. Normals come from
+    /// central differences of `height_at` one cell away, outside the grid too
+    /// so neighbouring grids light their shared edge the same. The texture
+    /// repeats every `tile_len` world units.
+    pub fn new(grid: &HeightfieldGrid, tile_len: f32, height_at: impl Fn(f32, f32) -> f32) -> Self {
+        Self::with_normal_step(grid, tile_len, grid.cell_size, height_at)
+    }
+
+    /// Like [`HeightfieldMesh::new`], the normals from differences
+    /// `normal_step` away instead of a cell, so grids of different cell sizes
+    /// light the vertices they share the same.
+    pub fn with_normal_step(
+        grid: &HeightfieldGrid,
+        tile_len: f32,
+        normal_step: f32,
+        height_at: impl Fn(f32, f32) -> f32,
+    ) -> Self {
+        let size = grid.vertices();
+        let step = normal_step;
+
+        let vertices = (0..size.y)
+            .flat_map(|j| (0..size.x).map(move |i| grid.position(i, j)))
+            .map(|p| {
+                let slope = Vec2::new(
+                    height_at(p.x + step, p.y) - height_at(p.x - step, p.y),
+                    height_at(p.x, p.y + step) - height_at(p.x, p.y - step),
+                ) / (2.0 * step);
+                SquareVertex::new(
+                    Vec3::new(p.x, height_at(p.x, p.y), p.y),
+                    p / tile_len,
+                    Vec3::new(-slope.x, 1.0, -slope.y).normalize(),
+                    UvRect::FULL,
+                )
+            })
+            .collect();
+
+        let index = |i: u32, j: u32| i + j * size.x;
+        let indices = (0..grid.cells.y)
+            .flat_map(|j| (0..grid.cells.x).map(move |i| (i, j)))
+            .flat_map(|(i, j)| {
+                let (a, b) = (index(i, j), index(i + 1, j));
+                let (c, d) = (index(i, j + 1), index(i + 1, j + 1));
+                [a, c, b, b, c, d]
+            })
+            .collect();
+
+        Self { vertices, indices }
+    }
+
+    /// Uploads the mesh to a new VAO, VBO and EBO laid out for the
+    /// `tex_square` shaders.
+    pub fn upload(&self, gl: &dyn GlBackend, program: ProgramId) -> Drawable {
+        let vertex_data: Vec<f32> = self.vertices.iter().flat_map(|v| v.flatten()).collect();
+        let (mut vao, mut vbo, mut ebo);
+        unsafe {
+            vao = std::mem::zeroed();
+            gl.GenVertexArrays(1, &mut vao);
+            gl.BindVertexArray(vao);
+
+            vbo = std::mem::zeroed();
+            gl.GenBuffers(1, &mut vbo);
+            gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertex_data.as_slice()) as gl::types::GLsizeiptr,
+                vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+
+            ebo = std::mem::zeroed();
+            gl.GenBuffers(1, &mut ebo);
+            gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl.BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                std::mem::size_of_val(self.indices.as_slice()) as gl::types::GLsizeiptr,
+                self.indices.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+
+            set_vertex_attributes(gl, program);
+        }
+
+        Drawable::Indexed(IndexedElements {
+            vao,
+            vbo,
+            ebo,
+            index_count: self.indices.len(),
+            bounds: Aabb::from_vertex_data(&vertex_data, SquareVertex::FLAT_SIZE),
+        })
+    }
+}
+
+/// A continuous terrain: a textured, lit and fogged triangle mesh following a
+/// height function, in place of voxels.
+pub struct Heightfield {
+    grid: HeightfieldGrid,
+    height_at: Box<dyn Fn(f32, f32) -> f32>,
+    texture: TextureSource,
+    tile_len: f32,
+    shader: Option<Shader>,
+}
+
+impl Heightfield {
+    /// The surface `y = height_at(x, z)` over `grid`, in world units, like
+    /// [`TerrainConfig::smooth_height_fn`](crate::terrain_builder::TerrainConfig::smooth_height_fn).
+    pub fn new(
+        grid: HeightfieldGrid,
+        height_at: impl Fn(f32, f32) -> f32 + 'static,
+        texture: TextureSource,
+    ) -> Self {
+        Self {
+            grid,
+            height_at: Box::new(height_at),
+            texture,
+            tile_len: 1.0,
+            shader: None,
+        }
+    }
+
+    /// Repeats the texture every `tile_len` world units, every 1 by default.
+    pub fn with_texture_tile(mut self, tile_len: f32) -> Self {
+        self.tile_len = tile_len;
+        self
+    }
+
+    pub fn grid(&self) -> &HeightfieldGrid {
+        &self.grid
+    }
+
+    /// Height of the surface at `(x, z)`, where the mesh vertices are.
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        (self.height_at)(x, z)
+    }
+}
+
+impl GlslPass for Heightfield {
+    /// # Panics
+    /// If the texture can't be loaded.
+    fn init(
+        &mut self,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) = ShaderFiles::build_program(
+            gl_fns.as_ref(),
+            tex_square::VERTEX_SHADER_PATH,
+            tex_square::FRAGMENT_SHADER_PATH,
+        )?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let mesh = HeightfieldMesh::new(&self.grid, self.tile_len, &self.height_at);
+        let drawable = mesh.upload(gl_fns.as_ref(), program);
+        let image = self
+            .texture
+            .load()
+            .unwrap_or_else(|err| panic!("{:?} should load: {err}", self.texture));
+        let tex = upload_texture(gl_fns.as_ref(), &image);
+
+        self.shader = Some(
+            Shader::new(gl_fns, program, uniforms, files, model, vec![drawable])
+                .with_tex(Some(tex)),
+        );
+
+        Ok(())
+    }
+
+    fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+        if let Some(shader) = &mut self.shader {
+            if let Some(model) = mat3d.model {
+                shader.model_transform = model;
+            }
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
+
+            for uniform in to_set_uniforms {
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
+            }
+        }
+    }
+
+    fn set_own_uniforms(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+        uniforms.set_sampler(gl, "tex", 0);
+        uniforms.set_bool(gl, "uEmissive", false);
+    }
+
+    fn get_shader(&self) -> Option<&Shader> {
+        self.shader.as_ref()
+    }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
+    }
+}
+
+impl Entity for Heightfield {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        renderer::backend::mock::{GlCall, MockGl},
+        voxel::blocks::DIRT_TEXTURE,
+    };
+
+    fn grid(origin: Vec2) -> HeightfieldGrid {
+        HeightfieldGrid {
+            origin,
+            cells: UVec2::new(4, 3),
+            cell_size: 0.5,
+        }
+    }
+
+    fn hill(x: f32, z: f32) -> f32 {
+        3.0 - 0.1 * (x * x + z * z)
+    }
+
+    #[test]
+    fn two_upward_triangles_per_cell() {
+        let mesh = HeightfieldMesh::new(&grid(Vec2::ZERO), 1.0, |_, _| 2.0);
+        assert_eq!(mesh.vertices.len(), 5 * 4);
+        assert_eq!(mesh.indices.len(), 4 * 3 * 6);
+
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| mesh.vertices[triangle[k] as usize].position);
+            let normal = (b - a).cross(c - a);
+            assert!(normal.y > 0.0, "{triangle:?} faces {normal}");
+        }
+        assert!(mesh.vertices.iter().all(|v| v.normal == Vec3::Y));
+        assert_eq!(mesh.vertices[19].position, Vec3::new(2.0, 2.0, 1.5));
+    }
+
+    #[test]
+    fn normals_follow_the_slope() {
+        let mesh = HeightfieldMesh::new(&grid(Vec2::ZERO), 1.0, |x, z| 0.5 * x - z);
+        let expected = Vec3::new(-0.5, 1.0, 1.0).normalize();
+        for vertex in &mesh.vertices {
+            assert!(
+                vertex.normal.abs_diff_eq(expected, 1e-6),
+                "{}",
+                vertex.normal
+            );
+        }
+    }
+
+    #[test]
+    fn neighbouring_grids_share_their_edge() {
+        let left = HeightfieldMesh::new(&grid(Vec2::new(-2.0, -1.0)), 1.0, hill);
+        let right = HeightfieldMesh::new(&grid(Vec2::new(0.0, -1.0)), 1.0, hill);
+
+        // The last column of the left grid is the first of the right one
+        for j in 0..4 {
+            let (a, b) = (&left.vertices[4 + j * 5], &right.vertices[j * 5]);
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.normal, b.normal);
+            assert_eq!(a.tex_map, b.tex_map);
+        }
+    }
+
+    #[test]
+    fn texture_repeats_every_tile() {
+        let mesh = HeightfieldMesh::new(&grid(Vec2::new(1.0, -1.0)), 0.25, hill);
+        let last = mesh.vertices.last().unwrap();
+        assert_eq!(last.tex_map, Vec2::new(3.0, 0.5) / 0.25);
+        assert_eq!(last.uv_rect, UvRect::FULL);
+    }
+
+    #[test]
+    fn draws_indexed_triangles() {
+        let gl = Rc::new(MockGl::default());
+        let mut field = Heightfield::new(
+            grid(Vec2::ZERO),
+            hill,
+            TextureSource::File(DIRT_TEXTURE.into()),
+        );
+        field.init(gl.clone(), Mat3DUpdate::default(), &[]).unwrap();
+        gl.clear_calls();
+
+        field.update_draw(Mat3DUpdate::default(), &[]);
+        let draws = gl.count(|c| matches!(c, GlCall::DrawElements { count: 72, .. }));
+        assert_eq!(draws, 1);
+        assert_eq!(field.height(1.0, 2.0), hill(1.0, 2.0));
+    }
+}
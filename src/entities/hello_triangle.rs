@@ -2,9 +2,15 @@ use std::{rc::Rc, time::Instant};
 
 use crate::{
     entities::Entity,
-    gl::{self, Gles2},
+    gl,
     helpers::{GlColor, GlPosition, Mat3DUpdate},
-    renderer::shader::{Array, Drawable, GlslPass, Shader, create_shader, uniform::Uniform},
+    renderer::{
+        backend::GlBackend,
+        shader::{
+            Array, Drawable, GlslPass, Shader, hot_reload::ShaderFiles, program::ShaderError,
+            uniform::Uniform,
+        },
+    },
 };
 
 #[derive(Clone)]
@@ -85,13 +91,19 @@ impl HelloTriangle {
 }
 
 impl GlslPass for HelloTriangle {
-    fn init(&mut self, gl_fns: Rc<Gles2>, mat3d: Mat3DUpdate, init_uniforms: &[Box<dyn Uniform>]) {
-        let program;
+    fn init(
+        &mut self,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) =
+            ShaderFiles::build_program(gl_fns.as_ref(), VERTEX_SHADER_PATH, FRAGMENT_SHADER_PATH)?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
         let mut vao;
         let mut vbo;
 
-        let mat3d = mat3d.as_init();
-
         let vertex_data: Vec<f32> = self
             .instance
             .into_iter()
@@ -99,23 +111,6 @@ impl GlslPass for HelloTriangle {
             .collect();
 
         unsafe {
-            program = gl_fns.CreateProgram();
-            gl_fns.UseProgram(program);
-
-            let vertex_shader = create_shader(&gl_fns, gl::VERTEX_SHADER, VERTEX_SHADER_SOURCE);
-            let fragment_shader =
-                create_shader(&gl_fns, gl::FRAGMENT_SHADER, FRAGMENT_SHADER_SOURCE);
-
-            gl_fns.AttachShader(program, vertex_shader);
-            gl_fns.AttachShader(program, fragment_shader);
-
-            gl_fns.LinkProgram(program);
-
-            gl_fns.DeleteShader(vertex_shader);
-            gl_fns.DeleteShader(fragment_shader);
-
-            gl_fns.UseProgram(program);
-
             vao = std::mem::zeroed();
             gl_fns.GenVertexArrays(1, &mut vao);
             gl_fns.BindVertexArray(vao);
@@ -131,8 +126,6 @@ impl GlslPass for HelloTriangle {
                 gl::STATIC_DRAW,
             );
 
-            mat3d.set_uniforms(&gl_fns, program);
-
             let pos_attrib = gl_fns.GetAttribLocation(program, c"position".as_ptr() as *const _);
             gl_fns.VertexAttribPointer(
                 pos_attrib as gl::types::GLuint,
@@ -155,10 +148,6 @@ impl GlslPass for HelloTriangle {
 
             gl_fns.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
             gl_fns.EnableVertexAttribArray(color_attrib as gl::types::GLuint);
-
-            for uniform in init_uniforms {
-                uniform.set(&gl_fns, program);
-            }
         }
 
         let drawable = Drawable::Array(Array {
@@ -167,19 +156,17 @@ impl GlslPass for HelloTriangle {
             len: self.instance.len(),
             offset: 3,
             count: 3,
+            // The vertices move, never culled
+            bounds: None,
         });
 
         let drawables = vec![drawable];
 
-        self.shader = Some(Shader {
-            program,
-            model_transform: mat3d
-                .model
-                .expect("mat3d as init should be at least IDENTITY"),
-            drawables,
-            tex: Default::default(),
-            gl_fns,
-        })
+        self.shader = Some(Shader::new(
+            gl_fns, program, uniforms, files, model, drawables,
+        ));
+
+        Ok(())
     }
 
     fn update(&mut self, mut mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
@@ -204,10 +191,10 @@ impl GlslPass for HelloTriangle {
                     * glam::Mat4::from_translation(-self.init_pos);
                 mat3d.model = Some(shader.model_transform);
             }
-            unsafe { mat3d.set_uniforms(&shader.gl_fns, shader.program) };
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
 
             for uniform in to_set_uniforms {
-                uniform.set(&shader.gl_fns, shader.program);
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
             }
         }
     }
@@ -215,37 +202,13 @@ impl GlslPass for HelloTriangle {
     fn get_shader(&self) -> Option<&Shader> {
         self.shader.as_ref()
     }
-}
-
-impl Entity for HelloTriangle {}
-
-const VERTEX_SHADER_SOURCE: &[u8] = b"
-#version 410 core
-
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
-
-layout(location = 0) in vec3 position;
-layout(location = 1) in vec3 color;
 
-out vec3 v_color;  // goes to the fragment shader
-
-void main() {
-    gl_Position = projection * view * model * vec4(position, 1.0);
-    // gl_Position = vec4(position, 0.0, 1.0);
-    v_color = color;
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
+    }
 }
-\0";
-
-const FRAGMENT_SHADER_SOURCE: &[u8] = b"
-#version 410 core
 
-in vec3 v_color;
-
-layout(location = 0) out vec4 FragColor;
+impl Entity for HelloTriangle {}
 
-void main() {
-    FragColor = vec4(v_color, 1.0);
-}
-\0";
+pub(crate) const VERTEX_SHADER_PATH: &str = "hello_triangle.vert";
+pub(crate) const FRAGMENT_SHADER_PATH: &str = "hello_triangle.frag";
@@ -0,0 +1,537 @@
+use std::{collections::HashMap, rc::Rc};
+
+use glam::{UVec2, Vec2};
+
+use crate::{
+    entities::{
+        Entity,
+        heightfield::{HeightfieldGrid, HeightfieldMesh},
+        tex_square::{self, upload_texture},
+    },
+    helpers::{GlPosition, Mat3DUpdate},
+    renderer::{
+        backend::GlBackend,
+        shader::{
+            Drawable, GlslPass, Shader, hot_reload::ShaderFiles, program::ShaderError,
+            registry::UniformRegistry, uniform::Uniform,
+        },
+    },
+    voxel::blocks::TextureSource,
+};
+
+/// Cells along each side of a patch at full detail, by default.
+pub const DEFAULT_PATCH_CELLS: u32 = 32;
+
+/// A square of patches, each of `patch_cells` by `patch_cells` cells at full
+/// detail. At level `l` a patch keeps every `2^l`th vertex of the full grid,
+/// down to a single cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodGrid {
+    /// Corner of the first patch, at the lowest x and z
+    pub origin: Vec2,
+    /// Patches along x and z
+    pub patches: UVec2,
+    /// A power of two
+    pub patch_cells: u32,
+    /// Side of a cell at full detail
+    pub cell_size: f32,
+}
+
+impl LodGrid {
+    /// The coarsest level, where a patch is one cell.
+    pub fn max_level(&self) -> u32 {
+        self.patch_cells.trailing_zeros()
+    }
+
+    /// The cells of `patch` at `level`.
+    pub fn patch_grid(&self, patch: UVec2, level: u32) -> HeightfieldGrid {
+        HeightfieldGrid {
+            origin: self.origin + (patch * self.patch_cells).as_vec2() * self.cell_size,
+            cells: UVec2::splat(self.patch_cells >> level),
+            cell_size: self.cell_size * (1 << level) as f32,
+        }
+    }
+
+    /// Level of every patch, row by row along x, coarser the further from
+    /// `pos` on the x, z plane, see [`lod_level`]. Each patch is stitched to
+    /// its coarser neighbours.
+    pub fn lods_around(&self, pos: GlPosition, lod_distance: f32) -> Vec<PatchLod> {
+        let levels: Vec<u32> = self
+            .patch_coords()
+            .map(|patch| {
+                let min = self.patch_grid(patch, 0).origin;
+                let max = min + Vec2::splat(self.patch_cells as f32 * self.cell_size);
+                let pos = Vec2::new(pos.x, pos.z);
+                let distance = pos.distance(pos.clamp(min, max));
+                lod_level(distance, lod_distance, self.max_level())
+            })
+            .collect();
+
+        let level_at = |patch: UVec2| levels[(patch.x + patch.y * self.patches.x) as usize];
+        let ratio = |level: u32, neighbour: Option<UVec2>| {
+            neighbour.map_or(1, |n| 1 << level_at(n).saturating_sub(level))
+        };
+        self.patch_coords()
+            .map(|patch| {
+                let level = level_at(patch);
+                let near = |dx: i32, dy: i32| {
+                    let n = patch.as_ivec2() + glam::IVec2::new(dx, dy);
+                    (n.cmpge(glam::IVec2::ZERO).all() && n.as_uvec2().cmplt(self.patches).all())
+                        .then(|| n.as_uvec2())
+                };
+                PatchLod {
+                    level,
+                    edges: EdgeRatios {
+                        neg_x: ratio(level, near(-1, 0)),
+                        pos_x: ratio(level, near(1, 0)),
+                        neg_z: ratio(level, near(0, -1)),
+                        pos_z: ratio(level, near(0, 1)),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Every patch, row by row along x.
+    pub fn patch_coords(&self) -> impl Iterator<Item = UVec2> + use<> {
+        let patches = self.patches;
+        (0..patches.y).flat_map(move |y| (0..patches.x).map(move |x| UVec2::new(x, y)))
+    }
+}
+
+/// Level of a patch `distance` away: full detail within `lod_distance`, one
+/// level coarser every time the distance doubles, at most `max_level`.
+pub fn lod_level(distance: f32, lod_distance: f32, max_level: u32) -> u32 {
+    if distance < lod_distance {
+        return 0;
+    }
+    ((distance / lod_distance).log2() as u32 + 1).min(max_level)
+}
+
+/// How many times coarser than a patch its neighbour on each side is, 1 when
+/// it is as fine or finer or there is none. A power of two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EdgeRatios {
+    pub neg_x: u32,
+    pub pos_x: u32,
+    pub neg_z: u32,
+    pub pos_z: u32,
+}
+
+impl EdgeRatios {
+    pub const NONE: Self = Self {
+        neg_x: 1,
+        pos_x: 1,
+        neg_z: 1,
+        pos_z: 1,
+    };
+}
+
+/// What a patch mesh is built for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PatchLod {
+    pub level: u32,
+    pub edges: EdgeRatios,
+}
+
+/// The mesh of `grid` with the edges against coarser neighbours stitched to
+/// them: along such an edge only every `ratio`th vertex is used, the vertices
+/// in between are snapped to the previous one, or the next one on the `pos`
+/// edges so no cell is split across a corner, and the triangles this flattens
+/// are dropped. The edge then follows the same vertices as the neighbour's
+/// and no cracks open between them.
+///
+/// Normals come from differences `normal_step` away, the full detail cell
+/// size, so every level lights the vertices it shares the same.
+pub fn patch_mesh(
+    grid: &HeightfieldGrid,
+    edges: EdgeRatios,
+    normal_step: f32,
+    tile_len: f32,
+    height_at: impl Fn(f32, f32) -> f32,
+) -> HeightfieldMesh {
+    let mut mesh = HeightfieldMesh::with_normal_step(grid, tile_len, normal_step, height_at);
+    let (cells, row) = (grid.cells, grid.vertices().x);
+    let snap = |index: u32| {
+        let (mut i, mut j) = (index % row, index / row);
+        let down = |t: u32, ratio: u32| t / ratio * ratio;
+        let up = |t: u32, ratio: u32| t.div_ceil(ratio) * ratio;
+        if j == 0 {
+            i = down(i, edges.neg_z);
+        } else if j == cells.y {
+            i = up(i, edges.pos_z);
+        }
+        if i == 0 {
+            j = down(j, edges.neg_x);
+        } else if i == cells.x {
+            j = up(j, edges.pos_x);
+        }
+        i + j * row
+    };
+    mesh.indices = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| [snap(triangle[0]), snap(triangle[1]), snap(triangle[2])])
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .flatten()
+        .collect();
+    mesh
+}
+
+/// A heightfield over a large area split into geomipmapped patches: the
+/// further a patch from the camera, the fewer vertices it has. Levels are
+/// picked by [`LodTerrain::update_lod`], patches are remeshed when their level
+/// or a neighbour's changes.
+pub struct LodTerrain {
+    grid: LodGrid,
+    height_at: Box<dyn Fn(f32, f32) -> f32>,
+    texture: TextureSource,
+    tile_len: f32,
+    /// Patches closer to the camera than this are at full detail, see
+    /// [`lod_level`]
+    pub lod_distance: f32,
+    patches: HashMap<UVec2, (PatchLod, Drawable)>,
+    shader: Option<Shader>,
+}
+
+impl LodTerrain {
+    /// The surface `y = height_at(x, z)` over `grid`, in world units, like
+    /// [`Heightfield::new`](crate::entities::heightfield::Heightfield::new).
+    /// Full detail within 4 patches of the camera.
+    pub fn new(
+        grid: LodGrid,
+        height_at: impl Fn(f32, f32) -> f32 + 'static,
+        texture: TextureSource,
+    ) -> Self {
+        Self {
+            grid,
+            height_at: Box::new(height_at),
+            texture,
+            tile_len: 1.0,
+            lod_distance: 4.0 * grid.patch_cells as f32 * grid.cell_size,
+            patches: HashMap::new(),
+            shader: None,
+        }
+    }
+
+    /// Repeats the texture every `tile_len` world units, every 1 by default.
+    pub fn with_texture_tile(mut self, tile_len: f32) -> Self {
+        self.tile_len = tile_len;
+        self
+    }
+
+    pub fn grid(&self) -> &LodGrid {
+        &self.grid
+    }
+
+    /// Height of the surface at `(x, z)`, where the full detail vertices are.
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        (self.height_at)(x, z)
+    }
+
+    /// What `patch` is currently meshed at, none before the first
+    /// [`LodTerrain::update_lod`].
+    pub fn patch_lod(&self, patch: UVec2) -> Option<PatchLod> {
+        self.patches.get(&patch).map(|(lod, _)| *lod)
+    }
+
+    /// Picks the level of every patch for a camera at `pos` and remeshes the
+    /// patches whose level or stitching changed. Nothing is drawn before the
+    /// first call.
+    pub fn update_lod(&mut self, pos: GlPosition) {
+        let Some(shader) = &mut self.shader else {
+            log::warn!("Tried to update the LOD of a LodTerrain before init");
+            return;
+        };
+        let gl = shader.gl_fns.as_ref();
+
+        let mut changed = false;
+        let lods = self.grid.lods_around(pos, self.lod_distance);
+        for (patch, lod) in self.grid.patch_coords().zip(lods) {
+            if self.patches.get(&patch).is_some_and(|(old, _)| *old == lod) {
+                continue;
+            }
+            let mesh = patch_mesh(
+                &self.grid.patch_grid(patch, lod.level),
+                lod.edges,
+                self.grid.cell_size,
+                self.tile_len,
+                &self.height_at,
+            );
+            let drawable = mesh.upload(gl, shader.program);
+            if let Some((_, old)) = self.patches.insert(patch, (lod, drawable)) {
+                unsafe { old.delete(gl) };
+            }
+            changed = true;
+        }
+
+        if changed {
+            // The shader draws, and frees on drop, the current meshes
+            shader.drawables = self.patches.values().map(|(_, d)| d.clone()).collect();
+        }
+    }
+}
+
+impl GlslPass for LodTerrain {
+    /// # Panics
+    /// If the texture can't be loaded.
+    fn init(
+        &mut self,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) = ShaderFiles::build_program(
+            gl_fns.as_ref(),
+            tex_square::VERTEX_SHADER_PATH,
+            tex_square::FRAGMENT_SHADER_PATH,
+        )?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let image = self
+            .texture
+            .load()
+            .unwrap_or_else(|err| panic!("{:?} should load: {err}", self.texture));
+        let tex = upload_texture(gl_fns.as_ref(), &image);
+
+        // Patches are meshed once the camera position is known
+        self.patches.clear();
+        self.shader =
+            Some(Shader::new(gl_fns, program, uniforms, files, model, vec![]).with_tex(Some(tex)));
+
+        Ok(())
+    }
+
+    fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+        if let Some(shader) = &mut self.shader {
+            if let Some(model) = mat3d.model {
+                shader.model_transform = model;
+            }
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
+
+            for uniform in to_set_uniforms {
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
+            }
+        }
+    }
+
+    fn set_own_uniforms(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+        uniforms.set_sampler(gl, "tex", 0);
+        uniforms.set_bool(gl, "uEmissive", false);
+    }
+
+    fn get_shader(&self) -> Option<&Shader> {
+        self.shader.as_ref()
+    }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
+    }
+}
+
+impl Entity for LodTerrain {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use glam::Vec3;
+
+    use super::*;
+    use crate::{
+        gl,
+        renderer::backend::mock::{GlCall, MockGl},
+        voxel::blocks::DIRT_TEXTURE,
+    };
+
+    const GRID: LodGrid = LodGrid {
+        origin: Vec2::new(-8.0, 4.0),
+        patches: UVec2::new(6, 5),
+        patch_cells: 8,
+        cell_size: 0.5,
+    };
+
+    fn hills(x: f32, z: f32) -> f32 {
+        (0.7 * x).sin() + (0.3 * z).cos() * 2.0
+    }
+
+    fn mesh(patch: UVec2, lod: PatchLod) -> HeightfieldMesh {
+        let grid = GRID.patch_grid(patch, lod.level);
+        patch_mesh(&grid, lod.edges, GRID.cell_size, 1.0, hills)
+    }
+
+    /// Position bits, normal bits and texture coordinates of the vertices the
+    /// triangles of `mesh` use where `on_edge`.
+    fn edge_vertices(mesh: &HeightfieldMesh, on_edge: impl Fn(Vec3) -> bool) -> BTreeSet<[u32; 8]> {
+        mesh.indices
+            .iter()
+            .map(|&index| &mesh.vertices[index as usize])
+            .filter(|v| on_edge(v.position))
+            .map(|v| {
+                let [x, y, z] = v.position.to_array().map(f32::to_bits);
+                let [nx, ny, nz] = v.normal.to_array().map(f32::to_bits);
+                let [u, w] = v.tex_map.to_array().map(f32::to_bits);
+                [x, y, z, nx, ny, nz, u, w]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn levels_drop_as_the_distance_doubles() {
+        assert_eq!(lod_level(0.0, 10.0, 5), 0);
+        assert_eq!(lod_level(9.9, 10.0, 5), 0);
+        assert_eq!(lod_level(10.0, 10.0, 5), 1);
+        assert_eq!(lod_level(19.9, 10.0, 5), 1);
+        assert_eq!(lod_level(20.0, 10.0, 5), 2);
+        assert_eq!(lod_level(1e6, 10.0, 5), 5);
+    }
+
+    #[test]
+    fn patches_near_the_camera_are_finer_and_stitched_to_the_coarser() {
+        let corner = Vec3::new(GRID.origin.x, 0.0, GRID.origin.y);
+        let lods = GRID.lods_around(corner, 4.0);
+        assert_eq!(lods.len(), 30);
+
+        let first = lods[0];
+        assert_eq!(first.level, 0);
+        assert_eq!(first.edges.neg_x, 1, "no neighbour outside");
+        assert_eq!(first.edges.pos_x, 1 << lods[1].level);
+        assert_eq!(lods[29].level, GRID.max_level());
+        assert_eq!(lods[29].edges, EdgeRatios::NONE);
+        assert!(lods.windows(2).take(5).all(|w| w[0].level <= w[1].level));
+    }
+
+    #[test]
+    fn stitched_patches_cover_their_square_facing_up() {
+        let area = (GRID.patch_cells as f32 * GRID.cell_size).powi(2);
+        for level in 0..=GRID.max_level() {
+            let coarser = 1..=1 << (GRID.max_level() - level);
+            for ratio in coarser.filter(|r: &u32| r.is_power_of_two()) {
+                let edges = [
+                    EdgeRatios {
+                        neg_x: ratio,
+                        ..EdgeRatios::NONE
+                    },
+                    EdgeRatios {
+                        pos_z: ratio,
+                        ..EdgeRatios::NONE
+                    },
+                    EdgeRatios {
+                        neg_z: ratio,
+                        pos_x: ratio,
+                        ..EdgeRatios::NONE
+                    },
+                    EdgeRatios {
+                        neg_x: ratio,
+                        pos_x: ratio,
+                        neg_z: ratio,
+                        pos_z: ratio,
+                    },
+                ];
+                for edges in edges {
+                    let mesh = mesh(UVec2::ONE, PatchLod { level, edges });
+                    let covered: f32 = mesh
+                        .indices
+                        .chunks(3)
+                        .map(|triangle| {
+                            let [a, b, c] =
+                                [0, 1, 2].map(|k| mesh.vertices[triangle[k] as usize].position);
+                            let normal = (b - a).cross(c - a);
+                            assert!(normal.y > 0.0, "{level} {edges:?}: {triangle:?} faces down");
+                            normal.y / 2.0
+                        })
+                        .sum();
+                    assert!(
+                        (covered - area).abs() < 1e-3,
+                        "{level} {edges:?}: covers {covered}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adjacent_patches_share_edge_vertices() {
+        // Detail drops from the corner, so patches meet at every pair of levels
+        let corner = Vec3::new(GRID.origin.x, 0.0, GRID.origin.y);
+        let lods = GRID.lods_around(corner, 2.0);
+        let levels: BTreeSet<_> = lods.iter().map(|lod| lod.level).collect();
+        assert!(levels.len() > 2, "{levels:?}");
+
+        let lod_of = |patch: UVec2| lods[(patch.x + patch.y * GRID.patches.x) as usize];
+        for patch in GRID.patch_coords() {
+            let here = mesh(patch, lod_of(patch));
+            let far_corner = GRID.patch_grid(patch + 1, 0).origin;
+
+            if patch.x + 1 < GRID.patches.x {
+                let right = patch + UVec2::X;
+                let there = mesh(right, lod_of(right));
+                let on_edge = |p: Vec3| p.x == far_corner.x;
+                let shared = edge_vertices(&here, on_edge);
+                assert_eq!(
+                    shared,
+                    edge_vertices(&there, on_edge),
+                    "{patch} and {right}"
+                );
+                let coarsest = lod_of(patch).level.max(lod_of(right).level);
+                assert_eq!(shared.len() as u32, (GRID.patch_cells >> coarsest) + 1);
+            }
+            if patch.y + 1 < GRID.patches.y {
+                let front = patch + UVec2::Y;
+                let there = mesh(front, lod_of(front));
+                let on_edge = |p: Vec3| p.z == far_corner.y;
+                let shared = edge_vertices(&here, on_edge);
+                assert_eq!(
+                    shared,
+                    edge_vertices(&there, on_edge),
+                    "{patch} and {front}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_patches_whose_lod_changed_are_remeshed() {
+        let gl = Rc::new(MockGl::default());
+        let mut terrain = LodTerrain::new(GRID, hills, TextureSource::File(DIRT_TEXTURE.into()));
+        terrain.lod_distance = 4.0;
+        terrain
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        let meshes = |gl: &MockGl| {
+            gl.count(|c| {
+                matches!(
+                    c,
+                    GlCall::BufferData {
+                        target: gl::ELEMENT_ARRAY_BUFFER,
+                        ..
+                    }
+                )
+            })
+        };
+
+        gl.clear_calls();
+        let corner = Vec3::new(GRID.origin.x, 0.0, GRID.origin.y);
+        terrain.update_lod(corner);
+        assert_eq!(meshes(&gl), 30);
+        assert_eq!(terrain.get_shader().unwrap().drawables.len(), 30);
+        assert_eq!(terrain.patch_lod(UVec2::ZERO).unwrap().level, 0);
+
+        gl.clear_calls();
+        terrain.update_lod(corner + Vec3::Y * 10.0);
+        assert_eq!(meshes(&gl), 0, "same levels");
+
+        let before = terrain.grid().lods_around(corner, 4.0);
+        let opposite = corner + Vec3::new(24.0, 0.0, 20.0);
+        terrain.update_lod(opposite);
+        let after = terrain.grid().lods_around(opposite, 4.0);
+        let changed = before.iter().zip(&after).filter(|(a, b)| a != b).count();
+        assert_eq!(meshes(&gl), changed);
+        let freed = gl.count(|c| matches!(c, GlCall::DeleteVertexArrays(_)));
+        assert_eq!(freed, changed);
+        assert_eq!(
+            terrain.patch_lod(UVec2::ZERO).unwrap().level,
+            GRID.max_level()
+        );
+    }
+}
@@ -1,8 +1,14 @@
 use crate::renderer::shader::GlslPass;
 
+pub mod block_highlight;
+pub mod chunked_terrain;
+pub mod heightfield;
 pub mod hello_triangle;
+pub mod lod_terrain;
 pub mod sun;
 pub mod tex_cube;
 pub mod tex_square;
 pub mod utah_teapot;
+pub mod vegetation;
+pub mod water;
 pub trait Entity: GlslPass {}
@@ -8,7 +8,14 @@ use crate::{
         tex_square::{Square, TexSquare},
     },
     helpers::{GlPosition, Mat3DUpdate},
-    renderer::shader::{GlslPass, Shader, uniform::Uniform},
+    renderer::{
+        atlas::UvRect,
+        backend::GlBackend,
+        shader::{
+            GlslPass, Shader, program::ShaderError, registry::UniformRegistry, uniform::Uniform,
+        },
+        stats::RenderStats,
+    },
 };
 
 pub struct Sun {
@@ -37,9 +44,11 @@ impl Sun {
                         position.y,
                         position.z + SIDE_LEN,
                     ),
+                    uv_rect: UvRect::FULL,
                 }],
                 Some("./assets/sun.png".into()),
-            ),
+            )
+            .with_emissive(true),
             initial_pos: position,
             actual_pos: position,
             init: Instant::now(),
@@ -54,41 +63,48 @@ impl Sun {
 impl GlslPass for Sun {
     fn init(
         &mut self,
-        gl_fns: Rc<crate::gl::Gles2>,
+        gl_fns: Rc<dyn GlBackend>,
         mat3d: crate::helpers::Mat3DUpdate,
         init_uniforms: &[Box<dyn Uniform>],
-    ) {
-        self.square.init(gl_fns.clone(), mat3d, init_uniforms);
+    ) -> Result<(), ShaderError> {
+        self.square.init(gl_fns, mat3d, init_uniforms)
     }
 
     fn update(&mut self, mat3d: crate::helpers::Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
-        let elapsed_s_f32 = self.init.elapsed().as_secs_f32();
-        let next_spin_stop = f32::ceil(elapsed_s_f32 / ORBIT_T_S) * ORBIT_T_S;
-        let perc_of_rotation = (next_spin_stop - elapsed_s_f32) / ORBIT_T_S;
-        let rotation = 360.0 * perc_of_rotation;
-        let rotation = rotation.to_radians();
-        let dx = rotation.cos() * ORBIT_R;
-        let dz = rotation.sin() * ORBIT_R;
+        let model = match mat3d.model {
+            Some(model) => model,
+            None => {
+                let elapsed_s_f32 = self.init.elapsed().as_secs_f32();
+                let next_spin_stop = f32::ceil(elapsed_s_f32 / ORBIT_T_S) * ORBIT_T_S;
+                let perc_of_rotation = (next_spin_stop - elapsed_s_f32) / ORBIT_T_S;
+                let rotation = 360.0 * perc_of_rotation;
+                let rotation = rotation.to_radians();
+                let dx = rotation.cos() * ORBIT_R;
+                let dz = rotation.sin() * ORBIT_R;
+                glam::Mat4::from_translation(Vec3::new(dx, 0.0, dz))
+            }
+        };
 
-        let model = Some(glam::Mat4::from_translation(Vec3::new(dx, 0.0, dz)));
+        self.actual_pos = model.transform_point3(self.initial_pos);
 
-        self.actual_pos = GlPosition {
-            x: self.initial_pos.x + dx,
-            z: self.initial_pos.z + dz,
-            ..self.actual_pos
-        };
+        self.square.update(Mat3DUpdate { model: Some(model) }, to_set_uniforms);
+    }
 
-        self.square
-            .update(Mat3DUpdate { model, ..mat3d }, to_set_uniforms);
+    unsafe fn draw(&self) -> RenderStats {
+        self.square.draw()
     }
 
-    unsafe fn draw(&self) {
-        self.square.draw();
+    fn set_own_uniforms(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+        self.square.set_own_uniforms(gl, uniforms);
     }
 
     fn get_shader(&self) -> Option<&Shader> {
         self.square.get_shader()
     }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.square.get_shader_mut()
+    }
 }
 
 impl Entity for Sun {}
@@ -1,89 +1,205 @@
 use std::{path::PathBuf, rc::Rc};
 
+use glam::Vec3;
+
 use crate::{
     entities::{
         Entity,
-        tex_square::{Square, TexSquare},
+        tex_square::{SquareVertex, load_texture, set_vertex_attributes, squares_vertex_data},
     },
-    helpers::GlPosition,
-    renderer::shader::{GlslPass, Shader, uniform::Uniform},
-};
-
-fn build_faces(pos: &GlPosition, side_len: f32) -> [Square; 6] {
-    let half_side = side_len / 2.0;
-    [
-        // Front face
-        Square {
-            bottom_left: GlPosition::new(pos.x + half_side, pos.y + half_side, pos.z + half_side),
-            top_right: GlPosition::new(pos.x - half_side, pos.y - half_side, pos.z + half_side),
-        },
-        // Back face
-        Square {
-            bottom_left: GlPosition::new(pos.x + half_side, pos.y - half_side, pos.z - half_side),
-            top_right: GlPosition::new(pos.x - half_side, pos.y + half_side, pos.z - half_side),
-        },
-        // Right face
-        Square {
-            bottom_left: GlPosition::new(pos.x + half_side, pos.y - half_side, pos.z + half_side),
-            top_right: GlPosition::new(pos.x + half_side, pos.y + half_side, pos.z - half_side),
+    gl,
+    helpers::{GlPosition, Mat3DUpdate},
+    renderer::{
+        backend::GlBackend,
+        culling::Aabb,
+        shader::{
+            Drawable, GlslPass, InstancedArrays, Shader,
+            hot_reload::ShaderFiles,
+            instancing::{Instance, instances_bounds, upload_instances},
+            program::ShaderError,
+            registry::UniformRegistry,
+            uniform::Uniform,
         },
-        // Left face
-        Square {
-            bottom_left: GlPosition::new(pos.x - half_side, pos.y - half_side, pos.z - half_side),
-            top_right: GlPosition::new(pos.x - half_side, pos.y + half_side, pos.z + half_side),
-        },
-        // Top face
-        Square {
-            bottom_left: GlPosition::new(pos.x - half_side, pos.y + half_side, pos.z + half_side),
-            top_right: GlPosition::new(pos.x + half_side, pos.y + half_side, pos.z - half_side),
-        },
-        // Bottom face
-        Square {
-            bottom_left: GlPosition::new(pos.x - half_side, pos.y - half_side, pos.z - half_side),
-            top_right: GlPosition::new(pos.x + half_side, pos.y - half_side, pos.z + half_side),
-        },
-    ]
-}
+    },
+    voxel::Face,
+};
 
+/// Textured cubes of the same size, one mesh drawn once per cube in a single
+/// instanced call.
 pub struct TexCube {
-    squares: TexSquare,
+    instances: Vec<Instance>,
+    side_len: f32,
+    texture: Option<PathBuf>,
+    shader: Option<Shader>,
 }
 
 impl TexCube {
     pub fn new(positions: Vec<GlPosition>, side_len: f32, tex: Option<PathBuf>) -> Self {
         TexCube {
-            squares: TexSquare::new(
-                positions
-                    .into_iter()
-                    .flat_map(|p| build_faces(&p, side_len))
-                    .collect(),
-                tex,
-            ),
+            instances: positions
+                .into_iter()
+                .map(|p| Instance::at(p, Vec3::ONE))
+                .collect(),
+            side_len,
+            texture: tex,
+            shader: None,
+        }
+    }
+
+    /// Tints the texture of the cubes by `colors`, in the order of their
+    /// positions. Cubes past the last color stay untinted.
+    pub fn with_colors(mut self, colors: impl IntoIterator<Item = Vec3>) -> Self {
+        for (instance, color) in self.instances.iter_mut().zip(colors) {
+            instance.color = color;
         }
+        self
     }
 }
 
+/// The faces of a cube centered on the origin, flattened like
+/// [`squares_vertex_data`] but as two triangles per face.
+fn cube_vertex_data(side_len: f32) -> Vec<f32> {
+    let squares = Face::ALL.map(|face| face.square(&GlPosition::ZERO, side_len));
+    squares_vertex_data(&squares, None)
+        .chunks_exact(4 * SquareVertex::FLAT_SIZE)
+        .flat_map(|strip| {
+            // The two triangles of the strip, keeping its winding
+            [0, 1, 2, 2, 1, 3].into_iter().flat_map(move |v| {
+                &strip[v * SquareVertex::FLAT_SIZE..(v + 1) * SquareVertex::FLAT_SIZE]
+            })
+        })
+        .copied()
+        .collect()
+}
+
 impl GlslPass for TexCube {
     fn init(
         &mut self,
-        gl_fns: Rc<crate::gl::Gles2>,
-        mat3d: crate::helpers::Mat3DUpdate,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
         init_uniforms: &[Box<dyn Uniform>],
-    ) {
-        self.squares.init(gl_fns.clone(), mat3d, init_uniforms);
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) =
+            ShaderFiles::build_program(gl_fns.as_ref(), VERTEX_SHADER_PATH, FRAGMENT_SHADER_PATH)?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let vertex_data = cube_vertex_data(self.side_len);
+        let gl = gl_fns.as_ref();
+        let (mut vao, mut vbo, instance_vbo);
+        unsafe {
+            vao = std::mem::zeroed();
+            gl.GenVertexArrays(1, &mut vao);
+            gl.BindVertexArray(vao);
+
+            vbo = std::mem::zeroed();
+            gl.GenBuffers(1, &mut vbo);
+            gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertex_data.as_slice()) as gl::types::GLsizeiptr,
+                vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            set_vertex_attributes(gl, program);
+
+            instance_vbo = upload_instances(gl, program, &self.instances);
+        }
+        let tex = self.texture.as_ref().map(|path| load_texture(gl, path));
+
+        let drawables = vec![Drawable::InstancedArrays(InstancedArrays {
+            vao,
+            vbo,
+            instance_vbo,
+            vertex_count: vertex_data.len() / SquareVertex::FLAT_SIZE,
+            instance_count: self.instances.len(),
+            bounds: instances_bounds(
+                Aabb::from_vertex_data(&vertex_data, SquareVertex::FLAT_SIZE),
+                self.instances.iter().map(|instance| instance.model),
+            ),
+        })];
+
+        self.shader =
+            Some(Shader::new(gl_fns, program, uniforms, files, model, drawables).with_tex(tex));
+
+        Ok(())
     }
 
-    fn update(&mut self, mat3d: crate::helpers::Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
-        self.squares.update(mat3d, to_set_uniforms);
+    fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+        if let Some(shader) = &mut self.shader {
+            if let Some(model) = mat3d.model {
+                shader.model_transform = model;
+            }
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
+
+            for uniform in to_set_uniforms {
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
+            }
+        }
     }
 
-    unsafe fn draw(&self) {
-        self.squares.draw();
+    fn set_own_uniforms(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+        uniforms.set_sampler(gl, "tex", 0);
     }
 
     fn get_shader(&self) -> Option<&Shader> {
-        self.squares.get_shader()
+        self.shader.as_ref()
+    }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
     }
 }
 
 impl Entity for TexCube {}
+
+const VERTEX_SHADER_PATH: &str = "tex_cube.vert";
+const FRAGMENT_SHADER_PATH: &str = "tex_cube.frag";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    #[test]
+    fn cube_triangles_face_outward() {
+        let vertex_data = cube_vertex_data(2.0);
+        let positions: Vec<Vec3> = vertex_data
+            .chunks_exact(SquareVertex::FLAT_SIZE)
+            .map(|v| Vec3::from_slice(&v[..3]))
+            .collect();
+        assert_eq!(positions.len(), 36);
+        for triangle in positions.chunks_exact(3) {
+            let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]);
+            let center = (triangle[0] + triangle[1] + triangle[2]) / 3.0;
+            assert!(normal.dot(center) > 0.0, "{triangle:?} faces inward");
+        }
+    }
+
+    #[test]
+    fn draws_every_cube_in_one_call() {
+        let gl = Rc::new(MockGl::default());
+        let positions = (0..10)
+            .map(|i| GlPosition::new(i as f32, 0.0, 0.0))
+            .collect();
+        let mut cubes = TexCube::new(positions, 1.0, None);
+        cubes.init(gl.clone(), Mat3DUpdate::default(), &[]).unwrap();
+
+        gl.clear_calls();
+        cubes.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!(
+            gl.calls()
+                .into_iter()
+                .filter(|c| matches!(
+                    c,
+                    GlCall::DrawArrays { .. } | GlCall::DrawArraysInstanced { .. }
+                ))
+                .collect::<Vec<_>>(),
+            [GlCall::DrawArraysInstanced {
+                mode: gl::TRIANGLES,
+                first: 0,
+                count: 36,
+                instances: 10,
+            }]
+        );
+    }
+}
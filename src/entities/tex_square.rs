@@ -1,28 +1,50 @@
-use std::{path::PathBuf, rc::Rc};
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crate::{
     entities::Entity,
     gl,
     helpers::{GlPosition, Mat3DUpdate},
-    renderer::shader::{Array, Drawable, GlslPass, Shader, Tex, create_shader, uniform::Uniform},
+    renderer::{
+        atlas::UvRect,
+        backend::GlBackend,
+        culling::Aabb,
+        shader::{
+            Drawable, GlslPass, IndexedElements, Shader, Tex,
+            hot_reload::ShaderFiles,
+            program::ShaderError,
+            registry::UniformRegistry,
+            uniform::{ProgramId, Uniform},
+        },
+    },
 };
 
 pub struct SquareVertex {
     pub position: glam::Vec3,
+    /// Repeats every 1 inside `uv_rect`
     pub tex_map: glam::Vec2,
     pub normal: glam::Vec3,
+    pub uv_rect: UvRect,
 }
 
 impl SquareVertex {
-    pub fn new(position: GlPosition, tex_map: glam::Vec2, normal: glam::Vec3) -> Self {
+    pub fn new(
+        position: GlPosition,
+        tex_map: glam::Vec2,
+        normal: glam::Vec3,
+        uv_rect: UvRect,
+    ) -> Self {
         Self {
             position,
             tex_map,
             normal,
+            uv_rect,
         }
     }
 
-    pub const FLAT_SIZE: usize = 8;
+    pub const FLAT_SIZE: usize = 12;
 
     pub fn flatten(&self) -> [f32; Self::FLAT_SIZE] {
         [
@@ -34,6 +56,10 @@ impl SquareVertex {
             self.normal.x,
             self.normal.y,
             self.normal.z,
+            self.uv_rect.min.x,
+            self.uv_rect.min.y,
+            self.uv_rect.size.x,
+            self.uv_rect.size.y,
         ]
     }
 }
@@ -42,6 +68,8 @@ impl SquareVertex {
 pub struct Square {
     pub bottom_left: GlPosition,
     pub top_right: GlPosition,
+    /// Part of the texture drawn on the square, like an atlas tile
+    pub uv_rect: UvRect,
 }
 
 impl Square {
@@ -87,6 +115,21 @@ impl Square {
     }
 
     pub fn as_vertex_data(&self) -> [SquareVertex; 4] {
+        self.vertex_data_with_uv(glam::Vec2::ONE)
+    }
+
+    /// Like [`Square::as_vertex_data`], but the texture repeats every
+    /// `tile_len` instead of stretching over the whole square.
+    pub fn as_tiled_vertex_data(&self, tile_len: f32) -> [SquareVertex; 4] {
+        let [bl, tl, br, _] = self.wound_corners();
+        self.vertex_data_with_uv(glam::Vec2::new(
+            bl.distance(br) / tile_len,
+            bl.distance(tl) / tile_len,
+        ))
+    }
+
+    /// The corners as a triangle strip winding so the normal points outward.
+    fn wound_corners(&self) -> [GlPosition; 4] {
         let [bl, mut tl, mut br, tr] = self.as_vertex_stride();
 
         // Decide if we must flip winding so the normal points outward
@@ -94,7 +137,7 @@ impl Square {
         let y_const = self.bottom_left.y == self.top_right.y;
         // let z_const = self.bottom_left.z == self.top_right.z;
 
-        // Rules derived from how build_faces sets bottom_left/top_right
+        // Rules derived from how Face::square sets bottom_left/top_right
         let flip_winding = if x_const {
             // left face (x = -h) has bl.z < tr.z
             self.bottom_left.z < self.top_right.z
@@ -111,14 +154,21 @@ impl Square {
             std::mem::swap(&mut tl, &mut br);
         }
 
+        [bl, tl, br, tr]
+    }
+
+    fn vertex_data_with_uv(&self, uv_max: glam::Vec2) -> [SquareVertex; 4] {
+        let [bl, tl, br, tr] = self.wound_corners();
+
         // Recompute normal after potential swap
         let normal = (tl - bl).cross(br - bl).normalize();
 
+        let rect = self.uv_rect;
         [
-            SquareVertex::new(bl, glam::Vec2::new(0.0, 0.0), normal),
-            SquareVertex::new(tl, glam::Vec2::new(0.0, 1.0), normal),
-            SquareVertex::new(br, glam::Vec2::new(1.0, 0.0), normal),
-            SquareVertex::new(tr, glam::Vec2::new(1.0, 1.0), normal),
+            SquareVertex::new(bl, glam::Vec2::new(0.0, 0.0), normal, rect),
+            SquareVertex::new(tl, glam::Vec2::new(0.0, uv_max.y), normal, rect),
+            SquareVertex::new(br, glam::Vec2::new(uv_max.x, 0.0), normal, rect),
+            SquareVertex::new(tr, uv_max, normal, rect),
         ]
     }
 }
@@ -127,6 +177,8 @@ pub struct TexSquare {
     shader: Option<Shader>,
     instances: Vec<Square>,
     texture: Option<PathBuf>,
+    texture_tile: Option<f32>,
+    emissive: bool,
 }
 
 impl TexSquare {
@@ -135,6 +187,189 @@ impl TexSquare {
             shader: None,
             instances,
             texture,
+            texture_tile: None,
+            emissive: false,
+        }
+    }
+
+    /// Repeats the texture every `tile_len` along the squares instead of
+    /// stretching it once over each of them.
+    pub fn with_texture_tile(mut self, tile_len: f32) -> Self {
+        self.texture_tile = Some(tile_len);
+        self
+    }
+
+    /// Emissive squares show their texture as is, ignoring lighting and fog.
+    pub fn with_emissive(mut self, emissive: bool) -> Self {
+        self.emissive = emissive;
+        self
+    }
+}
+
+/// Uploads `squares` to a new VAO and VBO laid out for the `tex_square`
+/// shaders, `texture_tile` as in [`TexSquare::with_texture_tile`].
+pub fn upload_squares(
+    gl: &dyn GlBackend,
+    program: ProgramId,
+    squares: &[Square],
+    texture_tile: Option<f32>,
+) -> Drawable {
+    let vertex_data = squares_vertex_data(squares, texture_tile);
+    upload_vertex_data(gl, program, &vertex_data)
+}
+
+/// The flattened [`SquareVertex`]es of `squares`, 4 per square.
+pub fn squares_vertex_data(squares: &[Square], texture_tile: Option<f32>) -> Vec<f32> {
+    squares
+        .iter()
+        .flat_map(|sq| match texture_tile {
+            Some(tile_len) => sq.as_tiled_vertex_data(tile_len),
+            None => sq.as_vertex_data(),
+        })
+        .flat_map(|sv| sv.flatten())
+        .collect()
+}
+
+/// Two triangles per square of [`squares_vertex_data`], wound like its
+/// triangle strips, so all the squares are drawn in one call.
+pub fn square_indices(squares: usize) -> Vec<u32> {
+    (0..squares as u32)
+        .flat_map(|square| [0, 1, 2, 2, 1, 3].map(|corner| 4 * square + corner))
+        .collect()
+}
+
+/// Uploads vertex data from [`squares_vertex_data`], possibly built on another
+/// thread, like [`upload_squares`], along with its [`square_indices`].
+pub fn upload_vertex_data(gl: &dyn GlBackend, program: ProgramId, vertex_data: &[f32]) -> Drawable {
+    let indices = square_indices(vertex_data.len() / (4 * SquareVertex::FLAT_SIZE));
+    let mut vao;
+    let mut vbo;
+    let mut ebo;
+    unsafe {
+        vao = std::mem::zeroed();
+        gl.GenVertexArrays(1, &mut vao);
+        gl.BindVertexArray(vao);
+
+        vbo = std::mem::zeroed();
+        gl.GenBuffers(1, &mut vbo);
+        gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+        gl.BufferData(
+            gl::ARRAY_BUFFER,
+            std::mem::size_of_val(vertex_data) as gl::types::GLsizeiptr,
+            vertex_data.as_ptr() as *const _,
+            gl::STATIC_DRAW,
+        );
+
+        set_vertex_attributes(gl, program);
+
+        ebo = std::mem::zeroed();
+        gl.GenBuffers(1, &mut ebo);
+        gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+        gl.BufferData(
+            gl::ELEMENT_ARRAY_BUFFER,
+            std::mem::size_of_val(indices.as_slice()) as gl::types::GLsizeiptr,
+            indices.as_ptr() as *const _,
+            gl::STATIC_DRAW,
+        );
+    }
+
+    Drawable::Indexed(IndexedElements {
+        vao,
+        vbo,
+        ebo,
+        index_count: indices.len(),
+        bounds: Aabb::from_vertex_data(vertex_data, SquareVertex::FLAT_SIZE),
+    })
+}
+
+/// Points the attributes of the `tex_square` shaders at the bound vertex
+/// buffer, holding flattened [`SquareVertex`]es, and enables them.
+///
+/// # Safety
+/// FFI calls, a vertex array and buffer must be bound.
+pub unsafe fn set_vertex_attributes(gl: &dyn GlBackend, program: ProgramId) {
+    let pos_attrib = gl.GetAttribLocation(program, c"position".as_ptr() as *const _);
+    assert_ne!(pos_attrib, -1);
+    gl.VertexAttribPointer(
+        pos_attrib as gl::types::GLuint,
+        3,
+        gl::FLOAT,
+        0,
+        SquareVertex::FLAT_SIZE as i32 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+        std::ptr::null(),
+    );
+    let tex_attrib = gl.GetAttribLocation(program, c"textureCoord".as_ptr() as *const _);
+    assert_ne!(tex_attrib, -1);
+    gl.VertexAttribPointer(
+        tex_attrib as gl::types::GLuint,
+        2,
+        gl::FLOAT,
+        0,
+        SquareVertex::FLAT_SIZE as i32 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+        (3 * std::mem::size_of::<f32>()) as *const () as *const _,
+    );
+    let norm_attrib = gl.GetAttribLocation(program, c"normal".as_ptr() as *const _);
+    assert_ne!(norm_attrib, -1);
+    gl.VertexAttribPointer(
+        norm_attrib as gl::types::GLuint,
+        3,
+        gl::FLOAT,
+        0,
+        SquareVertex::FLAT_SIZE as i32 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+        (5 * std::mem::size_of::<f32>()) as *const () as *const _,
+    );
+
+    let rect_attrib = gl.GetAttribLocation(program, c"uvRect".as_ptr() as *const _);
+    assert_ne!(rect_attrib, -1);
+    gl.VertexAttribPointer(
+        rect_attrib as gl::types::GLuint,
+        4,
+        gl::FLOAT,
+        0,
+        SquareVertex::FLAT_SIZE as i32 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+        (8 * std::mem::size_of::<f32>()) as *const () as *const _,
+    );
+
+    gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
+    gl.EnableVertexAttribArray(tex_attrib as gl::types::GLuint);
+    gl.EnableVertexAttribArray(norm_attrib as gl::types::GLuint);
+    gl.EnableVertexAttribArray(rect_attrib as gl::types::GLuint);
+}
+
+/// Loads the image at `path` into a new mipmapped `TEXTURE_2D`.
+///
+/// # Panics
+/// If the image can't be read or decoded.
+pub fn load_texture(gl: &dyn GlBackend, path: &Path) -> Tex {
+    let image = image::ImageReader::open(path)
+        .unwrap_or_else(|_| panic!("{path:?} should be readable"))
+        .decode()
+        .unwrap_or_else(|_| panic!("{path:?} should be decodable"));
+    upload_texture(gl, &image.to_rgb8())
+}
+
+/// Uploads `image` into a new mipmapped `TEXTURE_2D`.
+pub fn upload_texture(gl: &dyn GlBackend, image: &image::RgbImage) -> Tex {
+    unsafe {
+        let mut tex = std::mem::zeroed();
+        gl.GenTextures(1, &mut tex);
+        gl.BindTexture(gl::TEXTURE_2D, tex);
+        gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGB as i32,
+            image.width() as i32,
+            image.height() as i32,
+            0,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            image.as_raw().as_ptr() as *const _,
+        );
+        gl.GenerateMipmap(gl::TEXTURE_2D);
+        Tex {
+            tex,
+            target: gl::TEXTURE_2D,
         }
     }
 }
@@ -142,153 +377,25 @@ impl TexSquare {
 impl GlslPass for TexSquare {
     fn init(
         &mut self,
-        gl_fns: Rc<crate::gl::Gles2>,
+        gl_fns: Rc<dyn GlBackend>,
         mat3d: Mat3DUpdate,
         init_uniforms: &[Box<dyn Uniform>],
-    ) {
-        let image = self.texture.as_ref().map(|path| {
-            image::ImageReader::open(path)
-                .unwrap_or_else(|_| panic!("{path:?} should be readable"))
-                .decode()
-                .unwrap_or_else(|_| panic!("{path:?} should be decodable"))
-        });
-
-        let program;
-        let mut vao;
-        let mut vbo;
-
-        let mat3d = mat3d.as_init();
-
-        let vertex_data: Vec<f32> = self
-            .instances
-            .iter()
-            .flat_map(|sq| sq.as_vertex_data())
-            .flat_map(|sv| sv.flatten())
-            .collect();
-
-        let tex;
-
-        unsafe {
-            program = gl_fns.CreateProgram();
-            gl_fns.UseProgram(program);
-
-            let vertex_shader = create_shader(&gl_fns, gl::VERTEX_SHADER, VERTEX_SHADER_SOURCE);
-            let fragment_shader =
-                create_shader(&gl_fns, gl::FRAGMENT_SHADER, FRAGMENT_SHADER_SOURCE);
-
-            gl_fns.AttachShader(program, vertex_shader);
-            gl_fns.AttachShader(program, fragment_shader);
-
-            gl_fns.LinkProgram(program);
-
-            gl_fns.DeleteShader(vertex_shader);
-            gl_fns.DeleteShader(fragment_shader);
-
-            gl_fns.UseProgram(program);
-
-            vao = std::mem::zeroed();
-            gl_fns.GenVertexArrays(1, &mut vao);
-            gl_fns.BindVertexArray(vao);
-
-            vbo = std::mem::zeroed();
-            gl_fns.GenBuffers(1, &mut vbo);
-            gl_fns.BindBuffer(gl::ARRAY_BUFFER, vbo);
-
-            gl_fns.BufferData(
-                gl::ARRAY_BUFFER,
-                (vertex_data.len() * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                vertex_data.as_ptr() as *const _,
-                gl::STATIC_DRAW,
-            );
-
-            mat3d.set_uniforms(&gl_fns, program);
-
-            let pos_attrib = gl_fns.GetAttribLocation(program, c"position".as_ptr() as *const _);
-            assert_ne!(pos_attrib, -1);
-            gl_fns.VertexAttribPointer(
-                pos_attrib as gl::types::GLuint,
-                3,
-                gl::FLOAT,
-                0,
-                SquareVertex::FLAT_SIZE as i32 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-                std::ptr::null(),
-            );
-            let tex_attrib =
-                gl_fns.GetAttribLocation(program, c"textureCoord".as_ptr() as *const _);
-            assert_ne!(tex_attrib, -1);
-            gl_fns.VertexAttribPointer(
-                tex_attrib as gl::types::GLuint,
-                2,
-                gl::FLOAT,
-                0,
-                SquareVertex::FLAT_SIZE as i32 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-                (3 * std::mem::size_of::<f32>()) as *const () as *const _,
-            );
-            let norm_attrib = gl_fns.GetAttribLocation(program, c"normal".as_ptr() as *const _);
-            assert_ne!(norm_attrib, -1);
-            gl_fns.VertexAttribPointer(
-                norm_attrib as gl::types::GLuint,
-                3,
-                gl::FLOAT,
-                0,
-                SquareVertex::FLAT_SIZE as i32 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-                (5 * std::mem::size_of::<f32>()) as *const () as *const _,
-            );
-
-            gl_fns.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
-            gl_fns.EnableVertexAttribArray(tex_attrib as gl::types::GLuint);
-            gl_fns.EnableVertexAttribArray(norm_attrib as gl::types::GLuint);
-
-            // -- TEXTURE
-            tex = image.map(|image| {
-                let mut tex_id = std::mem::zeroed();
-                gl_fns.GenTextures(1, &mut tex_id);
-                gl_fns.BindTexture(gl::TEXTURE_2D, tex_id);
-                gl_fns.TexImage2D(
-                    gl::TEXTURE_2D,
-                    0,
-                    gl::RGB as i32,
-                    image.width() as i32,
-                    image.height() as i32,
-                    0,
-                    gl::RGB,
-                    gl::UNSIGNED_BYTE,
-                    image.to_rgb8().as_raw().as_ptr() as *const _,
-                );
-                gl_fns.GenerateMipmap(gl::TEXTURE_2D);
-                tex_id
-            });
-            // --
-
-            for uniform in init_uniforms {
-                uniform.set(&gl_fns, program);
-            }
-        }
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) =
+            ShaderFiles::build_program(gl_fns.as_ref(), VERTEX_SHADER_PATH, FRAGMENT_SHADER_PATH)?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let drawable = upload_squares(gl_fns.as_ref(), program, &self.instances, self.texture_tile);
+        let tex = self
+            .texture
+            .as_ref()
+            .map(|path| load_texture(gl_fns.as_ref(), path));
+
+        self.shader = Some(
+            Shader::new(gl_fns, program, uniforms, files, model, vec![drawable]).with_tex(tex),
+        );
 
-        let tex = tex.map(|tex| Tex {
-            tex,
-            target: gl::TEXTURE_2D,
-        });
-
-        let drawable = Drawable::Array(Array {
-            vao,
-            vbo,
-            len: self.instances.len(),
-            offset: 4,
-            count: 4,
-        });
-
-        let drawables = vec![drawable];
-
-        self.shader = Some(Shader {
-            program,
-            model_transform: mat3d
-                .model
-                .expect("mat3d as_init should be at least IDENTITY"),
-            tex,
-            drawables,
-            gl_fns,
-        })
+        Ok(())
     }
 
     fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
@@ -296,100 +403,29 @@ impl GlslPass for TexSquare {
             if let Some(model_updated) = mat3d.model {
                 shader.model_transform = model_updated;
             }
-            unsafe { mat3d.set_uniforms(&shader.gl_fns, shader.program) };
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
 
             for uniform in to_set_uniforms {
-                uniform.set(&shader.gl_fns, shader.program);
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
             }
         }
     }
 
+    fn set_own_uniforms(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+        uniforms.set_sampler(gl, "tex", 0);
+        uniforms.set_bool(gl, "uEmissive", self.emissive);
+    }
+
     fn get_shader(&self) -> Option<&Shader> {
         self.shader.as_ref()
     }
-}
-
-impl Entity for TexSquare {}
-
-const VERTEX_SHADER_SOURCE: &[u8] = b"
-#version 410 core
-
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
-
-layout(location = 0) in vec3 position;
-layout(location = 1) in vec2 textureCoord;
-layout(location = 2) in vec3 normal;
-
-out vec2 TexCoord;
-out vec3 fragNorm;
-out vec3 fragPos;
-
-void main() {
-    gl_Position = projection * view * model * vec4(position, 1.0);
-    TexCoord = textureCoord;
-    fragPos = vec3(model * vec4(position, 1.0));
-    fragNorm = mat3(transpose(inverse(model))) * normal;  
-
-}
-\0";
-
-const FRAGMENT_SHADER_SOURCE: &[u8] = b"
-#version 410 core
-
-layout(location = 0) out vec4 FragColor;
 
-
-uniform sampler2D tex;
-
-uniform vec3 uLightPos;
-uniform vec3 uEyePos;
-
-uniform float uFogNear;
-uniform float uFogFar;
-uniform vec3 uFogColor;
-
-uniform bool uEnabledLighting;
-uniform bool uEnabledFog;
-
-uniform float uAmbientStrength;
-uniform float uSpecularStrength;
-
-
-in vec3 fragNorm;
-in vec3 fragPos;
-
-in vec2 TexCoord;
-
-void main() {
-
-    vec4 albedo = texture(tex, TexCoord);
-    vec3 finalRgb = albedo.rgb;
-
-    if (uEnabledLighting) {
-        vec3 norm = normalize(fragNorm);
-
-        vec3 lightDir = normalize(uLightPos - fragPos);
-        float diffuse = max(dot(norm, lightDir), 0.0);
-
-        vec3 viewDir = normalize(uEyePos - fragPos);
-        vec3 reflectDir = reflect(-lightDir, norm);
-        float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
-        float specular = uSpecularStrength * spec;
-
-        finalRgb = finalRgb * (uAmbientStrength + diffuse + specular);
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
     }
+}
 
-    if (uEnabledFog) {
-        float d = length(uEyePos - fragPos);
-        float f = clamp((uFogFar - d) / (uFogFar - uFogNear), 0.0, 1.0);
-
-        finalRgb = mix(uFogColor, finalRgb, f);
-    }
-    
-    
-    FragColor = vec4(finalRgb, 1.0);
+impl Entity for TexSquare {}
 
-}
-\0";
+pub(crate) const VERTEX_SHADER_PATH: &str = "tex_square.vert";
+pub(crate) const FRAGMENT_SHADER_PATH: &str = "tex_square.frag";
@@ -7,23 +7,38 @@ use crate::{
     entities::Entity,
     gl,
     helpers::GlPosition,
-    renderer::shader::{
-        Drawable, GlslPass, IndexedElements, Shader, create_shader, uniform::Uniform,
+    renderer::{
+        backend::GlBackend,
+        culling::Aabb,
+        shader::{
+            Drawable, GlslPass, InstancedArrays, Shader,
+            hot_reload::ShaderFiles,
+            instancing::{Instance, instances_bounds, upload_instances},
+            program::ShaderError,
+            uniform::Uniform,
+        },
     },
 };
 
+/// Teapots sharing one mesh, loaded once and drawn in a single instanced call.
 #[derive(Clone)]
 pub struct UtahTeapot {
-    position: GlPosition,
-    color: Vec3,
+    instances: Vec<Instance>,
     shader: Option<Shader>,
 }
 
 impl UtahTeapot {
     pub fn new(position: GlPosition, color: Vec3) -> Self {
+        Self::batch([(position, color)])
+    }
+
+    /// One teapot per position, each of its own color.
+    pub fn batch(teapots: impl IntoIterator<Item = (GlPosition, Vec3)>) -> Self {
         Self {
-            color,
-            position,
+            instances: teapots
+                .into_iter()
+                .map(|(position, color)| Instance::at(position, color))
+                .collect(),
             shader: None,
         }
     }
@@ -39,10 +54,10 @@ struct Vertex {
 impl GlslPass for UtahTeapot {
     fn init(
         &mut self,
-        gl_fns: Rc<crate::gl::Gles2>,
-        mut mat3d: crate::helpers::Mat3DUpdate,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: crate::helpers::Mat3DUpdate,
         init_uniforms: &[Box<dyn Uniform>],
-    ) {
+    ) -> Result<(), ShaderError> {
         let lo = LoadOptions {
             triangulate: true,
             ..Default::default()
@@ -67,148 +82,101 @@ impl GlslPass for UtahTeapot {
             );
         }
 
-        let program;
-
+        let (program, uniforms, files) =
+            ShaderFiles::build_program(gl_fns.as_ref(), VERTEX_SHADER_PATH, FRAGMENT_SHADER_PATH)?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        // Every model of the file in one mesh
+        let vertex_data: Vec<Vertex> = models
+            .iter()
+            .flat_map(|model| {
+                let mesh = &model.mesh;
+                mesh.indices
+                    .iter()
+                    .zip(&mesh.normal_indices)
+                    .map(|(p_i, n_i)| (*p_i as usize * 3, *n_i as usize * 3))
+                    .map(|(p_i, n_i)| Vertex {
+                        position: Vec3::from_slice(&mesh.positions[p_i..p_i + 3]),
+                        normal: Vec3::from_slice(&mesh.normals[n_i..n_i + 3]),
+                    })
+            })
+            .collect();
+        let mesh_bounds = Aabb::from_points(vertex_data.iter().map(|v| v.position));
+
+        let mut vbo;
+        let mut vao;
+        let instance_vbo;
         unsafe {
-            program = gl_fns.CreateProgram();
-            gl_fns.UseProgram(program);
-
-            let vertex_shader = create_shader(&gl_fns, gl::VERTEX_SHADER, VERTEX_SHADER_SOURCE);
-            let fragment_shader =
-                create_shader(&gl_fns, gl::FRAGMENT_SHADER, FRAGMENT_SHADER_SOURCE);
-
-            gl_fns.AttachShader(program, vertex_shader);
-            gl_fns.AttachShader(program, fragment_shader);
-
-            gl_fns.LinkProgram(program);
-
-            gl_fns.DeleteShader(vertex_shader);
-            gl_fns.DeleteShader(fragment_shader);
-
-            gl_fns.UseProgram(program);
-        }
-
-        let mut drawables = vec![];
-
-        for model in &models {
-            let positions = &model.mesh.positions;
-            let position_i = &model.mesh.indices;
-            let normals = &model.mesh.normals;
-            let normal_i = &model.mesh.normal_indices;
-            let mut ebo;
-            let mut vbo;
-            let mut vao;
-
-            let vertex_data: Vec<Vertex> = position_i
-                .iter()
-                .zip(normal_i)
-                .map(|(p_i, n_i)| (*p_i as usize, *n_i as usize))
-                .map(|(p_i, n_i)| (p_i * 3, n_i * 3))
-                .map(|(p_i, n_i)| Vertex {
-                    position: Vec3::from_slice(&positions[p_i..p_i + 3]),
-                    normal: Vec3::from_slice(&normals[n_i..n_i + 3]),
-                })
-                .collect();
-            let final_indices: Vec<u32> = (0..vertex_data.len() as u32).collect();
-
-            unsafe {
-                vao = std::mem::zeroed();
-                gl_fns.GenVertexArrays(1, &mut vao);
-                gl_fns.BindVertexArray(vao);
-
-                vbo = std::mem::zeroed();
-                gl_fns.GenBuffers(1, &mut vbo);
-                gl_fns.BindBuffer(gl::ARRAY_BUFFER, vbo);
-                gl_fns.BufferData(
-                    gl::ARRAY_BUFFER,
-                    (vertex_data.len() * 6 * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                    vertex_data.as_ptr() as *const _,
-                    gl::STATIC_DRAW,
-                );
-
-                // indices
-                ebo = std::mem::zeroed();
-                gl_fns.GenBuffers(1, &mut ebo);
-                gl_fns.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-                gl_fns.BufferData(
-                    gl::ELEMENT_ARRAY_BUFFER,
-                    (final_indices.len() * std::mem::size_of::<u32>()) as gl::types::GLsizeiptr,
-                    final_indices.as_ptr() as *const _,
-                    gl::STATIC_DRAW,
-                );
-
-                let pos_attrib =
-                    gl_fns.GetAttribLocation(program, c"position".as_ptr() as *const _);
-                assert_ne!(pos_attrib, -1);
-                gl_fns.VertexAttribPointer(
-                    pos_attrib as gl::types::GLuint,
-                    3,
-                    gl::FLOAT,
-                    0,
-                    6 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-                    std::ptr::null(),
-                );
-
-                let norm_attrib = gl_fns.GetAttribLocation(program, c"normal".as_ptr() as *const _);
-                assert_ne!(norm_attrib, -1);
-                gl_fns.VertexAttribPointer(
-                    norm_attrib as gl::types::GLuint,
-                    3,
-                    gl::FLOAT,
-                    0,
-                    6 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-                    (3 * std::mem::size_of::<f32>()) as *const _,
-                );
-
-                gl_fns.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
-                gl_fns.EnableVertexAttribArray(norm_attrib as gl::types::GLuint);
-
-                for unifom in init_uniforms {
-                    unifom.set(&gl_fns, program);
-                }
-            }
+            vao = std::mem::zeroed();
+            gl_fns.GenVertexArrays(1, &mut vao);
+            gl_fns.BindVertexArray(vao);
+
+            vbo = std::mem::zeroed();
+            gl_fns.GenBuffers(1, &mut vbo);
+            gl_fns.BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl_fns.BufferData(
+                gl::ARRAY_BUFFER,
+                (vertex_data.len() * 6 * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
 
-            drawables.push(Drawable::Indexed(IndexedElements {
-                ebo,
-                vbo,
-                vao,
-                index_count: position_i.len(),
-            }));
-        }
+            let pos_attrib = gl_fns.GetAttribLocation(program, c"position".as_ptr() as *const _);
+            assert_ne!(pos_attrib, -1);
+            gl_fns.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint,
+                3,
+                gl::FLOAT,
+                0,
+                6 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+                std::ptr::null(),
+            );
 
-        mat3d.model = Some(
-            glam::Mat4::from_translation(self.position), // * glam::Mat4::from_scale(glam::Vec3::splat(0.3)),
-        );
+            let norm_attrib = gl_fns.GetAttribLocation(program, c"normal".as_ptr() as *const _);
+            assert_ne!(norm_attrib, -1);
+            gl_fns.VertexAttribPointer(
+                norm_attrib as gl::types::GLuint,
+                3,
+                gl::FLOAT,
+                0,
+                6 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+                (3 * std::mem::size_of::<f32>()) as *const _,
+            );
 
-        unsafe {
-            mat3d.set_uniforms(&gl_fns, program);
-        }
+            gl_fns.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
+            gl_fns.EnableVertexAttribArray(norm_attrib as gl::types::GLuint);
 
-        // Color uniform
-        unsafe {
-            let color_loc = gl_fns.GetUniformLocation(program, c"uColor".as_ptr() as *const _);
-            gl_fns.Uniform3f(color_loc, self.color.x, self.color.y, self.color.z);
+            instance_vbo = upload_instances(gl_fns.as_ref(), program, &self.instances);
         }
 
-        self.shader = Some(Shader {
-            program,
-            model_transform: mat3d
-                .model
-                .expect("mat3d as init should at least be IDENTITY"),
-            drawables,
-            gl_fns,
-            tex: Default::default(),
-        })
+        let drawables = vec![Drawable::InstancedArrays(InstancedArrays {
+            vao,
+            vbo,
+            instance_vbo,
+            vertex_count: vertex_data.len(),
+            instance_count: self.instances.len(),
+            bounds: instances_bounds(
+                mesh_bounds,
+                self.instances.iter().map(|instance| instance.model),
+            ),
+        })];
+
+        self.shader = Some(Shader::new(
+            gl_fns, program, uniforms, files, model, drawables,
+        ));
+
+        Ok(())
     }
 
     fn update(&mut self, mat3d: crate::helpers::Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
-        if let Some(shader) = &self.shader {
-            unsafe {
-                mat3d.set_uniforms(&shader.gl_fns, shader.program);
+        if let Some(shader) = &mut self.shader {
+            if let Some(model) = mat3d.model {
+                shader.model_transform = model;
             }
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
 
             for uniform in to_set_uniforms {
-                uniform.set(&shader.gl_fns, shader.program);
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
             }
         }
     }
@@ -216,85 +184,13 @@ impl GlslPass for UtahTeapot {
     fn get_shader(&self) -> Option<&Shader> {
         self.shader.as_ref()
     }
-}
 
-impl Entity for UtahTeapot {}
-
-const VERTEX_SHADER_SOURCE: &[u8] = b"
-#version 410 core
-
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
-
-layout(location = 0) in vec3 position;
-layout(location = 1) in vec3 normal;
-
-out vec3 fragNorm;
-out vec3 fragPos;
-
-void main() {
-    gl_Position = projection * view * model * vec4(position, 1.0);
-    fragPos = vec3(model * vec4(position, 1.0));
-    // Use the upper 3x3 of the model matrix for rotation/scaling
-    fragNorm = mat3(transpose(inverse(model))) * normal;  
-}
-\0";
-
-const FRAGMENT_SHADER_SOURCE: &[u8] = b"
-#version 410 core
-
-layout(location = 0) out vec4 FragColor;
-
-
-uniform vec3 uColor;
-
-uniform vec3 uLightPos;
-uniform vec3 uEyePos;
-
-uniform float uFogNear;
-uniform float uFogFar;
-uniform vec3 uFogColor;
-
-uniform bool uEnabledLighting;
-uniform bool uEnabledFog;
-
-uniform float uAmbientStrength;
-uniform float uSpecularStrength;
-
-
-in vec3 fragNorm;
-in vec3 fragPos;
-
-
-
-void main() {
-    vec4 albedo = vec4(uColor, 1.0);
-    vec3 finalRgb = albedo.rgb;
-
-    if (uEnabledLighting) {
-        vec3 norm = normalize(fragNorm);
-
-        vec3 lightDir = normalize(uLightPos - fragPos);
-        float diffuse = max(dot(norm, lightDir), 0.0);
-
-        vec3 viewDir = normalize(uEyePos - fragPos);
-        vec3 reflectDir = reflect(-lightDir, norm);
-        float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
-        float specular = uSpecularStrength * spec;
-
-        finalRgb = finalRgb * (uAmbientStrength + diffuse + specular);
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
     }
+}
 
-    if (uEnabledFog) {
-        float d = length(uEyePos - fragPos);
-        float f = clamp((uFogFar - d) / (uFogFar - uFogNear), 0.0, 1.0);
-
-        finalRgb = mix(uFogColor, finalRgb, f);
-    }
-    
-    
-    FragColor = vec4(finalRgb, 1.0);
+impl Entity for UtahTeapot {}
 
-}
-\0";
+const VERTEX_SHADER_PATH: &str = "utah_teapot.vert";
+const FRAGMENT_SHADER_PATH: &str = "utah_teapot.frag";
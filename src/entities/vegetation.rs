@@ -0,0 +1,266 @@
+use std::rc::Rc;
+
+use glam::Vec3;
+use tobj::LoadOptions;
+
+use crate::{
+    entities::Entity,
+    gl,
+    helpers::Mat3DUpdate,
+    renderer::{
+        backend::GlBackend,
+        culling::Aabb,
+        shader::{
+            Drawable, GlslPass, InstancedArrays, Shader,
+            hot_reload::ShaderFiles,
+            instancing::{Instance, attribute, instances_bounds, upload_instances},
+            program::ShaderError,
+            uniform::Uniform,
+        },
+    },
+    scatter::{Placement, Plant},
+};
+
+pub const TREE_OBJ: &str = "./assets/tree.obj";
+pub const ROCK_OBJ: &str = "./assets/rock.obj";
+pub const GRASS_OBJ: &str = "./assets/grass.obj";
+
+/// The model of `plant`, its parts colored by their material.
+pub fn obj_path(plant: Plant) -> &'static str {
+    match plant {
+        Plant::Tree => TREE_OBJ,
+        Plant::Rock => ROCK_OBJ,
+        Plant::Grass => GRASS_OBJ,
+    }
+}
+
+/// Copies of an OBJ model scattered on the terrain, drawn in one instanced
+/// call. Each part of the model is colored by the diffuse color of its
+/// material.
+pub struct Vegetation {
+    obj_path: &'static str,
+    placements: Vec<Placement>,
+    shader: Option<Shader>,
+}
+
+impl Vegetation {
+    /// One instance of the model at `obj_path` per placement, e.g. from
+    /// [`scatter`](crate::scatter::scatter).
+    pub fn new(obj_path: &'static str, placements: Vec<Placement>) -> Self {
+        Self {
+            obj_path,
+            placements,
+            shader: None,
+        }
+    }
+
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Vertex {
+    position: Vec3,
+    normal: Vec3,
+    color: Vec3,
+}
+
+/// The triangles of every model of the OBJ file at `path`, one vertex per
+/// corner like [`UtahTeapot`](crate::entities::utah_teapot::UtahTeapot) does.
+/// Parts without a diffuse color are white.
+///
+/// # Panics
+/// If the file can't be loaded.
+fn load_vertices(path: &str) -> Vec<Vertex> {
+    let lo = LoadOptions {
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, materials) = tobj::load_obj(path, &lo).expect("The asset should be available");
+    let materials = materials.unwrap_or_else(|_| Default::default());
+
+    models
+        .iter()
+        .flat_map(|model| {
+            let mesh = &model.mesh;
+            let color = mesh
+                .material_id
+                .and_then(|id| materials.get(id)?.diffuse)
+                .map_or(Vec3::ONE, Vec3::from);
+            mesh.indices
+                .iter()
+                .zip(&mesh.normal_indices)
+                .map(|(p_i, n_i)| (*p_i as usize * 3, *n_i as usize * 3))
+                .map(move |(p_i, n_i)| Vertex {
+                    position: Vec3::from_slice(&mesh.positions[p_i..p_i + 3]),
+                    normal: Vec3::from_slice(&mesh.normals[n_i..n_i + 3]),
+                    color,
+                })
+        })
+        .collect()
+}
+
+impl GlslPass for Vegetation {
+    /// # Panics
+    /// If the model can't be loaded.
+    fn init(
+        &mut self,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) =
+            ShaderFiles::build_program(gl_fns.as_ref(), VERTEX_SHADER_PATH, FRAGMENT_SHADER_PATH)?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let vertex_data = load_vertices(self.obj_path);
+        // The colors come from the materials, the instances don't tint them
+        let instances: Vec<Instance> = self
+            .placements
+            .iter()
+            .map(|placement| Instance {
+                model: placement.transform(),
+                color: Vec3::ONE,
+            })
+            .collect();
+        let mesh_bounds = Aabb::from_points(vertex_data.iter().map(|v| v.position));
+
+        let gl = gl_fns.as_ref();
+        let (mut vao, mut vbo, instance_vbo);
+        unsafe {
+            vao = std::mem::zeroed();
+            gl.GenVertexArrays(1, &mut vao);
+            gl.BindVertexArray(vao);
+
+            vbo = std::mem::zeroed();
+            gl.GenBuffers(1, &mut vbo);
+            gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertex_data.as_slice()) as gl::types::GLsizeiptr,
+                vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            let stride = std::mem::size_of::<Vertex>();
+            for (k, name) in [c"position", c"normal", c"color"].into_iter().enumerate() {
+                let location = attribute(gl, program, name);
+                gl.VertexAttribPointer(
+                    location,
+                    3,
+                    gl::FLOAT,
+                    0,
+                    stride as gl::types::GLsizei,
+                    (k * std::mem::size_of::<Vec3>()) as *const _,
+                );
+                gl.EnableVertexAttribArray(location);
+            }
+
+            instance_vbo = upload_instances(gl, program, &instances);
+        }
+
+        let drawables = vec![Drawable::InstancedArrays(InstancedArrays {
+            vao,
+            vbo,
+            instance_vbo,
+            vertex_count: vertex_data.len(),
+            instance_count: instances.len(),
+            bounds: instances_bounds(mesh_bounds, instances.iter().map(|i| i.model)),
+        })];
+
+        self.shader = Some(Shader::new(
+            gl_fns, program, uniforms, files, model, drawables,
+        ));
+
+        Ok(())
+    }
+
+    fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+        if let Some(shader) = &mut self.shader {
+            if let Some(model) = mat3d.model {
+                shader.model_transform = model;
+            }
+            mat3d.set_uniforms(shader.gl_fns.as_ref(), &shader.uniforms);
+
+            for uniform in to_set_uniforms {
+                uniform.set(shader.gl_fns.as_ref(), &shader.uniforms);
+            }
+        }
+    }
+
+    fn get_shader(&self) -> Option<&Shader> {
+        self.shader.as_ref()
+    }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
+    }
+}
+
+impl Entity for Vegetation {}
+
+const VERTEX_SHADER_PATH: &str = "vegetation.vert";
+const FRAGMENT_SHADER_PATH: &str = "vegetation.frag";
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::{
+        renderer::backend::mock::{GlCall, MockGl},
+        scatter::scatter,
+    };
+
+    #[test]
+    fn models_keep_the_colors_of_their_materials() {
+        let vertices = load_vertices(TREE_OBJ);
+        assert_eq!(vertices.len() % 3, 0);
+        let bark = vertices.iter().filter(|v| v.color.x > v.color.y).count();
+        let leaves = vertices.iter().filter(|v| v.color.y > v.color.x).count();
+        assert!(
+            bark > 0 && leaves > 0,
+            "{bark} bark and {leaves} leaf vertices"
+        );
+        // Standing on the ground, growing up
+        assert!(vertices.iter().all(|v| v.position.y >= 0.0));
+    }
+
+    #[test]
+    fn draws_every_instance_in_one_call() {
+        let gl = Rc::new(MockGl::default());
+        let placements = scatter(
+            &Plant::Grass.rule(1),
+            Vec2::ZERO,
+            Vec2::splat(20.0),
+            1,
+            |_, _| 0.0,
+        );
+        let mut grass = Vegetation::new(GRASS_OBJ, placements);
+        grass.init(gl.clone(), Mat3DUpdate::default(), &[]).unwrap();
+        let divisors = gl.count(|c| matches!(c, GlCall::VertexAttribDivisor { divisor: 1, .. }));
+        assert_eq!(divisors, 5, "4 model matrix columns and the color");
+
+        gl.clear_calls();
+        grass.update_draw(Mat3DUpdate::default(), &[]);
+        let instances = grass.placements().len() as i32;
+        assert!(instances > 0);
+        let draws = gl.count(
+            |c| matches!(c, GlCall::DrawArraysInstanced { instances: n, .. } if *n == instances),
+        );
+        assert_eq!(draws, 1);
+        assert_eq!(gl.count(|c| matches!(c, GlCall::DrawArrays { .. })), 0);
+        // No index buffer, the vertices are drawn in order
+        assert_eq!(
+            gl.count(|c| matches!(
+                c,
+                GlCall::BindBuffer {
+                    target: gl::ELEMENT_ARRAY_BUFFER,
+                    ..
+                }
+            )),
+            0
+        );
+    }
+}
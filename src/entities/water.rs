@@ -0,0 +1,447 @@
+use std::{f32::consts::TAU, rc::Rc, time::Duration};
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+use crate::{
+    entities::{Entity, tex_square::upload_texture},
+    gl,
+    helpers::Mat3DUpdate,
+    renderer::{
+        backend::GlBackend,
+        culling::Aabb,
+        shader::{
+            Drawable, GlslPass, IndexedElements, Shader, hot_reload::ShaderFiles,
+            program::ShaderError, registry::UniformRegistry, uniform::Uniform,
+        },
+        stats::RenderStats,
+        uniform_buffer::FrameGlobals,
+    },
+};
+
+/// Side of the generated normal map, in pixels
+pub const NORMAL_MAP_SIZE: u32 = 128;
+
+/// How far past the water surface the reflection and refraction passes keep
+/// drawing, so the distorted lookups along the shore don't find gaps.
+const CLIP_SLACK: f32 = 0.1;
+
+/// Ripples of the normal map, as whole waves per tile along u and v, height
+/// and phase. Whole waves keep it tileable.
+const WAVES: [(f32, f32, f32, f32); 5] = [
+    (1.0, 2.0, 0.020, 0.0),
+    (3.0, -1.0, 0.012, 1.3),
+    (-2.0, 3.0, 0.010, 2.1),
+    (5.0, 4.0, 0.005, 4.0),
+    (-7.0, 2.0, 0.004, 0.7),
+];
+
+/// The normal of the ripples at `uv`, up along y. Repeats every 1 along u
+/// and v.
+pub fn ripple_normal(uv: Vec2) -> Vec3 {
+    let slope = WAVES
+        .iter()
+        .map(|&(ku, kv, height, phase)| {
+            let k = Vec2::new(ku, kv);
+            k * TAU * height * (TAU * k.dot(uv) + phase).cos()
+        })
+        .sum::<Vec2>();
+    Vec3::new(-slope.x, 1.0, -slope.y).normalize()
+}
+
+/// A tileable normal map of [`ripple_normal`], x and z in red and green, up in
+/// blue, like the `water` shaders read it.
+pub fn normal_map(size: u32) -> image::RgbImage {
+    image::RgbImage::from_fn(size, size, |i, j| {
+        let n = ripple_normal(Vec2::new(i as f32, j as f32) / size as f32);
+        let rgb = (Vec3::new(n.x, n.z, n.y) * 0.5 + 0.5) * 255.0;
+        image::Rgb(rgb.round().to_array().map(|c| c as u8))
+    })
+}
+
+/// Mirrors points about the horizontal plane at `height`.
+pub fn mirror(height: f32) -> Mat4 {
+    Mat4::from_translation(Vec3::Y * height)
+        * Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0))
+        * Mat4::from_translation(Vec3::NEG_Y * height)
+}
+
+/// Replaces the near plane of `projection` by `plane`, in world space, so
+/// only points with `plane.dot((p, 1)) >= 0` are drawn (Lengyel's oblique
+/// near plane). Left as is when the camera of `view` isn't on the clipped
+/// side, where the trick breaks the depth range.
+pub fn clip_projection(projection: Mat4, view: Mat4, plane: Vec4) -> Mat4 {
+    let c = view.inverse().transpose() * plane;
+    if c.w >= 0.0 {
+        return projection;
+    }
+    // Far corner of the frustum opposite the plane, in view space
+    let q = projection.inverse() * Vec4::new(c.x.signum(), c.y.signum(), 1.0, 1.0);
+    let c = c * (2.0 / c.dot(q));
+
+    // Columns of the transpose are the rows of the projection
+    let mut rows = projection.transpose();
+    rows.z_axis = c - rows.w_axis;
+    rows.transpose()
+}
+
+/// What the water at `height` reflects, seen from `frame`: the world mirrored
+/// about the surface, only above it.
+pub fn reflected(frame: &FrameGlobals, height: f32) -> FrameGlobals {
+    let mirror = mirror(height);
+    let view = frame.view * mirror;
+    FrameGlobals {
+        view,
+        projection: clip_projection(
+            frame.projection,
+            view,
+            Vec4::new(0.0, 1.0, 0.0, CLIP_SLACK - height),
+        ),
+        eye_pos: mirror.transform_point3(frame.eye_pos),
+    }
+}
+
+/// What is under the water at `height`, seen from `frame`.
+pub fn refracted(frame: &FrameGlobals, height: f32) -> FrameGlobals {
+    FrameGlobals {
+        projection: clip_projection(
+            frame.projection,
+            frame.view,
+            Vec4::new(0.0, -1.0, 0.0, height + CLIP_SLACK),
+        ),
+        ..*frame
+    }
+}
+
+/// A square lake surface at a fixed height, blending what it reflects and
+/// what is under it with Fresnel, rippled by a scrolling normal map.
+///
+/// The reflection and refraction are rendered by the caller, e.g. with
+/// [`Renderer::render_to`](crate::renderer::Renderer::render_to) from the
+/// [`reflected`] and [`refracted`] frames, and handed over with
+/// [`Water::set_targets`] before drawing.
+pub struct Water {
+    center: Vec2,
+    size: f32,
+    height: f32,
+    color: Vec3,
+    /// Seconds the ripples have scrolled
+    time: f32,
+    /// Reflection and refraction textures
+    targets: Option<(gl::types::GLuint, gl::types::GLuint)>,
+    shader: Option<Shader>,
+}
+
+impl Water {
+    /// A `size` by `size` surface centered on `center`, at `height`.
+    pub fn new(center: Vec2, size: f32, height: f32) -> Self {
+        Self {
+            center,
+            size,
+            height,
+            color: Vec3::new(0.1, 0.3, 0.35),
+            time: 0.0,
+            targets: None,
+            shader: None,
+        }
+    }
+
+    /// The color of deep water, tinting what is seen through it.
+    pub fn with_color(mut self, color: Vec3) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// Scrolls the ripples by `dt`.
+    pub fn advance(&mut self, dt: &Duration) {
+        self.time += dt.as_secs_f32();
+    }
+
+    /// The textures the reflection and the refraction were rendered to.
+    pub fn set_targets(&mut self, reflection: gl::types::GLuint, refraction: gl::types::GLuint) {
+        self.targets = Some((reflection, refraction));
+    }
+}
+
+impl GlslPass for Water {
+    fn init(
+        &mut self,
+        gl_fns: Rc<dyn GlBackend>,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> Result<(), ShaderError> {
+        let (program, uniforms, files) =
+            ShaderFiles::build_program(gl_fns.as_ref(), VERTEX_SHADER_PATH, FRAGMENT_SHADER_PATH)?;
+        let model = self.set_init_uniforms(gl_fns.as_ref(), &uniforms, mat3d, init_uniforms);
+
+        let half = self.size / 2.0;
+        let vertex_data: Vec<Vec3> = [(-1.0, -1.0), (-1.0, 1.0), (1.0, 1.0), (1.0, -1.0)]
+            .into_iter()
+            .map(|(x, z)| {
+                Vec3::new(
+                    self.center.x + x * half,
+                    self.height,
+                    self.center.y + z * half,
+                )
+            })
+            .collect();
+        // Facing up
+        let indices: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+        let gl = gl_fns.as_ref();
+        let (mut vao, mut vbo, mut ebo);
+        unsafe {
+            vao = std::mem::zeroed();
+            gl.GenVertexArrays(1, &mut vao);
+            gl.BindVertexArray(vao);
+
+            vbo = std::mem::zeroed();
+            gl.GenBuffers(1, &mut vbo);
+            gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertex_data.as_slice()) as gl::types::GLsizeiptr,
+                vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            let pos_attrib = gl.GetAttribLocation(program, c"position".as_ptr() as *const _);
+            assert_ne!(pos_attrib, -1);
+            gl.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint,
+                3,
+                gl::FLOAT,
+                0,
+                std::mem::size_of::<Vec3>() as gl::types::GLsizei,
+                std::ptr::null(),
+            );
+            gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
+
+            ebo = std::mem::zeroed();
+            gl.GenBuffers(1, &mut ebo);
+            gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl.BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                std::mem::size_of_val(&indices) as gl::types::GLsizeiptr,
+                indices.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+        }
+        let tex = upload_texture(gl, &normal_map(NORMAL_MAP_SIZE));
+        unsafe {
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        }
+
+        let drawables = vec![Drawable::Indexed(IndexedElements {
+            vao,
+            vbo,
+            ebo,
+            index_count: indices.len(),
+            bounds: Aabb::from_points(vertex_data.iter().copied()),
+        })];
+
+        self.shader = Some(
+            Shader::new(gl_fns, program, uniforms, files, model, drawables).with_tex(Some(tex)),
+        );
+
+        Ok(())
+    }
+
+    fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+        if let Some(shader) = &mut self.shader {
+            if let Some(model) = mat3d.model {
+                shader.model_transform = model;
+            }
+            let gl = shader.gl_fns.as_ref();
+            mat3d.set_uniforms(gl, &shader.uniforms);
+            shader.uniforms.set_f32(gl, "uTime", self.time);
+
+            for uniform in to_set_uniforms {
+                uniform.set(gl, &shader.uniforms);
+            }
+        }
+    }
+
+    /// Binds the reflection, refraction and normal map to texture units 0 to
+    /// 2, draws, and leaves unit 0 active for the other passes.
+    unsafe fn draw(&self) -> RenderStats {
+        let Some(shader) = self.get_shader() else {
+            log::warn!("Tried to render Water before init");
+            return RenderStats::default();
+        };
+        let Some((reflection, refraction)) = self.targets else {
+            log::warn!("Tried to render Water before its reflection and refraction");
+            return RenderStats::default();
+        };
+        let gl = &shader.gl_fns;
+
+        let normal_map = shader.tex.as_ref().map_or(0, |tex| tex.tex);
+        for (unit, tex) in [reflection, refraction, normal_map].into_iter().enumerate() {
+            gl.ActiveTexture(gl::TEXTURE0 + unit as gl::types::GLenum);
+            gl.BindTexture(gl::TEXTURE_2D, tex);
+        }
+
+        let stats = shader
+            .drawables
+            .iter()
+            .map(|drawable| drawable.draw(gl.as_ref()))
+            .sum();
+
+        gl.ActiveTexture(gl::TEXTURE0);
+        stats
+    }
+
+    fn set_own_uniforms(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+        uniforms.set_sampler(gl, "reflectionTex", 0);
+        uniforms.set_sampler(gl, "refractionTex", 1);
+        uniforms.set_sampler(gl, "normalMap", 2);
+        uniforms.set_vec3(gl, "uWaterColor", self.color);
+    }
+
+    fn get_shader(&self) -> Option<&Shader> {
+        self.shader.as_ref()
+    }
+
+    fn get_shader_mut(&mut self) -> Option<&mut Shader> {
+        self.shader.as_mut()
+    }
+}
+
+impl Entity for Water {}
+
+const VERTEX_SHADER_PATH: &str = "water.vert";
+const FRAGMENT_SHADER_PATH: &str = "water.frag";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    /// Looking down at the water at 0 from 3 above it.
+    fn frame() -> FrameGlobals {
+        let eye = Vec3::new(0.0, 3.0, 0.0);
+        FrameGlobals::new(
+            Mat4::look_at_rh(eye, Vec3::new(0.0, 0.0, -5.0), Vec3::Y),
+            Vec2::new(4.0, 3.0),
+            eye,
+        )
+    }
+
+    fn clip(frame: &FrameGlobals, p: Vec3) -> Vec4 {
+        frame.projection * frame.view * p.extend(1.0)
+    }
+
+    fn is_clipped(frame: &FrameGlobals, p: Vec3) -> bool {
+        let c = clip(frame, p);
+        c.z < -c.w
+    }
+
+    #[test]
+    fn mirror_flips_heights_about_the_surface() {
+        let m = mirror(2.0);
+        let p = Vec3::new(1.0, 5.0, -3.0);
+        assert!(
+            m.transform_point3(p)
+                .abs_diff_eq(Vec3::new(1.0, -1.0, -3.0), 1e-6)
+        );
+        assert!(
+            m.transform_point3(m.transform_point3(p))
+                .abs_diff_eq(p, 1e-5)
+        );
+    }
+
+    #[test]
+    fn reflection_shows_what_is_above_where_its_mirror_image_is() {
+        let frame = frame();
+        let reflection = reflected(&frame, 0.0);
+        assert!(
+            reflection
+                .eye_pos
+                .abs_diff_eq(Vec3::new(0.0, -3.0, 0.0), 1e-6)
+        );
+
+        let above = Vec3::new(1.0, 1.0, -6.0);
+        assert!(!is_clipped(&reflection, above));
+        let seen = clip(&reflection, above);
+        let mirrored = clip(&frame, Vec3::new(1.0, -1.0, -6.0));
+        assert!(
+            (seen.truncate() / seen.w)
+                .truncate()
+                .abs_diff_eq((mirrored.truncate() / mirrored.w).truncate(), 1e-5)
+        );
+
+        assert!(!is_clipped(&frame, Vec3::new(1.0, 1.0, -6.0)));
+        assert!(
+            is_clipped(&reflection, Vec3::new(1.0, -1.0, -6.0)),
+            "under water"
+        );
+    }
+
+    #[test]
+    fn refraction_shows_only_what_is_under_water() {
+        let frame = frame();
+        let refraction = refracted(&frame, 0.0);
+        assert_eq!(refraction.view, frame.view);
+        assert!(!is_clipped(&refraction, Vec3::new(1.0, -1.0, -6.0)));
+        assert!(is_clipped(&refraction, Vec3::new(1.0, 1.0, -6.0)));
+
+        // From under water the projection can't clip, everything is drawn
+        let mut below = frame;
+        below.view = Mat4::look_at_rh(Vec3::NEG_Y, Vec3::new(0.0, -1.0, -5.0), Vec3::Y);
+        assert_eq!(refracted(&below, 0.0).projection, below.projection);
+    }
+
+    #[test]
+    fn normal_map_tiles_and_faces_up() {
+        for uv in [Vec2::ZERO, Vec2::new(0.3, 0.7), Vec2::new(0.9, 0.1)] {
+            let n = ripple_normal(uv);
+            assert!(n.y > 0.8, "{n} at {uv}");
+            assert!(ripple_normal(uv + Vec2::X).abs_diff_eq(n, 1e-4));
+            assert!(ripple_normal(uv + Vec2::Y).abs_diff_eq(n, 1e-4));
+        }
+
+        let map = normal_map(16);
+        assert!(map.pixels().all(|p| p.0[2] > 200), "blue is up");
+    }
+
+    #[test]
+    fn draws_with_both_targets_and_the_normal_map() {
+        let gl = Rc::new(MockGl::default());
+        let mut water = Water::new(Vec2::ZERO, 10.0, 1.0);
+        water.init(gl.clone(), Mat3DUpdate::default(), &[]).unwrap();
+
+        gl.clear_calls();
+        water.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!(
+            gl.count(|c| matches!(c, GlCall::DrawElements { .. })),
+            0,
+            "no targets yet"
+        );
+
+        water.set_targets(7, 8);
+        water.advance(&Duration::from_millis(500));
+        water.update_draw(Mat3DUpdate::default(), &[]);
+        let calls = gl.calls();
+        for texture in [7, 8] {
+            assert!(calls.contains(&GlCall::BindTexture {
+                target: gl::TEXTURE_2D,
+                texture
+            }));
+        }
+        let units: Vec<_> = calls
+            .iter()
+            .filter_map(|c| match c {
+                GlCall::ActiveTexture(unit) => Some(unit - gl::TEXTURE0),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(units, [0, 1, 2, 0], "unit 0 is active again after");
+        assert_eq!(
+            gl.count(|c| matches!(c, GlCall::DrawElements { count: 6, .. })),
+            1
+        );
+    }
+}
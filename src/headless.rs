@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use glutin::api::egl::{context::PossiblyCurrentContext, device::Device, display::Display};
+use glutin::config::{ConfigSurfaceTypes, ConfigTemplateBuilder};
+use glutin::context::{ContextApi, ContextAttributesBuilder, GlProfile, Version};
+use glutin::prelude::*;
+
+use crate::gl;
+use crate::renderer::Renderer;
+use crate::renderer::framebuffer::Framebuffer;
+use crate::scene::{CLEAR_COLOR, FloorKind, Scene};
+
+/// Simulated time between two headless frames.
+const FRAME_DT: Duration = Duration::from_millis(16);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadlessOptions {
+    pub dimensions: glam::USizeVec2,
+    pub frames: usize,
+    /// Directory the `frame_XXXX.png` files are written to; created if missing.
+    pub output_dir: PathBuf,
+    pub floor: FloorKind,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            dimensions: glam::USizeVec2::new(800, 600),
+            frames: 1,
+            output_dir: "./headless_output".into(),
+            floor: FloorKind::default(),
+        }
+    }
+}
+
+/// A GL context with no window or surface behind it, current on this thread.
+///
+/// NOTE: The EGL display is never terminated: EGL hands out the same display for
+/// a device to every caller, so terminating it would also tear down headless
+/// contexts living on other threads (e.g. parallel tests).
+pub struct HeadlessContext {
+    pub gl_fns: Rc<gl::Gl>,
+    _context: PossiblyCurrentContext,
+}
+
+impl HeadlessContext {
+    /// Creates a surfaceless EGL context on the first EGL device that accepts it
+    /// (e.g. Mesa's llvmpipe software device on CI machines).
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let mut last_err: Box<dyn Error> = "No EGL device available".into();
+
+        for device in Device::query_devices()? {
+            match Self::with_device(&device) {
+                Ok(context) => return Ok(context),
+                Err(err) => {
+                    log::warn!("Skipping EGL device {:?}: {err}", device.name());
+                    last_err = err;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    fn with_device(device: &Device) -> Result<Self, Box<dyn Error>> {
+        let display = unsafe { Display::with_device(device, None)? };
+
+        // We never create a surface, so any surface type will do.
+        let template = ConfigTemplateBuilder::new()
+            .with_alpha_size(8)
+            .with_surface_type(ConfigSurfaceTypes::empty())
+            .build();
+        let config = unsafe { display.find_configs(template)? }
+            .next()
+            .ok_or("No EGL config found")?;
+
+        // The entity shaders are `#version 410 core`.
+        let context_attributes = ContextAttributesBuilder::new()
+            .with_profile(GlProfile::Core)
+            .with_context_api(ContextApi::OpenGl(Some(Version::new(4, 1))))
+            .build(None);
+        let fallback_context_attributes = ContextAttributesBuilder::new().build(None);
+
+        let context = unsafe {
+            display
+                .create_context(&config, &context_attributes)
+                .or_else(|_| display.create_context(&config, &fallback_context_attributes))?
+        };
+        let context = context.make_current_surfaceless()?;
+
+        let gl_fns = gl::Gl::load_with(|symbol| {
+            let symbol = CString::new(symbol).unwrap();
+            display.get_proc_address(symbol.as_c_str()).cast()
+        });
+
+        Ok(Self {
+            gl_fns: Rc::new(gl_fns),
+            _context: context,
+        })
+    }
+}
+
+/// Renders `options.frames` frames of the app scene into an offscreen framebuffer
+/// and writes each one as a PNG. Returns the written paths in frame order.
+pub fn run(options: &HeadlessOptions) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let context = HeadlessContext::new()?;
+    let gl_fns = context.gl_fns.clone();
+
+    std::fs::create_dir_all(&options.output_dir)?;
+
+    let framebuffer = Framebuffer::new(gl_fns.clone(), options.dimensions)?;
+    framebuffer.bind();
+
+    let mut renderer = Renderer::new(gl_fns.clone(), options.dimensions, CLEAR_COLOR);
+    renderer.resize(options.dimensions.x as i32, options.dimensions.y as i32);
+
+    let mut scene = Scene::with_floor(gl_fns, options.floor)?;
+    // Frames show the whole terrain, however fast the workers are
+    scene.finish_streaming();
+
+    let mut written = Vec::with_capacity(options.frames);
+    for frame in 0..options.frames {
+        framebuffer.bind();
+        scene.render(&mut renderer, &FRAME_DT);
+
+        let path = options.output_dir.join(format!("frame_{frame:04}.png"));
+        framebuffer.read_rgba8().save(&path)?;
+        log::info!("Wrote {path:?}, {}", renderer.take_stats());
+        written.push(path);
+    }
+
+    // GL objects must be freed while the context is still alive.
+    drop(scene);
+    drop(framebuffer);
+    drop(context);
+
+    Ok(written)
+}
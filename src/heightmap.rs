@@ -0,0 +1,259 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::Arc,
+};
+
+use glam::{IVec2, UVec2};
+use image::{DynamicImage, ImageBuffer, ImageResult, Luma};
+
+/// A 16-bit grayscale image, the format heightmaps are exported to.
+pub type HeightImage = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+/// Surface heights of a rectangle of columns, in voxels, loaded from an image
+/// or captured from a generator. Column `(x, z)` is pixel `(x, z)` of the
+/// images.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    size: UVec2,
+    heights: Arc<[f32]>,
+}
+
+impl Heightmap {
+    /// # Panics
+    /// If there isn't one height per column.
+    pub fn new(size: UVec2, heights: Vec<f32>) -> Self {
+        assert_eq!(
+            heights.len(),
+            size.element_product() as usize,
+            "one height per column of {size}"
+        );
+        Self {
+            size,
+            heights: heights.into(),
+        }
+    }
+
+    /// The columns of the `size` rectangle starting at column `min`, as
+    /// `height_at` generates them, e.g.
+    /// [`terrain_height`](crate::terrain_builder::terrain_height).
+    pub fn capture(min: IVec2, size: UVec2, height_at: impl Fn(i32, i32) -> usize) -> Self {
+        let heights = (0..size.y as i32)
+            .flat_map(|z| (0..size.x as i32).map(move |x| (x, z)))
+            .map(|(x, z)| height_at(min.x + x, min.y + z) as f32)
+            .collect();
+        Self::new(size, heights)
+    }
+
+    /// Brightness scaled from black at 0 to white at `max_height`. 8 and
+    /// 16-bit images keep their precision, colors are turned to gray.
+    pub fn from_image(image: &DynamicImage, max_height: usize) -> Self {
+        let image = image.to_luma16();
+        let scale = max_height as f32 / u16::MAX as f32;
+        let heights = image.pixels().map(|Luma([v])| *v as f32 * scale).collect();
+        Self::new(UVec2::new(image.width(), image.height()), heights)
+    }
+
+    /// Loads a grayscale image, PNG or any format `image` reads, see
+    /// [`Heightmap::from_image`].
+    pub fn load(path: impl AsRef<Path>, max_height: usize) -> ImageResult<Self> {
+        Ok(Self::from_image(&image::open(path)?, max_height))
+    }
+
+    /// Reads `size` little endian 16-bit samples, the raw heightmaps of terrain
+    /// tools, scaled like [`Heightmap::from_image`].
+    pub fn from_raw_u16(bytes: &[u8], size: UVec2, max_height: usize) -> io::Result<Self> {
+        let samples = samples::<2>(bytes, size)?;
+        let scale = max_height as f32 / u16::MAX as f32;
+        let heights = samples
+            .map(|sample| u16::from_le_bytes(sample) as f32 * scale)
+            .collect();
+        Ok(Self::new(size, heights))
+    }
+
+    /// Reads heights written by [`Heightmap::to_raw_f32`].
+    pub fn from_raw_f32(bytes: &[u8], size: UVec2) -> io::Result<Self> {
+        let heights = samples::<4>(bytes, size)?.map(f32::from_le_bytes).collect();
+        Ok(Self::new(size, heights))
+    }
+
+    pub fn load_raw_f32(path: impl AsRef<Path>, size: UVec2) -> io::Result<Self> {
+        Self::from_raw_f32(&fs::read(path)?, size)
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    /// Height of column `(x, z)`, that of the nearest edge column outside the
+    /// map.
+    pub fn height(&self, x: i64, z: i64) -> f32 {
+        let max = self.size.as_i64vec2() - 1;
+        let (x, z) = (x.clamp(0, max.x.max(0)), z.clamp(0, max.y.max(0)));
+        self.heights
+            .get((x + z * self.size.x as i64) as usize)
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// The heights rounded to whole voxels, in place of
+    /// [`terrain_builder`](crate::terrain_builder::terrain_builder).
+    pub fn height_fn(&self) -> impl Fn(usize, usize) -> usize + use<> {
+        let map = self.clone();
+        move |x, z| map.height(x as i64, z as i64).round().max(0.0) as usize
+    }
+
+    /// Like [`Heightmap::height_fn`], for any column like
+    /// [`terrain_height`](crate::terrain_builder::terrain_height), so the map
+    /// can back a [`Generator`](crate::voxel::world::Generator).
+    pub fn height_at_fn(&self) -> impl Fn(i32, i32) -> usize + Send + Sync + use<> {
+        let map = self.clone();
+        move |x, z| map.height(x as i64, z as i64).round().max(0.0) as usize
+    }
+
+    /// The heights as brightness, white at `max_height`, the inverse of
+    /// [`Heightmap::from_image`]. Heights past `max_height` are white.
+    pub fn to_image(&self, max_height: usize) -> HeightImage {
+        let scale = u16::MAX as f32 / max_height.max(1) as f32;
+        let samples = self
+            .heights
+            .iter()
+            .map(|h| (h * scale).round().clamp(0.0, u16::MAX as f32) as u16)
+            .collect();
+        HeightImage::from_raw(self.size.x, self.size.y, samples).expect("one sample per column")
+    }
+
+    /// Writes a 16-bit grayscale image, PNG for a `.png` path.
+    pub fn save_image(&self, path: impl AsRef<Path>, max_height: usize) -> ImageResult<()> {
+        self.to_image(max_height).save(path)
+    }
+
+    /// The heights as little endian `f32`, row by row, without a header.
+    pub fn to_raw_f32(&self) -> Vec<u8> {
+        self.heights.iter().flat_map(|h| h.to_le_bytes()).collect()
+    }
+
+    pub fn save_raw_f32(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_raw_f32())
+    }
+}
+
+/// The `N` byte samples of a raw map of `size`.
+fn samples<const N: usize>(
+    bytes: &[u8],
+    size: UVec2,
+) -> io::Result<impl Iterator<Item = [u8; N]> + '_> {
+    let expected = size.element_product() as usize * N;
+    if bytes.len() != expected {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "a {size} raw heightmap is {expected} bytes, not {}",
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(N)
+        .map(|sample| sample.try_into().expect("chunks of N bytes")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{GrayImage, ImageFormat};
+
+    use super::*;
+    use crate::terrain_builder::terrain_height;
+
+    fn generated() -> Heightmap {
+        Heightmap::capture(
+            IVec2::new(-40, 17),
+            UVec2::new(33, 20),
+            terrain_height(5, 30),
+        )
+    }
+
+    #[test]
+    fn captures_the_region_of_the_generator() {
+        let height_at = terrain_height(5, 30);
+        let map = generated();
+        let height_fn = map.height_fn();
+        assert_eq!(height_fn(0, 0), height_at(-40, 17));
+        assert_eq!(height_fn(32, 19), height_at(-8, 36));
+        // Clamped past the edges
+        assert_eq!(height_fn(100, 0), height_at(-8, 17));
+        assert_eq!(map.height_at_fn()(-5, 3), height_at(-40, 20));
+    }
+
+    #[test]
+    fn png_round_trips_whole_heights() {
+        let map = generated();
+        let mut png = vec![];
+        map.to_image(30)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let loaded = Heightmap::from_image(&image::load_from_memory(&png).unwrap(), 30);
+        assert_eq!(loaded.size(), map.size());
+        for x in 0..33 {
+            for z in 0..20 {
+                assert_eq!(
+                    loaded.height_fn()(x, z),
+                    map.height_fn()(x, z),
+                    "({x}, {z})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn raw_f32_round_trips_exactly() {
+        let map = Heightmap::new(UVec2::new(3, 2), vec![0.0, 1.5, 2.25, -1.0, 1e4, 7.125]);
+        let raw = map.to_raw_f32();
+        assert_eq!(Heightmap::from_raw_f32(&raw, map.size()).unwrap(), map);
+
+        let short = Heightmap::from_raw_f32(&raw[1..], map.size());
+        assert_eq!(short.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn maps_are_saved_and_loaded_from_files() {
+        let map = generated();
+        // Unique to the process, concurrent test runs don't share the files
+        let path = |extension: &str| {
+            std::env::temp_dir().join(format!(
+                "glutin-hello-world-heightmap-{}.{extension}",
+                std::process::id()
+            ))
+        };
+        let (png, raw) = (path("png"), path("f32"));
+
+        map.save_image(&png, 30).unwrap();
+        let from_png = Heightmap::load(&png, 30);
+        map.save_raw_f32(&raw).unwrap();
+        let from_raw = Heightmap::load_raw_f32(&raw, map.size());
+        std::fs::remove_file(&png).unwrap();
+        std::fs::remove_file(&raw).unwrap();
+
+        assert_eq!(from_png.unwrap().size(), map.size());
+        assert_eq!(from_raw.unwrap(), map);
+    }
+
+    #[test]
+    fn reads_8_and_16_bit_maps() {
+        let gray = GrayImage::from_raw(2, 1, vec![0, 255]).unwrap();
+        let map = Heightmap::from_image(&DynamicImage::ImageLuma8(gray), 20);
+        assert_eq!((map.height(0, 0), map.height(1, 0)), (0.0, 20.0));
+
+        let bytes: Vec<u8> = [0u16, 32768, u16::MAX]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let map = Heightmap::from_raw_u16(&bytes, UVec2::new(3, 1), 100).unwrap();
+        assert_eq!(map.height_fn()(1, 0), 50);
+        assert_eq!(map.height_fn()(2, 0), 100);
+    }
+}
@@ -3,16 +3,24 @@
 pub mod app;
 pub mod camera;
 pub mod entities;
+pub mod headless;
+pub mod heightmap;
 pub mod helpers;
 pub mod renderer;
+pub mod scatter;
+pub mod scene;
 pub mod terrain_builder;
+pub mod voxel;
 
 use glutin::config::ConfigTemplateBuilder;
 use glutin_winit::DisplayBuilder;
 use std::error::Error;
+use std::path::PathBuf;
 use winit::window::{Window, WindowAttributes};
 
 use crate::app::App;
+use crate::headless::HeadlessOptions;
+use crate::scene::FloorKind;
 
 pub mod gl {
     #![allow(clippy::all)]
@@ -21,7 +29,11 @@ pub mod gl {
     pub use Gles2 as Gl;
 }
 
-pub fn main(event_loop: winit::event_loop::EventLoop<()>) -> Result<(), Box<dyn Error>> {
+/// Opens the app window, the scene standing on `floor`.
+pub fn main(
+    event_loop: winit::event_loop::EventLoop<()>,
+    floor: FloorKind,
+) -> Result<(), Box<dyn Error>> {
     // The template will match only the configurations supporting rendering
     // to windows.
     //
@@ -36,12 +48,18 @@ pub fn main(event_loop: winit::event_loop::EventLoop<()>) -> Result<(), Box<dyn
 
     let display_builder = DisplayBuilder::new().with_window_attributes(Some(window_attributes()));
 
-    let mut app = App::new(template, display_builder);
+    let mut app = App::new(template, display_builder).with_floor(floor);
     event_loop.run_app(&mut app)?;
 
     app.exit_state
 }
 
+/// Renders the same scene as [`main`] without a window, writing every frame to
+/// `options.output_dir` as PNG. Returns the written paths in frame order.
+pub fn headless_main(options: &HeadlessOptions) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    headless::run(options)
+}
+
 fn window_attributes() -> WindowAttributes {
     Window::default_attributes()
         .with_transparent(true)
@@ -62,7 +80,29 @@ mod tests {
     use winit::event_loop::EventLoop;
 
     #[test]
+    #[ignore = "opens a real window and runs until it is closed"]
     fn test_run_main() {
-        main(EventLoop::new().unwrap()).unwrap();
+        main(EventLoop::new().unwrap(), FloorKind::default()).unwrap();
+    }
+
+    #[test]
+    fn test_run_headless_main() {
+        let options = HeadlessOptions {
+            dimensions: glam::USizeVec2::new(160, 120),
+            frames: 2,
+            output_dir: std::env::temp_dir().join("glutin-hello-world-headless"),
+            ..Default::default()
+        };
+
+        let written = headless_main(&options).unwrap();
+        assert_eq!(written.len(), options.frames);
+
+        for path in written {
+            let frame = image::open(&path).unwrap().to_rgba8();
+            assert_eq!((frame.width(), frame.height()), (160, 120));
+            // Something other than the clear color got drawn
+            let first = frame.get_pixel(0, 0);
+            assert!(frame.pixels().any(|p| p != first));
+        }
     }
 }
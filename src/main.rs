@@ -1,6 +1,127 @@
+use glutin_hello_world::{headless::HeadlessOptions, scene::FloorKind};
 use winit::event_loop::EventLoop;
 
+const USAGE: &str = "\
+Usage: helloWorld [--heightfield | --lod-heightfield] [--headless [frames [output_dir]]]
+
+  --heightfield      a smooth floor instead of voxels
+  --lod-heightfield  a smooth floor reaching further, coarser away from the camera
+  --headless         render `frames` (1) offscreen to PNG files in `output_dir`
+                     (./headless_output) instead of opening a window";
+
+/// What the command line asks for, flags in any order.
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    floor: FloorKind,
+    /// `None` to open a window
+    headless: Option<HeadlessOptions>,
+    help: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut floor = None;
+    let mut positionals = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--heightfield" | "--lod-heightfield" => {
+                if floor.is_some() {
+                    return Err("only one floor can be chosen".into());
+                }
+                floor = Some(match arg.as_str() {
+                    "--heightfield" => FloorKind::Heightfield,
+                    _ => FloorKind::LodHeightfield,
+                });
+            }
+            "--headless" => parsed.headless = Some(HeadlessOptions::default()),
+            "-h" | "--help" => parsed.help = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ => positionals.push(arg),
+        }
+    }
+    parsed.floor = floor.unwrap_or_default();
+
+    let mut positionals = positionals.into_iter();
+    // Only headless runs take positional arguments
+    if let Some(options) = &mut parsed.headless {
+        options.floor = parsed.floor;
+        if let Some(frames) = positionals.next() {
+            options.frames = frames
+                .parse()
+                .map_err(|_| format!("frames should be a number, got {frames}"))?;
+        }
+        if let Some(output_dir) = positionals.next() {
+            options.output_dir = output_dir.into();
+        }
+    }
+    if let Some(extra) = positionals.next() {
+        return Err(format!("unexpected argument {extra}"));
+    }
+    Ok(parsed)
+}
+
 fn main() {
     env_logger::init();
-    glutin_hello_world::main(EventLoop::new().unwrap()).unwrap();
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{USAGE}");
+        return;
+    }
+
+    if let Some(options) = args.headless {
+        glutin_hello_world::headless_main(&options).unwrap();
+        return;
+    }
+
+    glutin_hello_world::main(EventLoop::new().unwrap(), args.floor).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn flags_are_read_in_any_order() {
+        let expected = HeadlessOptions {
+            floor: FloorKind::Heightfield,
+            frames: 3,
+            output_dir: "out".into(),
+            ..Default::default()
+        };
+        for args in [
+            "--heightfield --headless 3 out",
+            "--headless 3 out --heightfield",
+            "--headless --heightfield 3 out",
+        ] {
+            let parsed = parse(args).unwrap();
+            assert_eq!(parsed.floor, FloorKind::Heightfield, "{args}");
+            assert_eq!(parsed.headless.as_ref(), Some(&expected), "{args}");
+        }
+        assert_eq!(parse("").unwrap(), Args::default());
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        for args in [
+            "--foo",
+            "--Heightfield",
+            "--heightfield --lod-heightfield",
+            "--headless many",
+            "--headless 1 out more",
+            "stray",
+        ] {
+            assert!(parse(args).is_err(), "{args}");
+        }
+    }
 }
@@ -0,0 +1,171 @@
+use glam::{UVec2, Vec2};
+use image::{RgbImage, imageops::FilterType};
+
+/// A sub-rectangle of a texture in UV space, where squares sample their
+/// texture. UVs past 1 repeat inside it, see `tex_square.frag`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: Vec2,
+    pub size: Vec2,
+}
+
+impl UvRect {
+    /// The whole texture.
+    pub const FULL: UvRect = UvRect {
+        min: Vec2::ZERO,
+        size: Vec2::ONE,
+    };
+
+    /// Where the UV `uv` of a square lands in the texture, repeating every 1.
+    pub fn map(&self, uv: Vec2) -> Vec2 {
+        self.min + uv.fract() * self.size
+    }
+}
+
+impl Default for UvRect {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// Where the tiles of an atlas go: a grid of square tiles, each surrounded by
+/// `padding` pixels repeating it so filtering and the first mipmaps don't
+/// bleed the neighbouring tiles in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasLayout {
+    pub tiles: u32,
+    /// Side of a tile, in pixels
+    pub tile_size: u32,
+    pub padding: u32,
+}
+
+impl AtlasLayout {
+    pub fn new(tiles: u32, tile_size: u32) -> Self {
+        Self {
+            tiles,
+            tile_size,
+            padding: tile_size / 8,
+        }
+    }
+
+    /// Tiles per row, as many as rows or one more.
+    pub fn columns(&self) -> u32 {
+        (self.tiles.max(1) as f64).sqrt().ceil() as u32
+    }
+
+    /// Size of the atlas in pixels.
+    pub fn size(&self) -> UVec2 {
+        let columns = self.columns();
+        let rows = self.tiles.max(1).div_ceil(columns);
+        UVec2::new(columns, rows) * self.cell()
+    }
+
+    /// The UVs of tile `tile`, its padding excluded.
+    pub fn rect(&self, tile: u32) -> UvRect {
+        let size = self.size().as_vec2();
+        UvRect {
+            min: self.origin(tile).as_vec2() / size,
+            size: Vec2::splat(self.tile_size as f32) / size,
+        }
+    }
+
+    /// Packs `tiles`, resized to the tile size, into an atlas image.
+    pub fn pack(&self, tiles: &[RgbImage]) -> RgbImage {
+        assert_eq!(tiles.len(), self.tiles as usize, "one image per tile");
+        let size = self.size();
+        let mut atlas = RgbImage::new(size.x, size.y);
+        let side = self.tile_size as i64;
+        let padding = self.padding as i64;
+
+        for (i, tile) in tiles.iter().enumerate() {
+            let tile =
+                image::imageops::resize(tile, self.tile_size, self.tile_size, FilterType::Triangle);
+            let origin = self.origin(i as u32).as_i64vec2();
+            for y in -padding..side + padding {
+                for x in -padding..side + padding {
+                    let pixel =
+                        tile.get_pixel(x.rem_euclid(side) as u32, y.rem_euclid(side) as u32);
+                    atlas.put_pixel((origin.x + x) as u32, (origin.y + y) as u32, *pixel);
+                }
+            }
+        }
+        atlas
+    }
+
+    fn cell(&self) -> u32 {
+        self.tile_size + 2 * self.padding
+    }
+
+    /// Top left pixel of tile `tile`, inside its padding.
+    fn origin(&self, tile: u32) -> UVec2 {
+        let columns = self.columns();
+        UVec2::new(tile % columns, tile / columns) * self.cell() + self.padding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    fn solid(color: [u8; 3]) -> RgbImage {
+        RgbImage::from_pixel(4, 4, Rgb(color))
+    }
+
+    #[test]
+    fn rects_are_disjoint_and_inside_the_atlas() {
+        let layout = AtlasLayout::new(7, 16);
+        assert_eq!(layout.size(), UVec2::new(3, 3) * 20);
+
+        let rects: Vec<_> = (0..7).map(|tile| layout.rect(tile)).collect();
+        for (i, a) in rects.iter().enumerate() {
+            let max = a.min + a.size;
+            assert!(
+                a.min.min_element() > 0.0 && max.max_element() < 1.0,
+                "{a:?}"
+            );
+            for b in &rects[i + 1..] {
+                let overlap = (a.min.cmplt(b.min + b.size) & b.min.cmplt(max)).all();
+                assert!(!overlap, "{a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_land_in_their_rect() {
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        let layout = AtlasLayout::new(3, 8);
+        let atlas = layout.pack(&colors.map(solid));
+        let size = layout.size().as_vec2();
+
+        for (tile, color) in colors.iter().enumerate() {
+            let rect = layout.rect(tile as u32);
+            // The corners, the center, and padding just outside the tile
+            for uv in [
+                Vec2::ZERO,
+                Vec2::splat(0.5),
+                Vec2::splat(0.99),
+                Vec2::splat(-0.1),
+                Vec2::splat(1.1),
+            ] {
+                let pixel = (rect.min + uv * rect.size) * size;
+                assert_eq!(
+                    atlas.get_pixel(pixel.x as u32, pixel.y as u32).0,
+                    *color,
+                    "{uv}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn repeating_uvs_stay_in_the_rect() {
+        let rect = AtlasLayout::new(4, 16).rect(3);
+        for uv in [Vec2::new(0.25, 0.5), Vec2::new(3.25, 7.5)] {
+            let mapped = rect.map(uv);
+            assert_eq!(mapped, rect.min + Vec2::new(0.25, 0.5) * rect.size);
+        }
+        assert_eq!(UvRect::FULL.map(Vec2::new(2.5, 0.75)), Vec2::new(0.5, 0.75));
+    }
+}
@@ -0,0 +1,754 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::{CStr, c_void},
+};
+
+use crate::{
+    gl::{
+        self,
+        types::{GLboolean, GLchar, GLenum, GLfloat, GLint, GLintptr, GLsizei, GLsizeiptr, GLuint},
+    },
+    renderer::backend::GlBackend,
+};
+
+/// One recorded call to [`MockGl`]. Pointer arguments are recorded by what they
+/// point to (names, generated ids, uniform values), raw data only by its size.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GlCall {
+    CreateProgram(GLuint),
+    UseProgram(GLuint),
+    DeleteProgram(GLuint),
+    AttachShader {
+        program: GLuint,
+        shader: GLuint,
+    },
+    LinkProgram(GLuint),
+    GetProgramiv {
+        program: GLuint,
+        pname: GLenum,
+    },
+    GetProgramInfoLog(GLuint),
+    CreateShader {
+        kind: GLenum,
+        shader: GLuint,
+    },
+    ShaderSource {
+        shader: GLuint,
+        source: String,
+    },
+    CompileShader(GLuint),
+    GetShaderiv {
+        shader: GLuint,
+        pname: GLenum,
+    },
+    GetShaderInfoLog(GLuint),
+    DeleteShader(GLuint),
+    GenVertexArrays(Vec<GLuint>),
+    BindVertexArray(GLuint),
+    DeleteVertexArrays(Vec<GLuint>),
+    GenBuffers(Vec<GLuint>),
+    BindBuffer {
+        target: GLenum,
+        buffer: GLuint,
+    },
+    BufferData {
+        target: GLenum,
+        size: GLsizeiptr,
+    },
+    BufferSubData {
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    },
+    DeleteBuffers(Vec<GLuint>),
+    BindBufferBase {
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+    },
+    GetAttribLocation {
+        program: GLuint,
+        name: String,
+    },
+    VertexAttribPointer {
+        index: GLuint,
+        size: GLint,
+        stride: GLsizei,
+        offset: usize,
+    },
+    EnableVertexAttribArray(GLuint),
+    VertexAttribDivisor {
+        index: GLuint,
+        divisor: GLuint,
+    },
+    GetActiveUniform {
+        program: GLuint,
+        index: GLuint,
+    },
+    GetUniformLocation {
+        program: GLuint,
+        name: String,
+    },
+    GetUniformBlockIndex {
+        program: GLuint,
+        name: String,
+    },
+    UniformBlockBinding {
+        program: GLuint,
+        block_index: GLuint,
+        binding: GLuint,
+    },
+    Uniform1i {
+        location: GLint,
+        value: GLint,
+    },
+    Uniform1f {
+        location: GLint,
+        value: GLfloat,
+    },
+    Uniform3f {
+        location: GLint,
+        value: [GLfloat; 3],
+    },
+    Uniform1ui {
+        location: GLint,
+        value: GLuint,
+    },
+    UniformMatrix4fv {
+        location: GLint,
+        value: Vec<GLfloat>,
+    },
+    GenTextures(Vec<GLuint>),
+    BindTexture {
+        target: GLenum,
+        texture: GLuint,
+    },
+    ActiveTexture(GLenum),
+    TexImage2D {
+        target: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    },
+    GenerateMipmap(GLenum),
+    TexParameteri {
+        target: GLenum,
+        pname: GLenum,
+        param: GLint,
+    },
+    DeleteTextures(Vec<GLuint>),
+    GenFramebuffers(Vec<GLuint>),
+    BindFramebuffer {
+        target: GLenum,
+        framebuffer: GLuint,
+    },
+    FramebufferTexture2D {
+        attachment: GLenum,
+        texture: GLuint,
+    },
+    FramebufferRenderbuffer {
+        attachment: GLenum,
+        renderbuffer: GLuint,
+    },
+    DeleteFramebuffers(Vec<GLuint>),
+    GenRenderbuffers(Vec<GLuint>),
+    BindRenderbuffer(GLuint),
+    RenderbufferStorage {
+        internalformat: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    },
+    DeleteRenderbuffers(Vec<GLuint>),
+    ReadPixels {
+        width: GLsizei,
+        height: GLsizei,
+    },
+    DrawArrays {
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+    },
+    DrawElements {
+        mode: GLenum,
+        count: GLsizei,
+    },
+    DrawArraysInstanced {
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instances: GLsizei,
+    },
+}
+
+/// A [`GlBackend`] that needs no driver: it records every call and hands out
+/// fake, never reused object names. Shaders compile and link unless told to
+/// fail, and every attribute, uniform or uniform block name resolves to a
+/// location or index. Programs report the uniforms declared with
+/// [`MockGl::declare_uniform`] as active. Framebuffers are complete unless
+/// told otherwise, and read back as zeros.
+#[derive(Default)]
+pub struct MockGl {
+    calls: RefCell<Vec<GlCall>>,
+    last_name: Cell<GLuint>,
+    compile_log: RefCell<Option<String>>,
+    link_log: RefCell<Option<String>>,
+    incomplete_framebuffers: Cell<bool>,
+    attrib_locations: RefCell<HashMap<String, GLint>>,
+    uniform_locations: RefCell<HashMap<String, GLint>>,
+    uniform_block_indices: RefCell<HashMap<String, GLint>>,
+    active_uniforms: RefCell<Vec<(String, GLenum)>>,
+}
+
+impl MockGl {
+    pub fn calls(&self) -> Vec<GlCall> {
+        self.calls.borrow().clone()
+    }
+
+    pub fn count(&self, pred: impl Fn(&GlCall) -> bool) -> usize {
+        self.calls.borrow().iter().filter(|c| pred(c)).count()
+    }
+
+    pub fn clear_calls(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    /// Makes every following compilation fail with `log` as the info log.
+    pub fn fail_compile(&self, log: &str) {
+        self.compile_log.replace(Some(log.to_owned()));
+    }
+
+    /// Makes every following link fail with `log` as the info log.
+    pub fn fail_link(&self, log: &str) {
+        self.link_log.replace(Some(log.to_owned()));
+    }
+
+    /// Makes every following framebuffer status check report an incomplete
+    /// attachment.
+    pub fn fail_framebuffers(&self) {
+        self.incomplete_framebuffers.set(true);
+    }
+
+    /// Makes every program report an active uniform `name` of GLSL type `gl_type`.
+    pub fn declare_uniform(&self, name: &str, gl_type: GLenum) {
+        self.active_uniforms
+            .borrow_mut()
+            .push((name.to_owned(), gl_type));
+    }
+
+    /// The location handed out for the uniform `name`, if it was ever looked up.
+    pub fn uniform_location(&self, name: &str) -> Option<GLint> {
+        self.uniform_locations.borrow().get(name).copied()
+    }
+
+    fn record(&self, call: GlCall) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn next_name(&self) -> GLuint {
+        self.last_name.set(self.last_name.get() + 1);
+        self.last_name.get()
+    }
+
+    unsafe fn gen_names(&self, n: GLsizei, names: *mut GLuint) -> Vec<GLuint> {
+        let generated: Vec<GLuint> = (0..n).map(|_| self.next_name()).collect();
+        std::ptr::copy_nonoverlapping(generated.as_ptr(), names, generated.len());
+        generated
+    }
+
+    unsafe fn read_names(n: GLsizei, names: *const GLuint) -> Vec<GLuint> {
+        std::slice::from_raw_parts(names, n as usize).to_vec()
+    }
+
+    /// Answers the status and info log length queries shared by shaders and programs.
+    fn status_param(log: &RefCell<Option<String>>, status_pname: GLenum, pname: GLenum) -> GLint {
+        let log = log.borrow();
+        match pname {
+            p if p == status_pname => log.is_none() as GLint,
+            gl::INFO_LOG_LENGTH => log.as_ref().map_or(0, |l| l.len() as GLint + 1),
+            _ => 0,
+        }
+    }
+
+    /// Writes a NUL-terminated string like glGet*InfoLog and glGetActiveUniform
+    /// do, truncated to `buf_size`.
+    unsafe fn write_str(s: &str, buf_size: GLsizei, length: *mut GLsizei, out: *mut GLchar) {
+        let bytes = s.as_bytes();
+        let written = bytes.len().min((buf_size.max(1) - 1) as usize);
+        if buf_size > 0 {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr().cast(), out, written);
+            *out.add(written) = 0;
+        }
+        if !length.is_null() {
+            *length = written as GLsizei;
+        }
+    }
+
+    fn location(map: &RefCell<HashMap<String, GLint>>, name: &str) -> GLint {
+        let mut map = map.borrow_mut();
+        let next = map.len() as GLint;
+        *map.entry(name.to_owned()).or_insert(next)
+    }
+}
+
+#[allow(non_snake_case)]
+impl GlBackend for MockGl {
+    unsafe fn CreateProgram(&self) -> GLuint {
+        let program = self.next_name();
+        self.record(GlCall::CreateProgram(program));
+        program
+    }
+
+    unsafe fn UseProgram(&self, program: GLuint) {
+        self.record(GlCall::UseProgram(program));
+    }
+
+    unsafe fn DeleteProgram(&self, program: GLuint) {
+        self.record(GlCall::DeleteProgram(program));
+    }
+
+    unsafe fn AttachShader(&self, program: GLuint, shader: GLuint) {
+        self.record(GlCall::AttachShader { program, shader });
+    }
+
+    unsafe fn LinkProgram(&self, program: GLuint) {
+        self.record(GlCall::LinkProgram(program));
+    }
+
+    unsafe fn GetProgramiv(&self, program: GLuint, pname: GLenum, params: *mut GLint) {
+        let active = self.active_uniforms.borrow();
+        *params = match pname {
+            gl::ACTIVE_UNIFORMS => active.len() as GLint,
+            gl::ACTIVE_UNIFORM_MAX_LENGTH => active
+                .iter()
+                .map(|(n, _)| n.len() as GLint + 1)
+                .max()
+                .unwrap_or(0),
+            _ => Self::status_param(&self.link_log, gl::LINK_STATUS, pname),
+        };
+        self.record(GlCall::GetProgramiv { program, pname });
+    }
+
+    unsafe fn GetProgramInfoLog(
+        &self,
+        program: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        info_log: *mut GLchar,
+    ) {
+        let log = self.link_log.borrow();
+        Self::write_str(
+            log.as_deref().unwrap_or_default(),
+            buf_size,
+            length,
+            info_log,
+        );
+        self.record(GlCall::GetProgramInfoLog(program));
+    }
+
+    unsafe fn CreateShader(&self, type_: GLenum) -> GLuint {
+        let shader = self.next_name();
+        self.record(GlCall::CreateShader {
+            kind: type_,
+            shader,
+        });
+        shader
+    }
+
+    unsafe fn ShaderSource(
+        &self,
+        shader: GLuint,
+        count: GLsizei,
+        string: *const *const GLchar,
+        length: *const GLint,
+    ) {
+        let source = (0..count as usize)
+            .map(|i| {
+                let s = *string.add(i);
+                if length.is_null() || *length.add(i) < 0 {
+                    CStr::from_ptr(s).to_string_lossy().into_owned()
+                } else {
+                    let bytes = std::slice::from_raw_parts(s.cast::<u8>(), *length.add(i) as usize);
+                    String::from_utf8_lossy(bytes).into_owned()
+                }
+            })
+            .collect();
+        self.record(GlCall::ShaderSource { shader, source });
+    }
+
+    unsafe fn CompileShader(&self, shader: GLuint) {
+        self.record(GlCall::CompileShader(shader));
+    }
+
+    unsafe fn GetShaderiv(&self, shader: GLuint, pname: GLenum, params: *mut GLint) {
+        *params = Self::status_param(&self.compile_log, gl::COMPILE_STATUS, pname);
+        self.record(GlCall::GetShaderiv { shader, pname });
+    }
+
+    unsafe fn GetShaderInfoLog(
+        &self,
+        shader: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        info_log: *mut GLchar,
+    ) {
+        let log = self.compile_log.borrow();
+        Self::write_str(
+            log.as_deref().unwrap_or_default(),
+            buf_size,
+            length,
+            info_log,
+        );
+        self.record(GlCall::GetShaderInfoLog(shader));
+    }
+
+    unsafe fn DeleteShader(&self, shader: GLuint) {
+        self.record(GlCall::DeleteShader(shader));
+    }
+
+    unsafe fn GenVertexArrays(&self, n: GLsizei, arrays: *mut GLuint) {
+        let generated = self.gen_names(n, arrays);
+        self.record(GlCall::GenVertexArrays(generated));
+    }
+
+    unsafe fn BindVertexArray(&self, array: GLuint) {
+        self.record(GlCall::BindVertexArray(array));
+    }
+
+    unsafe fn DeleteVertexArrays(&self, n: GLsizei, arrays: *const GLuint) {
+        self.record(GlCall::DeleteVertexArrays(Self::read_names(n, arrays)));
+    }
+
+    unsafe fn GenBuffers(&self, n: GLsizei, buffers: *mut GLuint) {
+        let generated = self.gen_names(n, buffers);
+        self.record(GlCall::GenBuffers(generated));
+    }
+
+    unsafe fn BindBuffer(&self, target: GLenum, buffer: GLuint) {
+        self.record(GlCall::BindBuffer { target, buffer });
+    }
+
+    unsafe fn BufferData(
+        &self,
+        target: GLenum,
+        size: GLsizeiptr,
+        _data: *const c_void,
+        _usage: GLenum,
+    ) {
+        self.record(GlCall::BufferData { target, size });
+    }
+
+    unsafe fn BufferSubData(
+        &self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        _data: *const c_void,
+    ) {
+        self.record(GlCall::BufferSubData {
+            target,
+            offset,
+            size,
+        });
+    }
+
+    unsafe fn DeleteBuffers(&self, n: GLsizei, buffers: *const GLuint) {
+        self.record(GlCall::DeleteBuffers(Self::read_names(n, buffers)));
+    }
+
+    unsafe fn BindBufferBase(&self, target: GLenum, index: GLuint, buffer: GLuint) {
+        self.record(GlCall::BindBufferBase {
+            target,
+            index,
+            buffer,
+        });
+    }
+
+    unsafe fn GetAttribLocation(&self, program: GLuint, name: *const GLchar) -> GLint {
+        let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+        let location = Self::location(&self.attrib_locations, &name);
+        self.record(GlCall::GetAttribLocation { program, name });
+        location
+    }
+
+    unsafe fn VertexAttribPointer(
+        &self,
+        index: GLuint,
+        size: GLint,
+        _type_: GLenum,
+        _normalized: GLboolean,
+        stride: GLsizei,
+        pointer: *const c_void,
+    ) {
+        self.record(GlCall::VertexAttribPointer {
+            index,
+            size,
+            stride,
+            offset: pointer as usize,
+        });
+    }
+
+    unsafe fn EnableVertexAttribArray(&self, index: GLuint) {
+        self.record(GlCall::EnableVertexAttribArray(index));
+    }
+
+    unsafe fn VertexAttribDivisor(&self, index: GLuint, divisor: GLuint) {
+        self.record(GlCall::VertexAttribDivisor { index, divisor });
+    }
+
+    unsafe fn GetActiveUniform(
+        &self,
+        program: GLuint,
+        index: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar,
+    ) {
+        let active = self.active_uniforms.borrow();
+        let (uniform, gl_type) = &active[index as usize];
+        Self::write_str(uniform, buf_size, length, name);
+        *size = 1;
+        *type_ = *gl_type;
+        self.record(GlCall::GetActiveUniform { program, index });
+    }
+
+    unsafe fn GetUniformLocation(&self, program: GLuint, name: *const GLchar) -> GLint {
+        let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+        let location = Self::location(&self.uniform_locations, &name);
+        self.record(GlCall::GetUniformLocation { program, name });
+        location
+    }
+
+    unsafe fn GetUniformBlockIndex(&self, program: GLuint, name: *const GLchar) -> GLuint {
+        let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+        let index = Self::location(&self.uniform_block_indices, &name) as GLuint;
+        self.record(GlCall::GetUniformBlockIndex { program, name });
+        index
+    }
+
+    unsafe fn UniformBlockBinding(&self, program: GLuint, block_index: GLuint, binding: GLuint) {
+        self.record(GlCall::UniformBlockBinding {
+            program,
+            block_index,
+            binding,
+        });
+    }
+
+    unsafe fn Uniform1i(&self, location: GLint, v0: GLint) {
+        self.record(GlCall::Uniform1i {
+            location,
+            value: v0,
+        });
+    }
+
+    unsafe fn Uniform1f(&self, location: GLint, v0: GLfloat) {
+        self.record(GlCall::Uniform1f {
+            location,
+            value: v0,
+        });
+    }
+
+    unsafe fn Uniform3f(&self, location: GLint, v0: GLfloat, v1: GLfloat, v2: GLfloat) {
+        self.record(GlCall::Uniform3f {
+            location,
+            value: [v0, v1, v2],
+        });
+    }
+
+    unsafe fn Uniform1ui(&self, location: GLint, v0: GLuint) {
+        self.record(GlCall::Uniform1ui {
+            location,
+            value: v0,
+        });
+    }
+
+    unsafe fn UniformMatrix4fv(
+        &self,
+        location: GLint,
+        count: GLsizei,
+        _transpose: GLboolean,
+        value: *const GLfloat,
+    ) {
+        let value = std::slice::from_raw_parts(value, 16 * count as usize).to_vec();
+        self.record(GlCall::UniformMatrix4fv { location, value });
+    }
+
+    unsafe fn GenTextures(&self, n: GLsizei, textures: *mut GLuint) {
+        let generated = self.gen_names(n, textures);
+        self.record(GlCall::GenTextures(generated));
+    }
+
+    unsafe fn BindTexture(&self, target: GLenum, texture: GLuint) {
+        self.record(GlCall::BindTexture { target, texture });
+    }
+
+    unsafe fn ActiveTexture(&self, texture: GLenum) {
+        self.record(GlCall::ActiveTexture(texture));
+    }
+
+    unsafe fn TexImage2D(
+        &self,
+        target: GLenum,
+        _level: GLint,
+        _internalformat: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        _border: GLint,
+        _format: GLenum,
+        _type_: GLenum,
+        _pixels: *const c_void,
+    ) {
+        self.record(GlCall::TexImage2D {
+            target,
+            width,
+            height,
+        });
+    }
+
+    unsafe fn GenerateMipmap(&self, target: GLenum) {
+        self.record(GlCall::GenerateMipmap(target));
+    }
+
+    unsafe fn TexParameteri(&self, target: GLenum, pname: GLenum, param: GLint) {
+        self.record(GlCall::TexParameteri {
+            target,
+            pname,
+            param,
+        });
+    }
+
+    unsafe fn DeleteTextures(&self, n: GLsizei, textures: *const GLuint) {
+        self.record(GlCall::DeleteTextures(Self::read_names(n, textures)));
+    }
+
+    unsafe fn GenFramebuffers(&self, n: GLsizei, framebuffers: *mut GLuint) {
+        let generated = self.gen_names(n, framebuffers);
+        self.record(GlCall::GenFramebuffers(generated));
+    }
+
+    unsafe fn BindFramebuffer(&self, target: GLenum, framebuffer: GLuint) {
+        self.record(GlCall::BindFramebuffer {
+            target,
+            framebuffer,
+        });
+    }
+
+    unsafe fn FramebufferTexture2D(
+        &self,
+        _target: GLenum,
+        attachment: GLenum,
+        _textarget: GLenum,
+        texture: GLuint,
+        _level: GLint,
+    ) {
+        self.record(GlCall::FramebufferTexture2D {
+            attachment,
+            texture,
+        });
+    }
+
+    unsafe fn FramebufferRenderbuffer(
+        &self,
+        _target: GLenum,
+        attachment: GLenum,
+        _renderbuffertarget: GLenum,
+        renderbuffer: GLuint,
+    ) {
+        self.record(GlCall::FramebufferRenderbuffer {
+            attachment,
+            renderbuffer,
+        });
+    }
+
+    unsafe fn CheckFramebufferStatus(&self, _target: GLenum) -> GLenum {
+        if self.incomplete_framebuffers.get() {
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT
+        } else {
+            gl::FRAMEBUFFER_COMPLETE
+        }
+    }
+
+    unsafe fn DeleteFramebuffers(&self, n: GLsizei, framebuffers: *const GLuint) {
+        self.record(GlCall::DeleteFramebuffers(Self::read_names(
+            n,
+            framebuffers,
+        )));
+    }
+
+    unsafe fn GenRenderbuffers(&self, n: GLsizei, renderbuffers: *mut GLuint) {
+        let generated = self.gen_names(n, renderbuffers);
+        self.record(GlCall::GenRenderbuffers(generated));
+    }
+
+    unsafe fn BindRenderbuffer(&self, _target: GLenum, renderbuffer: GLuint) {
+        self.record(GlCall::BindRenderbuffer(renderbuffer));
+    }
+
+    unsafe fn RenderbufferStorage(
+        &self,
+        _target: GLenum,
+        internalformat: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    ) {
+        self.record(GlCall::RenderbufferStorage {
+            internalformat,
+            width,
+            height,
+        });
+    }
+
+    unsafe fn DeleteRenderbuffers(&self, n: GLsizei, renderbuffers: *const GLuint) {
+        self.record(GlCall::DeleteRenderbuffers(Self::read_names(
+            n,
+            renderbuffers,
+        )));
+    }
+
+    unsafe fn PixelStorei(&self, _pname: GLenum, _param: GLint) {}
+
+    /// Writes zeros, reading RGBA bytes being the only use.
+    unsafe fn ReadPixels(
+        &self,
+        _x: GLint,
+        _y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        _format: GLenum,
+        _type_: GLenum,
+        pixels: *mut c_void,
+    ) {
+        std::ptr::write_bytes(pixels.cast::<u8>(), 0, (width * height * 4) as usize);
+        self.record(GlCall::ReadPixels { width, height });
+    }
+
+    unsafe fn DrawArrays(&self, mode: GLenum, first: GLint, count: GLsizei) {
+        self.record(GlCall::DrawArrays { mode, first, count });
+    }
+
+    unsafe fn DrawElements(
+        &self,
+        mode: GLenum,
+        count: GLsizei,
+        _type_: GLenum,
+        _indices: *const c_void,
+    ) {
+        self.record(GlCall::DrawElements { mode, count });
+    }
+
+    unsafe fn DrawArraysInstanced(
+        &self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instancecount: GLsizei,
+    ) {
+        self.record(GlCall::DrawArraysInstanced {
+            mode,
+            first,
+            count,
+            instances: instancecount,
+        });
+    }
+}
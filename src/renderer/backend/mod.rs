@@ -0,0 +1,528 @@
+use std::ffi::c_void;
+
+use crate::gl::{
+    Gles2,
+    types::{GLboolean, GLchar, GLenum, GLfloat, GLint, GLintptr, GLsizei, GLsizeiptr, GLuint},
+};
+
+#[cfg(test)]
+pub mod mock;
+
+/// The subset of the GL API used by shaders, entities, uniforms and render
+/// targets.
+///
+/// Methods keep the GL names and signatures of the generated bindings so call
+/// sites read the same whether they run on [`Gles2`] or on the test mock.
+///
+/// # Safety
+/// Every method is an FFI call with the usual GL pointer and context requirements.
+#[allow(non_snake_case, clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub trait GlBackend {
+    unsafe fn CreateProgram(&self) -> GLuint;
+    unsafe fn UseProgram(&self, program: GLuint);
+    unsafe fn DeleteProgram(&self, program: GLuint);
+    unsafe fn AttachShader(&self, program: GLuint, shader: GLuint);
+    unsafe fn LinkProgram(&self, program: GLuint);
+    unsafe fn GetProgramiv(&self, program: GLuint, pname: GLenum, params: *mut GLint);
+    unsafe fn GetProgramInfoLog(
+        &self,
+        program: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        info_log: *mut GLchar,
+    );
+    unsafe fn CreateShader(&self, type_: GLenum) -> GLuint;
+    unsafe fn ShaderSource(
+        &self,
+        shader: GLuint,
+        count: GLsizei,
+        string: *const *const GLchar,
+        length: *const GLint,
+    );
+    unsafe fn CompileShader(&self, shader: GLuint);
+    unsafe fn GetShaderiv(&self, shader: GLuint, pname: GLenum, params: *mut GLint);
+    unsafe fn GetShaderInfoLog(
+        &self,
+        shader: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        info_log: *mut GLchar,
+    );
+    unsafe fn DeleteShader(&self, shader: GLuint);
+    unsafe fn GenVertexArrays(&self, n: GLsizei, arrays: *mut GLuint);
+    unsafe fn BindVertexArray(&self, array: GLuint);
+    unsafe fn DeleteVertexArrays(&self, n: GLsizei, arrays: *const GLuint);
+    unsafe fn GenBuffers(&self, n: GLsizei, buffers: *mut GLuint);
+    unsafe fn BindBuffer(&self, target: GLenum, buffer: GLuint);
+    unsafe fn BufferData(
+        &self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        usage: GLenum,
+    );
+    unsafe fn BufferSubData(
+        &self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *const c_void,
+    );
+    unsafe fn DeleteBuffers(&self, n: GLsizei, buffers: *const GLuint);
+    unsafe fn BindBufferBase(&self, target: GLenum, index: GLuint, buffer: GLuint);
+    unsafe fn GetAttribLocation(&self, program: GLuint, name: *const GLchar) -> GLint;
+    unsafe fn VertexAttribPointer(
+        &self,
+        index: GLuint,
+        size: GLint,
+        type_: GLenum,
+        normalized: GLboolean,
+        stride: GLsizei,
+        pointer: *const c_void,
+    );
+    unsafe fn EnableVertexAttribArray(&self, index: GLuint);
+    unsafe fn VertexAttribDivisor(&self, index: GLuint, divisor: GLuint);
+    unsafe fn GetActiveUniform(
+        &self,
+        program: GLuint,
+        index: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar,
+    );
+    unsafe fn GetUniformLocation(&self, program: GLuint, name: *const GLchar) -> GLint;
+    unsafe fn GetUniformBlockIndex(&self, program: GLuint, name: *const GLchar) -> GLuint;
+    unsafe fn UniformBlockBinding(&self, program: GLuint, block_index: GLuint, binding: GLuint);
+    unsafe fn Uniform1i(&self, location: GLint, v0: GLint);
+    unsafe fn Uniform1f(&self, location: GLint, v0: GLfloat);
+    unsafe fn Uniform3f(&self, location: GLint, v0: GLfloat, v1: GLfloat, v2: GLfloat);
+    unsafe fn Uniform1ui(&self, location: GLint, v0: GLuint);
+    unsafe fn UniformMatrix4fv(
+        &self,
+        location: GLint,
+        count: GLsizei,
+        transpose: GLboolean,
+        value: *const GLfloat,
+    );
+    unsafe fn GenTextures(&self, n: GLsizei, textures: *mut GLuint);
+    unsafe fn BindTexture(&self, target: GLenum, texture: GLuint);
+    unsafe fn ActiveTexture(&self, texture: GLenum);
+    unsafe fn TexImage2D(
+        &self,
+        target: GLenum,
+        level: GLint,
+        internalformat: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        border: GLint,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *const c_void,
+    );
+    unsafe fn GenerateMipmap(&self, target: GLenum);
+    unsafe fn TexParameteri(&self, target: GLenum, pname: GLenum, param: GLint);
+    unsafe fn DeleteTextures(&self, n: GLsizei, textures: *const GLuint);
+    unsafe fn GenFramebuffers(&self, n: GLsizei, framebuffers: *mut GLuint);
+    unsafe fn BindFramebuffer(&self, target: GLenum, framebuffer: GLuint);
+    unsafe fn FramebufferTexture2D(
+        &self,
+        target: GLenum,
+        attachment: GLenum,
+        textarget: GLenum,
+        texture: GLuint,
+        level: GLint,
+    );
+    unsafe fn FramebufferRenderbuffer(
+        &self,
+        target: GLenum,
+        attachment: GLenum,
+        renderbuffertarget: GLenum,
+        renderbuffer: GLuint,
+    );
+    unsafe fn CheckFramebufferStatus(&self, target: GLenum) -> GLenum;
+    unsafe fn DeleteFramebuffers(&self, n: GLsizei, framebuffers: *const GLuint);
+    unsafe fn GenRenderbuffers(&self, n: GLsizei, renderbuffers: *mut GLuint);
+    unsafe fn BindRenderbuffer(&self, target: GLenum, renderbuffer: GLuint);
+    unsafe fn RenderbufferStorage(
+        &self,
+        target: GLenum,
+        internalformat: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    );
+    unsafe fn DeleteRenderbuffers(&self, n: GLsizei, renderbuffers: *const GLuint);
+    unsafe fn PixelStorei(&self, pname: GLenum, param: GLint);
+    unsafe fn ReadPixels(
+        &self,
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *mut c_void,
+    );
+    unsafe fn DrawArrays(&self, mode: GLenum, first: GLint, count: GLsizei);
+    unsafe fn DrawElements(
+        &self,
+        mode: GLenum,
+        count: GLsizei,
+        type_: GLenum,
+        indices: *const c_void,
+    );
+    unsafe fn DrawArraysInstanced(
+        &self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instancecount: GLsizei,
+    );
+}
+
+#[allow(non_snake_case)]
+impl GlBackend for Gles2 {
+    unsafe fn CreateProgram(&self) -> GLuint {
+        Gles2::CreateProgram(self)
+    }
+
+    unsafe fn UseProgram(&self, program: GLuint) {
+        Gles2::UseProgram(self, program)
+    }
+
+    unsafe fn DeleteProgram(&self, program: GLuint) {
+        Gles2::DeleteProgram(self, program)
+    }
+
+    unsafe fn AttachShader(&self, program: GLuint, shader: GLuint) {
+        Gles2::AttachShader(self, program, shader)
+    }
+
+    unsafe fn LinkProgram(&self, program: GLuint) {
+        Gles2::LinkProgram(self, program)
+    }
+
+    unsafe fn GetProgramiv(&self, program: GLuint, pname: GLenum, params: *mut GLint) {
+        Gles2::GetProgramiv(self, program, pname, params)
+    }
+
+    unsafe fn GetProgramInfoLog(
+        &self,
+        program: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        info_log: *mut GLchar,
+    ) {
+        Gles2::GetProgramInfoLog(self, program, buf_size, length, info_log)
+    }
+
+    unsafe fn CreateShader(&self, type_: GLenum) -> GLuint {
+        Gles2::CreateShader(self, type_)
+    }
+
+    unsafe fn ShaderSource(
+        &self,
+        shader: GLuint,
+        count: GLsizei,
+        string: *const *const GLchar,
+        length: *const GLint,
+    ) {
+        Gles2::ShaderSource(self, shader, count, string, length)
+    }
+
+    unsafe fn CompileShader(&self, shader: GLuint) {
+        Gles2::CompileShader(self, shader)
+    }
+
+    unsafe fn GetShaderiv(&self, shader: GLuint, pname: GLenum, params: *mut GLint) {
+        Gles2::GetShaderiv(self, shader, pname, params)
+    }
+
+    unsafe fn GetShaderInfoLog(
+        &self,
+        shader: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        info_log: *mut GLchar,
+    ) {
+        Gles2::GetShaderInfoLog(self, shader, buf_size, length, info_log)
+    }
+
+    unsafe fn DeleteShader(&self, shader: GLuint) {
+        Gles2::DeleteShader(self, shader)
+    }
+
+    unsafe fn GenVertexArrays(&self, n: GLsizei, arrays: *mut GLuint) {
+        Gles2::GenVertexArrays(self, n, arrays)
+    }
+
+    unsafe fn BindVertexArray(&self, array: GLuint) {
+        Gles2::BindVertexArray(self, array)
+    }
+
+    unsafe fn DeleteVertexArrays(&self, n: GLsizei, arrays: *const GLuint) {
+        Gles2::DeleteVertexArrays(self, n, arrays)
+    }
+
+    unsafe fn GenBuffers(&self, n: GLsizei, buffers: *mut GLuint) {
+        Gles2::GenBuffers(self, n, buffers)
+    }
+
+    unsafe fn BindBuffer(&self, target: GLenum, buffer: GLuint) {
+        Gles2::BindBuffer(self, target, buffer)
+    }
+
+    unsafe fn BufferData(
+        &self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        usage: GLenum,
+    ) {
+        Gles2::BufferData(self, target, size, data, usage)
+    }
+
+    unsafe fn BufferSubData(
+        &self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *const c_void,
+    ) {
+        Gles2::BufferSubData(self, target, offset, size, data)
+    }
+
+    unsafe fn DeleteBuffers(&self, n: GLsizei, buffers: *const GLuint) {
+        Gles2::DeleteBuffers(self, n, buffers)
+    }
+
+    unsafe fn BindBufferBase(&self, target: GLenum, index: GLuint, buffer: GLuint) {
+        Gles2::BindBufferBase(self, target, index, buffer)
+    }
+
+    unsafe fn GetAttribLocation(&self, program: GLuint, name: *const GLchar) -> GLint {
+        Gles2::GetAttribLocation(self, program, name)
+    }
+
+    unsafe fn VertexAttribPointer(
+        &self,
+        index: GLuint,
+        size: GLint,
+        type_: GLenum,
+        normalized: GLboolean,
+        stride: GLsizei,
+        pointer: *const c_void,
+    ) {
+        Gles2::VertexAttribPointer(self, index, size, type_, normalized, stride, pointer)
+    }
+
+    unsafe fn EnableVertexAttribArray(&self, index: GLuint) {
+        Gles2::EnableVertexAttribArray(self, index)
+    }
+
+    unsafe fn VertexAttribDivisor(&self, index: GLuint, divisor: GLuint) {
+        Gles2::VertexAttribDivisor(self, index, divisor)
+    }
+
+    unsafe fn GetActiveUniform(
+        &self,
+        program: GLuint,
+        index: GLuint,
+        buf_size: GLsizei,
+        length: *mut GLsizei,
+        size: *mut GLint,
+        type_: *mut GLenum,
+        name: *mut GLchar,
+    ) {
+        Gles2::GetActiveUniform(self, program, index, buf_size, length, size, type_, name)
+    }
+
+    unsafe fn GetUniformLocation(&self, program: GLuint, name: *const GLchar) -> GLint {
+        Gles2::GetUniformLocation(self, program, name)
+    }
+
+    unsafe fn GetUniformBlockIndex(&self, program: GLuint, name: *const GLchar) -> GLuint {
+        Gles2::GetUniformBlockIndex(self, program, name)
+    }
+
+    unsafe fn UniformBlockBinding(&self, program: GLuint, block_index: GLuint, binding: GLuint) {
+        Gles2::UniformBlockBinding(self, program, block_index, binding)
+    }
+
+    unsafe fn Uniform1i(&self, location: GLint, v0: GLint) {
+        Gles2::Uniform1i(self, location, v0)
+    }
+
+    unsafe fn Uniform1f(&self, location: GLint, v0: GLfloat) {
+        Gles2::Uniform1f(self, location, v0)
+    }
+
+    unsafe fn Uniform3f(&self, location: GLint, v0: GLfloat, v1: GLfloat, v2: GLfloat) {
+        Gles2::Uniform3f(self, location, v0, v1, v2)
+    }
+
+    unsafe fn Uniform1ui(&self, location: GLint, v0: GLuint) {
+        Gles2::Uniform1ui(self, location, v0)
+    }
+
+    unsafe fn UniformMatrix4fv(
+        &self,
+        location: GLint,
+        count: GLsizei,
+        transpose: GLboolean,
+        value: *const GLfloat,
+    ) {
+        Gles2::UniformMatrix4fv(self, location, count, transpose, value)
+    }
+
+    unsafe fn GenTextures(&self, n: GLsizei, textures: *mut GLuint) {
+        Gles2::GenTextures(self, n, textures)
+    }
+
+    unsafe fn BindTexture(&self, target: GLenum, texture: GLuint) {
+        Gles2::BindTexture(self, target, texture)
+    }
+
+    unsafe fn ActiveTexture(&self, texture: GLenum) {
+        Gles2::ActiveTexture(self, texture)
+    }
+
+    unsafe fn TexImage2D(
+        &self,
+        target: GLenum,
+        level: GLint,
+        internalformat: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        border: GLint,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *const c_void,
+    ) {
+        Gles2::TexImage2D(
+            self,
+            target,
+            level,
+            internalformat,
+            width,
+            height,
+            border,
+            format,
+            type_,
+            pixels,
+        )
+    }
+
+    unsafe fn GenerateMipmap(&self, target: GLenum) {
+        Gles2::GenerateMipmap(self, target)
+    }
+
+    unsafe fn TexParameteri(&self, target: GLenum, pname: GLenum, param: GLint) {
+        Gles2::TexParameteri(self, target, pname, param)
+    }
+
+    unsafe fn DeleteTextures(&self, n: GLsizei, textures: *const GLuint) {
+        Gles2::DeleteTextures(self, n, textures)
+    }
+
+    unsafe fn GenFramebuffers(&self, n: GLsizei, framebuffers: *mut GLuint) {
+        Gles2::GenFramebuffers(self, n, framebuffers)
+    }
+
+    unsafe fn BindFramebuffer(&self, target: GLenum, framebuffer: GLuint) {
+        Gles2::BindFramebuffer(self, target, framebuffer)
+    }
+
+    unsafe fn FramebufferTexture2D(
+        &self,
+        target: GLenum,
+        attachment: GLenum,
+        textarget: GLenum,
+        texture: GLuint,
+        level: GLint,
+    ) {
+        Gles2::FramebufferTexture2D(self, target, attachment, textarget, texture, level)
+    }
+
+    unsafe fn FramebufferRenderbuffer(
+        &self,
+        target: GLenum,
+        attachment: GLenum,
+        renderbuffertarget: GLenum,
+        renderbuffer: GLuint,
+    ) {
+        Gles2::FramebufferRenderbuffer(self, target, attachment, renderbuffertarget, renderbuffer)
+    }
+
+    unsafe fn CheckFramebufferStatus(&self, target: GLenum) -> GLenum {
+        Gles2::CheckFramebufferStatus(self, target)
+    }
+
+    unsafe fn DeleteFramebuffers(&self, n: GLsizei, framebuffers: *const GLuint) {
+        Gles2::DeleteFramebuffers(self, n, framebuffers)
+    }
+
+    unsafe fn GenRenderbuffers(&self, n: GLsizei, renderbuffers: *mut GLuint) {
+        Gles2::GenRenderbuffers(self, n, renderbuffers)
+    }
+
+    unsafe fn BindRenderbuffer(&self, target: GLenum, renderbuffer: GLuint) {
+        Gles2::BindRenderbuffer(self, target, renderbuffer)
+    }
+
+    unsafe fn RenderbufferStorage(
+        &self,
+        target: GLenum,
+        internalformat: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    ) {
+        Gles2::RenderbufferStorage(self, target, internalformat, width, height)
+    }
+
+    unsafe fn DeleteRenderbuffers(&self, n: GLsizei, renderbuffers: *const GLuint) {
+        Gles2::DeleteRenderbuffers(self, n, renderbuffers)
+    }
+
+    unsafe fn PixelStorei(&self, pname: GLenum, param: GLint) {
+        Gles2::PixelStorei(self, pname, param)
+    }
+
+    unsafe fn ReadPixels(
+        &self,
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *mut c_void,
+    ) {
+        Gles2::ReadPixels(self, x, y, width, height, format, type_, pixels)
+    }
+
+    unsafe fn DrawArrays(&self, mode: GLenum, first: GLint, count: GLsizei) {
+        Gles2::DrawArrays(self, mode, first, count)
+    }
+
+    unsafe fn DrawElements(
+        &self,
+        mode: GLenum,
+        count: GLsizei,
+        type_: GLenum,
+        indices: *const c_void,
+    ) {
+        Gles2::DrawElements(self, mode, count, type_, indices)
+    }
+
+    unsafe fn DrawArraysInstanced(
+        &self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instancecount: GLsizei,
+    ) {
+        Gles2::DrawArraysInstanced(self, mode, first, count, instancecount)
+    }
+}
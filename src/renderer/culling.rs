@@ -0,0 +1,218 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// Axis-aligned bounding box, in the space of the vertices it was built from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// The smallest box around `points`, `None` without any.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        points.into_iter().fold(None, |aabb, p| {
+            Some(match aabb {
+                Some(Aabb { min, max }) => Aabb {
+                    min: min.min(p),
+                    max: max.max(p),
+                },
+                None => Aabb { min: p, max: p },
+            })
+        })
+    }
+
+    /// The box around interleaved vertex data of `stride` floats per vertex,
+    /// each starting with its position.
+    pub fn from_vertex_data(vertex_data: &[f32], stride: usize) -> Option<Self> {
+        Self::from_points(
+            vertex_data
+                .chunks_exact(stride)
+                .map(|vertex| Vec3::from_slice(&vertex[..3])),
+        )
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// The box around this one moved by `transform`, e.g. a model matrix.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Self::from_points(self.corners().map(|c| transform.transform_point3(c)))
+            .expect("a box has corners")
+    }
+}
+
+/// Where a box is relative to a [`Frustum`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Outside,
+    /// Partly inside, crossing at least one plane
+    Intersecting,
+    Inside,
+}
+
+/// The 6 planes bounding what a camera sees, facing inward: points `p` with
+/// `plane.dot((p, 1)) >= 0` for every plane are in view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// The planes of the clip volume of `view_projection`, in the space it
+    /// projects from (Gribb and Hartmann), e.g. world space for
+    /// `projection * view`.
+    pub fn from_matrix(view_projection: Mat4) -> Self {
+        let m = view_projection.transpose();
+        let (x, y, z, w) = (m.x_axis, m.y_axis, m.z_axis, m.w_axis);
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| {
+            // Normalized so the distances compare between planes
+            plane / plane.truncate().length()
+        });
+        Self { planes }
+    }
+
+    /// Tests the corner of `aabb` furthest along each plane normal, then the
+    /// nearest one: conservative, boxes near the frustum corners may be
+    /// reported intersecting while just outside.
+    pub fn classify(&self, aabb: &Aabb) -> Visibility {
+        let mut visibility = Visibility::Inside;
+        for plane in &self.planes {
+            let normal = plane.truncate();
+            let furthest = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            if normal.dot(furthest) + plane.w < 0.0 {
+                return Visibility::Outside;
+            }
+            let nearest = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.min, aabb.max);
+            if normal.dot(nearest) + plane.w < 0.0 {
+                visibility = Visibility::Intersecting;
+            }
+        }
+        visibility
+    }
+
+    /// Whether some of `aabb` may be in view.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.classify(aabb) != Visibility::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looking down -z from the origin, seeing from 1 to 10 away, 90° wide.
+    fn frustum() -> Frustum {
+        let projection = Mat4::perspective_rh_gl(90f32.to_radians(), 1.0, 1.0, 10.0);
+        Frustum::from_matrix(projection * Mat4::IDENTITY)
+    }
+
+    fn cube(center: Vec3, half: f32) -> Aabb {
+        Aabb {
+            min: center - half,
+            max: center + half,
+        }
+    }
+
+    #[test]
+    fn boxes_are_inside_outside_or_crossing_the_frustum() {
+        let frustum = frustum();
+        let cases = [
+            (cube(Vec3::new(0.0, 0.0, -5.0), 1.0), Visibility::Inside),
+            (cube(Vec3::new(0.0, 0.0, 5.0), 1.0), Visibility::Outside),
+            // Past the sides, widening 1 per unit away
+            (cube(Vec3::new(8.0, 0.0, -5.0), 1.0), Visibility::Outside),
+            (cube(Vec3::new(0.0, -8.0, -5.0), 1.0), Visibility::Outside),
+            (
+                cube(Vec3::new(5.0, 0.0, -5.0), 1.0),
+                Visibility::Intersecting,
+            ),
+            // Near and far planes
+            (cube(Vec3::new(0.0, 0.0, -0.2), 0.1), Visibility::Outside),
+            (
+                cube(Vec3::new(0.0, 0.0, -1.0), 0.5),
+                Visibility::Intersecting,
+            ),
+            (cube(Vec3::new(0.0, 0.0, -12.0), 1.0), Visibility::Outside),
+            (
+                cube(Vec3::new(0.0, 0.0, -10.0), 1.0),
+                Visibility::Intersecting,
+            ),
+            // Around the camera
+            (cube(Vec3::ZERO, 20.0), Visibility::Intersecting),
+        ];
+        for (aabb, expected) in cases {
+            assert_eq!(frustum.classify(&aabb), expected, "{aabb:?}");
+            assert_eq!(frustum.intersects(&aabb), expected != Visibility::Outside);
+        }
+    }
+
+    #[test]
+    fn frustum_follows_the_view() {
+        let projection = Mat4::perspective_rh_gl(90f32.to_radians(), 1.0, 1.0, 10.0);
+        let eye = Vec3::new(10.0, 2.0, 0.0);
+        let view = Mat4::look_at_rh(eye, eye + Vec3::X, Vec3::Y);
+        let frustum = Frustum::from_matrix(projection * view);
+
+        let ahead = cube(eye + Vec3::X * 5.0, 1.0);
+        let behind = cube(eye - Vec3::X * 5.0, 1.0);
+        assert_eq!(frustum.classify(&ahead), Visibility::Inside);
+        assert_eq!(frustum.classify(&behind), Visibility::Outside);
+        // What used to be ahead of the identity view
+        assert_eq!(
+            frustum.classify(&cube(Vec3::new(0.0, 0.0, -5.0), 1.0)),
+            Visibility::Outside
+        );
+    }
+
+    #[test]
+    fn flat_boxes_are_classified_too() {
+        let floor = Aabb {
+            min: Vec3::new(-2.0, -1.0, -6.0),
+            max: Vec3::new(2.0, -1.0, -4.0),
+        };
+        assert_eq!(frustum().classify(&floor), Visibility::Inside);
+    }
+
+    #[test]
+    fn boxes_wrap_points_and_their_transforms() {
+        assert_eq!(Aabb::from_points([]), None);
+        let aabb = Aabb::from_vertex_data(
+            &[1.0, 2.0, 3.0, 9.0, -1.0, 5.0, 0.0, 9.0, 2.0, 2.0, -4.0, 9.0],
+            4,
+        )
+        .unwrap();
+        assert_eq!(
+            aabb,
+            Aabb {
+                min: Vec3::new(-1.0, 2.0, -4.0),
+                max: Vec3::new(2.0, 5.0, 3.0),
+            }
+        );
+
+        let moved = aabb.transformed(&Mat4::from_translation(Vec3::ONE));
+        assert_eq!(moved.min, aabb.min + 1.0);
+        assert_eq!(moved.max, aabb.max + 1.0);
+        // A quarter turn around y swaps x and z
+        let turned = aabb.transformed(&Mat4::from_rotation_y(90f32.to_radians()));
+        assert!(turned.min.abs_diff_eq(Vec3::new(-4.0, 2.0, -2.0), 1e-5));
+        assert!(turned.max.abs_diff_eq(Vec3::new(3.0, 5.0, 1.0), 1e-5));
+    }
+}
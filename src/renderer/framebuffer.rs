@@ -0,0 +1,324 @@
+use std::rc::Rc;
+
+use crate::{gl, renderer::backend::GlBackend};
+
+/// What the color of a [`FramebufferObject`] is drawn into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorAttachment {
+    /// Only read back by the CPU
+    Renderbuffer,
+    /// Sampled by later draws
+    Texture,
+}
+
+/// A framebuffer object with an RGBA8 color attachment and a depth renderbuffer,
+/// all freed on drop.
+struct FramebufferObject {
+    fbo: gl::types::GLuint,
+    color: gl::types::GLuint,
+    color_attachment: ColorAttachment,
+    depth_rb: gl::types::GLuint,
+    dimensions: glam::USizeVec2,
+    gl_fns: Rc<dyn GlBackend>,
+}
+
+impl FramebufferObject {
+    /// Leaves the new framebuffer bound.
+    fn new(
+        gl_fns: Rc<dyn GlBackend>,
+        dimensions: glam::USizeVec2,
+        color_attachment: ColorAttachment,
+    ) -> Result<Self, String> {
+        let gl = gl_fns.as_ref();
+        let (width, height) = (dimensions.x as i32, dimensions.y as i32);
+        let mut fbo = 0;
+        let mut color = 0;
+        let mut depth_rb = 0;
+
+        let status = unsafe {
+            gl.GenFramebuffers(1, &mut fbo);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, fbo);
+
+            match color_attachment {
+                ColorAttachment::Renderbuffer => {
+                    gl.GenRenderbuffers(1, &mut color);
+                    gl.BindRenderbuffer(gl::RENDERBUFFER, color);
+                    gl.RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width, height);
+                    gl.FramebufferRenderbuffer(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        gl::RENDERBUFFER,
+                        color,
+                    );
+                }
+                ColorAttachment::Texture => {
+                    gl.GenTextures(1, &mut color);
+                    gl.BindTexture(gl::TEXTURE_2D, color);
+                    gl.TexImage2D(
+                        gl::TEXTURE_2D,
+                        0,
+                        gl::RGBA8 as i32,
+                        width,
+                        height,
+                        0,
+                        gl::RGBA,
+                        gl::UNSIGNED_BYTE,
+                        std::ptr::null(),
+                    );
+                    for (pname, param) in [
+                        (gl::TEXTURE_MIN_FILTER, gl::LINEAR),
+                        (gl::TEXTURE_MAG_FILTER, gl::LINEAR),
+                        (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+                        (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+                    ] {
+                        gl.TexParameteri(gl::TEXTURE_2D, pname, param as i32);
+                    }
+                    gl.FramebufferTexture2D(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        gl::TEXTURE_2D,
+                        color,
+                        0,
+                    );
+                }
+            }
+
+            gl.GenRenderbuffers(1, &mut depth_rb);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, depth_rb);
+            gl.RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+            gl.FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_STENCIL_ATTACHMENT,
+                gl::RENDERBUFFER,
+                depth_rb,
+            );
+
+            gl.CheckFramebufferStatus(gl::FRAMEBUFFER)
+        };
+
+        // Built before checking the status so the GL objects get freed on error.
+        let framebuffer = Self {
+            fbo,
+            color,
+            color_attachment,
+            depth_rb,
+            dimensions,
+            gl_fns,
+        };
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!(
+                "Framebuffer with a {color_attachment:?} color incomplete, status 0x{status:x}"
+            ));
+        }
+
+        Ok(framebuffer)
+    }
+
+    /// Makes this the target of subsequent draw calls.
+    fn bind(&self) {
+        unsafe { self.gl_fns.BindFramebuffer(gl::FRAMEBUFFER, self.fbo) }
+    }
+}
+
+impl Drop for FramebufferObject {
+    fn drop(&mut self) {
+        let gl = &self.gl_fns;
+        unsafe {
+            match self.color_attachment {
+                ColorAttachment::Renderbuffer => gl.DeleteRenderbuffers(1, &self.color),
+                ColorAttachment::Texture => gl.DeleteTextures(1, &self.color),
+            }
+            gl.DeleteRenderbuffers(1, &self.depth_rb);
+            gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
+
+/// Offscreen render target read back by the CPU, e.g. to write PNG files.
+pub struct Framebuffer {
+    target: FramebufferObject,
+}
+
+impl Framebuffer {
+    pub fn new(gl_fns: Rc<dyn GlBackend>, dimensions: glam::USizeVec2) -> Result<Self, String> {
+        let target = FramebufferObject::new(gl_fns, dimensions, ColorAttachment::Renderbuffer)?;
+        Ok(Self { target })
+    }
+
+    pub fn bind(&self) {
+        self.target.bind()
+    }
+
+    pub fn get_dimensions(&self) -> glam::USizeVec2 {
+        self.target.dimensions
+    }
+
+    /// Reads back the color attachment, flipped so the first row is the top of
+    /// the image.
+    pub fn read_rgba8(&self) -> image::RgbaImage {
+        let (width, height) = (
+            self.target.dimensions.x as u32,
+            self.target.dimensions.y as u32,
+        );
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+        let gl = &self.target.gl_fns;
+        unsafe {
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.target.fbo);
+            gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl.ReadPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut _,
+            );
+        }
+
+        let image = image::RgbaImage::from_raw(width, height, pixels)
+            .expect("Buffer was sized from the dimensions");
+        image::imageops::flip_vertical(&image)
+    }
+}
+
+/// Offscreen render target that can be sampled, e.g. for reflections.
+///
+/// The texture is linearly filtered without mipmaps, and clamped so slightly
+/// offset lookups at the borders don't wrap around.
+pub struct RenderTexture {
+    target: FramebufferObject,
+}
+
+impl RenderTexture {
+    pub fn new(gl_fns: Rc<dyn GlBackend>, dimensions: glam::USizeVec2) -> Result<Self, String> {
+        let target = FramebufferObject::new(gl_fns, dimensions, ColorAttachment::Texture)?;
+        Ok(Self { target })
+    }
+
+    pub fn bind(&self) {
+        self.target.bind()
+    }
+
+    /// The color texture, to sample what was drawn.
+    pub fn texture(&self) -> gl::types::GLuint {
+        self.target.color
+    }
+
+    pub fn get_dimensions(&self) -> glam::USizeVec2 {
+        self.target.dimensions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    /// Every name generated by `generated` calls is freed by a `deleted` call.
+    fn assert_all_freed(
+        gl: &MockGl,
+        generated: fn(&GlCall) -> Option<&Vec<u32>>,
+        deleted: fn(&GlCall) -> Option<&Vec<u32>>,
+    ) {
+        let calls = gl.calls();
+        let names = |f: fn(&GlCall) -> Option<&Vec<u32>>| -> Vec<u32> {
+            calls.iter().filter_map(f).flatten().copied().collect()
+        };
+        let (generated, deleted) = (names(generated), names(deleted));
+        assert!(!generated.is_empty());
+        for name in &generated {
+            assert!(deleted.contains(name), "{name} leaked");
+        }
+        assert_eq!(generated.len(), deleted.len(), "deleted twice");
+    }
+
+    fn assert_frees_everything(gl: &MockGl) {
+        assert_all_freed(
+            gl,
+            |c| match c {
+                GlCall::GenFramebuffers(n) => Some(n),
+                _ => None,
+            },
+            |c| match c {
+                GlCall::DeleteFramebuffers(n) => Some(n),
+                _ => None,
+            },
+        );
+        assert_all_freed(
+            gl,
+            |c| match c {
+                GlCall::GenRenderbuffers(n) => Some(n),
+                _ => None,
+            },
+            |c| match c {
+                GlCall::DeleteRenderbuffers(n) => Some(n),
+                _ => None,
+            },
+        );
+    }
+
+    #[test]
+    fn framebuffers_free_every_gl_object() {
+        let gl = Rc::new(MockGl::default());
+        let dimensions = glam::USizeVec2::new(4, 2);
+
+        let framebuffer = Framebuffer::new(gl.clone(), dimensions).unwrap();
+        assert_eq!(framebuffer.read_rgba8().dimensions(), (4, 2));
+        drop(framebuffer);
+        assert_frees_everything(&gl);
+        assert_eq!(gl.count(|c| matches!(c, GlCall::GenTextures(_))), 0);
+
+        gl.clear_calls();
+        drop(RenderTexture::new(gl.clone(), dimensions).unwrap());
+        assert_frees_everything(&gl);
+        assert_all_freed(
+            &gl,
+            |c| match c {
+                GlCall::GenTextures(n) => Some(n),
+                _ => None,
+            },
+            |c| match c {
+                GlCall::DeleteTextures(n) => Some(n),
+                _ => None,
+            },
+        );
+    }
+
+    #[test]
+    fn incomplete_framebuffers_are_errors_and_still_freed() {
+        let gl = Rc::new(MockGl::default());
+        gl.fail_framebuffers();
+
+        let dimensions = glam::USizeVec2::new(4, 2);
+        assert!(Framebuffer::new(gl.clone(), dimensions).is_err());
+        assert!(RenderTexture::new(gl.clone(), dimensions).is_err());
+        assert_frees_everything(&gl);
+    }
+
+    #[test]
+    fn render_textures_sample_their_color_attachment() {
+        let gl = Rc::new(MockGl::default());
+        let target = RenderTexture::new(gl.clone(), glam::USizeVec2::new(4, 2)).unwrap();
+
+        let calls = gl.calls();
+        assert!(calls.contains(&GlCall::FramebufferTexture2D {
+            attachment: gl::COLOR_ATTACHMENT0,
+            texture: target.texture(),
+        }));
+        let Some(GlCall::GenFramebuffers(fbo)) = calls.first() else {
+            panic!("The framebuffer comes first, got {calls:?}");
+        };
+        gl.clear_calls();
+        target.bind();
+        assert_eq!(
+            gl.calls(),
+            [GlCall::BindFramebuffer {
+                target: gl::FRAMEBUFFER,
+                framebuffer: fbo[0],
+            }]
+        );
+    }
+}
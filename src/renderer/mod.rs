@@ -5,15 +5,33 @@ use glam::USizeVec2;
 use crate::{
     gl::{self, Gles2},
     helpers::Mat3DUpdate,
-    renderer::shader::{GlslPass, uniform::Uniform},
+    renderer::{
+        culling::Frustum,
+        framebuffer::RenderTexture,
+        shader::{GlslPass, uniform::Uniform},
+        stats::RenderStats,
+        uniform_buffer::{FrameGlobals, LightingGlobals, UniformBuffer},
+    },
 };
 
+pub mod atlas;
+pub mod backend;
+pub mod culling;
+pub mod framebuffer;
 pub mod shader;
+pub mod stats;
+pub mod uniform_buffer;
 
 pub struct Renderer {
     window_dimensions: glam::USizeVec2,
     gl: Rc<gl::Gl>,
     clear_color: glam::Vec3,
+    frame_globals: UniformBuffer<FrameGlobals>,
+    lighting: UniformBuffer<LightingGlobals>,
+    /// Added up over every [`Renderer::draw`] since [`Renderer::take_stats`]
+    stats: RenderStats,
+    /// The view of the bound [`FrameGlobals`], drawables outside are skipped
+    frustum: Option<Frustum>,
 }
 
 impl Renderer {
@@ -37,8 +55,12 @@ impl Renderer {
 
         Self {
             window_dimensions,
+            frame_globals: UniformBuffer::new(gl_fns.clone()),
+            lighting: UniformBuffer::new(gl_fns.clone()),
             gl: gl_fns,
             clear_color,
+            stats: RenderStats::default(),
+            frustum: None,
         }
     }
 
@@ -54,6 +76,14 @@ impl Renderer {
         }
     }
 
+    /// Uploads the state shared by every program for the coming draws, which
+    /// skip what is out of view of `frame`.
+    pub fn bind_globals(&mut self, frame: &FrameGlobals, lighting: &LightingGlobals) {
+        self.frustum = Some(Frustum::from_matrix(frame.projection * frame.view));
+        self.frame_globals.upload(frame);
+        self.lighting.upload(lighting);
+    }
+
     pub fn draw<'a, I>(
         &mut self,
         objects: I,
@@ -63,10 +93,60 @@ impl Renderer {
         I: Iterator<Item = &'a mut dyn GlslPass>,
     {
         for obj in objects {
-            obj.update_draw(mat3d, to_set_uniforms);
+            if let Some(shader) = obj.get_shader_mut() {
+                shader.cull = self.frustum;
+            }
+            self.stats += obj.update_draw(mat3d, to_set_uniforms);
         }
     }
 
+    /// What was drawn since the stats were last taken.
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Returns the stats and starts counting again, e.g. once per frame.
+    pub fn take_stats(&mut self) -> RenderStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// A render target of `dimensions` for [`Renderer::render_to`]. The
+    /// current framebuffer stays bound.
+    pub fn render_texture(&self, dimensions: USizeVec2) -> Result<RenderTexture, String> {
+        let previous = self.bound_framebuffer();
+        let target = RenderTexture::new(self.gl.clone(), dimensions);
+        unsafe { self.gl.BindFramebuffer(gl::FRAMEBUFFER, previous) };
+        target
+    }
+
+    /// Runs `draw` with `target` bound in place of the current framebuffer,
+    /// e.g. to render reflections, then binds the previous one back.
+    pub fn render_to(&mut self, target: &RenderTexture, draw: impl FnOnce(&mut Self)) {
+        let previous = self.bound_framebuffer();
+        let size = target.get_dimensions().as_ivec2();
+        unsafe {
+            target.bind();
+            self.gl.Viewport(0, 0, size.x, size.y);
+        }
+
+        draw(self);
+
+        let window = self.window_dimensions.as_ivec2();
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, previous);
+            self.gl.Viewport(0, 0, window.x, window.y);
+        }
+    }
+
+    fn bound_framebuffer(&self) -> gl::types::GLuint {
+        let mut framebuffer = 0;
+        unsafe {
+            self.gl
+                .GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer)
+        };
+        framebuffer as gl::types::GLuint
+    }
+
     pub fn resize(&mut self, width: i32, height: i32) {
         self.window_dimensions = USizeVec2::new(width as usize, height as usize);
         unsafe {
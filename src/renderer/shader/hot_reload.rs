@@ -0,0 +1,281 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    gl,
+    renderer::{
+        backend::GlBackend,
+        shader::{
+            loader::{SHADERS_DIR, ShaderLoader},
+            program::{ShaderError, ShaderProgram},
+            registry::UniformRegistry,
+        },
+    },
+};
+
+/// The vertex and fragment files a program is built from, with the modification
+/// time of every file read (includes too) at the last build, so edits can be
+/// polled for.
+#[derive(Clone, Debug)]
+pub struct ShaderFiles {
+    loader: ShaderLoader,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ShaderFiles {
+    pub fn new(loader: ShaderLoader, vertex: impl AsRef<Path>, fragment: impl AsRef<Path>) -> Self {
+        let watched = [vertex.as_ref(), fragment.as_ref()]
+            .into_iter()
+            .map(|path| watch(loader.root().join(path)))
+            .collect();
+        Self {
+            loader,
+            vertex: vertex.as_ref().to_owned(),
+            fragment: fragment.as_ref().to_owned(),
+            watched,
+        }
+    }
+
+    /// Builds the program of `vertex` and `fragment` in [`SHADERS_DIR`] and puts
+    /// it in use, the first step of every entity `init`. Returns it with its
+    /// uniforms and the files to watch for hot reloading.
+    pub fn build_program(
+        gl: &dyn GlBackend,
+        vertex: impl AsRef<Path>,
+        fragment: impl AsRef<Path>,
+    ) -> Result<(gl::types::GLuint, UniformRegistry, Self), ShaderError> {
+        let mut files = Self::new(ShaderLoader::new(SHADERS_DIR), vertex, fragment);
+        let program = files.build(gl)?;
+        let uniforms = UniformRegistry::reflect(gl, program);
+        unsafe { gl.UseProgram(program) };
+        Ok((program, uniforms, files))
+    }
+
+    /// Loads and builds the program, then records the files it was made of.
+    ///
+    /// When loading fails the previously watched files are kept, so a broken
+    /// include is retried once any of them is saved again.
+    pub fn build(&mut self, gl: &dyn GlBackend) -> Result<gl::types::GLuint, ShaderError> {
+        let program = ShaderProgram::load(&self.loader, &self.vertex, &self.fragment);
+
+        let mut paths: Vec<PathBuf> = match &program {
+            Ok(program) => program.files().map(Path::to_owned).collect(),
+            Err(_) => self.watched.drain(..).map(|(path, _)| path).collect(),
+        };
+        paths.sort();
+        paths.dedup();
+        self.watched = paths.into_iter().map(watch).collect();
+
+        program?.build(gl)
+    }
+
+    /// Whether a watched file was modified, created or removed since the last build.
+    pub fn changed(&self) -> bool {
+        self.watched
+            .iter()
+            .any(|(path, modified)| modified_at(path) != *modified)
+    }
+}
+
+fn watch(path: PathBuf) -> (PathBuf, Option<SystemTime>) {
+    let modified = modified_at(&path);
+    (path, modified)
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        rc::Rc,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::{
+        entities::tex_square::{Square, TexSquare},
+        gl,
+        helpers::{GlPosition, Mat3DUpdate},
+        renderer::{
+            atlas::UvRect,
+            backend::mock::{GlCall, MockGl},
+            shader::{GlslPass, registry::UniformRegistry, uniform::Uniform},
+        },
+    };
+
+    struct Tint(f32);
+
+    impl Uniform for Tint {
+        fn set(&self, gl: &dyn GlBackend, uniforms: &UniformRegistry) {
+            uniforms.set_f32(gl, "uTint", self.0);
+        }
+    }
+
+    /// A fresh directory with a vertex shader and a fragment shader including
+    /// `common.glsl`.
+    fn shader_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join("glutin-hello-world-hot-reload")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (path, contents) in [
+            ("main.vert", "#version 410 core\nvoid main() {}\n"),
+            (
+                "main.frag",
+                "#version 410 core\n#include \"common.glsl\"\nvoid main() {}\n",
+            ),
+            ("common.glsl", "uniform float uFogNear;\n"),
+        ] {
+            std::fs::write(dir.join(path), contents).unwrap();
+        }
+        dir
+    }
+
+    /// Moves the modification time of `path` forward, as saving it in an editor would.
+    fn touch(path: &Path) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+    }
+
+    fn files(dir: &Path) -> ShaderFiles {
+        ShaderFiles::new(ShaderLoader::new(dir), "main.vert", "main.frag")
+    }
+
+    /// A `TexSquare` inited on `gl`, its program then rebuilt from `dir`.
+    fn square_watching(gl: &Rc<MockGl>, dir: &Path) -> TexSquare {
+        let mut square = TexSquare::new(
+            vec![Square {
+                bottom_left: GlPosition::new(0.0, 0.0, 0.0),
+                top_right: GlPosition::new(1.0, 0.0, 1.0),
+                uv_rect: UvRect::FULL,
+            }],
+            None,
+        );
+        square
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        let mut files = files(dir);
+        let shader = square.get_shader_mut().unwrap();
+        shader.program = files.build(gl.as_ref()).unwrap();
+        shader.files = Some(files);
+        square
+    }
+
+    #[test]
+    fn build_program_leaves_the_program_in_use_and_watched() {
+        let gl = MockGl::default();
+        gl.declare_uniform("model", gl::FLOAT_MAT4);
+        let (program, uniforms, files) =
+            ShaderFiles::build_program(&gl, "tex_square.vert", "tex_square.frag").unwrap();
+
+        assert_eq!(gl.calls().last(), Some(&GlCall::UseProgram(program)));
+        assert!(uniforms.get("model").is_some());
+        assert!(!files.changed());
+    }
+
+    #[test]
+    fn changed_tracks_includes_until_the_next_build() {
+        let dir = shader_dir("changed");
+        let gl = MockGl::default();
+        let mut files = files(&dir);
+        files.build(&gl).unwrap();
+        assert!(!files.changed());
+
+        touch(&dir.join("common.glsl"));
+        assert!(files.changed());
+
+        files.build(&gl).unwrap();
+        assert!(!files.changed());
+    }
+
+    #[test]
+    fn failed_load_keeps_watching_the_previous_files() {
+        let dir = shader_dir("failed_load");
+        let gl = MockGl::default();
+        let mut files = files(&dir);
+        files.build(&gl).unwrap();
+
+        std::fs::remove_file(dir.join("common.glsl")).unwrap();
+        assert!(files.changed());
+        assert!(matches!(files.build(&gl), Err(ShaderError::Load(_))));
+        assert!(!files.changed());
+
+        std::fs::write(dir.join("common.glsl"), "").unwrap();
+        assert!(files.changed());
+        files.build(&gl).unwrap();
+    }
+
+    #[test]
+    fn reload_swaps_the_program_and_restores_init_uniforms() {
+        let dir = shader_dir("reload");
+        let gl = Rc::new(MockGl::default());
+        let mut square = square_watching(&gl, &dir);
+        let old = square.get_shader().unwrap().program;
+        let init_uniforms: Vec<Box<dyn Uniform>> = vec![Box::new(Tint(0.5))];
+        gl.declare_uniform("uTint", gl::FLOAT);
+        gl.declare_uniform("model", gl::FLOAT_MAT4);
+        gl.declare_uniform("tex", gl::SAMPLER_2D);
+
+        assert!(!square.reload_shader(&init_uniforms).unwrap());
+
+        touch(&dir.join("main.frag"));
+        gl.clear_calls();
+        assert!(square.reload_shader(&init_uniforms).unwrap());
+
+        let new = square.get_shader().unwrap().program;
+        assert_ne!(new, old);
+        let calls = gl.calls();
+        assert!(calls.contains(&GlCall::DeleteProgram(old)));
+        assert!(calls.contains(&GlCall::UseProgram(new)));
+        let set_location = |call: &GlCall| match call {
+            GlCall::Uniform1f { location, .. }
+            | GlCall::Uniform1i { location, .. }
+            | GlCall::UniformMatrix4fv { location, .. } => Some(*location),
+            _ => None,
+        };
+        let set: Vec<_> = calls.iter().filter_map(set_location).collect();
+        for name in ["uTint", "model", "tex"] {
+            assert!(
+                set.contains(&gl.uniform_location(name).unwrap()),
+                "{name} should be set on the new program"
+            );
+        }
+        assert_eq!(
+            gl.count(
+                |c| matches!(c, GlCall::UniformBlockBinding { program, .. } if *program == new)
+            ),
+            2,
+            "the new program should read the shared uniform blocks"
+        );
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_old_program() {
+        let dir = shader_dir("failed_reload");
+        let gl = Rc::new(MockGl::default());
+        let mut square = square_watching(&gl, &dir);
+        let old = square.get_shader().unwrap().program;
+
+        touch(&dir.join("main.frag"));
+        gl.fail_link("error: undefined symbol");
+        gl.clear_calls();
+
+        assert!(matches!(
+            square.reload_shader(&[]),
+            Err(ShaderError::Link { .. })
+        ));
+        assert_eq!(square.get_shader().unwrap().program, old);
+        assert_eq!(gl.count(|c| *c == GlCall::DeleteProgram(old)), 0);
+        assert_eq!(gl.count(|c| matches!(c, GlCall::UseProgram(_))), 0);
+        // Not retried until the files change again
+        assert!(!square.reload_shader(&[]).unwrap());
+    }
+}
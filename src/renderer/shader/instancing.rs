@@ -0,0 +1,150 @@
+use glam::{Mat4, Vec3};
+
+use crate::{
+    gl,
+    renderer::{backend::GlBackend, culling::Aabb, shader::uniform::ProgramId},
+};
+
+/// Per instance attributes of the instanced entities: where a copy of the
+/// mesh is drawn and its color.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instance {
+    pub model: Mat4,
+    pub color: Vec3,
+}
+
+impl Instance {
+    /// A copy moved to `position`.
+    pub fn at(position: Vec3, color: Vec3) -> Self {
+        Self {
+            model: Mat4::from_translation(position),
+            color,
+        }
+    }
+}
+
+/// Around every copy of a mesh bounded by `mesh` moved by one of `models`,
+/// `None` if the mesh isn't bounded or there are no copies.
+pub fn instances_bounds(
+    mesh: Option<Aabb>,
+    models: impl IntoIterator<Item = Mat4>,
+) -> Option<Aabb> {
+    let mesh = mesh?;
+    models
+        .into_iter()
+        .map(|model| mesh.transformed(&model))
+        .reduce(Aabb::union)
+}
+
+/// Uploads `instances` to a new buffer and points the `instanceModel` and
+/// `instanceColor` attributes of `program` at it, advancing once per instance.
+/// Returns the buffer.
+///
+/// # Safety
+/// FFI calls, the vertex array the attributes belong to must be bound.
+pub unsafe fn upload_instances(
+    gl: &dyn GlBackend,
+    program: ProgramId,
+    instances: &[Instance],
+) -> gl::types::GLuint {
+    let mut instance_vbo = std::mem::zeroed();
+    gl.GenBuffers(1, &mut instance_vbo);
+    gl.BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
+    gl.BufferData(
+        gl::ARRAY_BUFFER,
+        std::mem::size_of_val(instances) as gl::types::GLsizeiptr,
+        instances.as_ptr() as *const _,
+        gl::STATIC_DRAW,
+    );
+
+    let stride = std::mem::size_of::<Instance>() as gl::types::GLsizei;
+    // A mat4 attribute is 4 vec4 columns at consecutive locations
+    let model = attribute(gl, program, c"instanceModel");
+    let columns = (0..4).map(|column| {
+        let offset =
+            std::mem::offset_of!(Instance, model) + column * std::mem::size_of::<[f32; 4]>();
+        (model + column as gl::types::GLuint, 4, offset)
+    });
+    let color = (
+        attribute(gl, program, c"instanceColor"),
+        3,
+        std::mem::offset_of!(Instance, color),
+    );
+    for (location, size, offset) in columns.chain([color]) {
+        gl.VertexAttribPointer(location, size, gl::FLOAT, 0, stride, offset as *const _);
+        gl.EnableVertexAttribArray(location);
+        gl.VertexAttribDivisor(location, 1);
+    }
+
+    instance_vbo
+}
+
+/// The location of the vertex attribute `name` of `program`.
+///
+/// # Safety
+/// FFI call
+///
+/// # Panics
+/// If `program` has no such active attribute.
+pub(crate) unsafe fn attribute(
+    gl: &dyn GlBackend,
+    program: ProgramId,
+    name: &std::ffi::CStr,
+) -> gl::types::GLuint {
+    let location = gl.GetAttribLocation(program, name.as_ptr() as *const _);
+    assert_ne!(location, -1, "{name:?} should be an attribute");
+    location as gl::types::GLuint
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    #[test]
+    fn every_instance_attribute_advances_once_per_instance() {
+        let gl = Rc::new(MockGl::default());
+        let instances = [
+            Instance::at(Vec3::ZERO, Vec3::X),
+            Instance::at(Vec3::Y, Vec3::Z),
+        ];
+        let vbo = unsafe { upload_instances(gl.as_ref(), 1, &instances) };
+
+        let calls = gl.calls();
+        assert!(calls.contains(&GlCall::BindBuffer {
+            target: gl::ARRAY_BUFFER,
+            buffer: vbo
+        }));
+        assert!(calls.contains(&GlCall::BufferData {
+            target: gl::ARRAY_BUFFER,
+            size: 2 * std::mem::size_of::<Instance>() as gl::types::GLsizeiptr,
+        }));
+        let divisors = gl.count(|c| matches!(c, GlCall::VertexAttribDivisor { divisor: 1, .. }));
+        assert_eq!(divisors, 5, "4 model matrix columns and the color");
+    }
+
+    #[test]
+    fn instances_are_bounded_together() {
+        let mesh = Aabb {
+            min: Vec3::splat(-0.5),
+            max: Vec3::splat(0.5),
+        };
+        let instances = [
+            Instance::at(Vec3::new(-2.0, 0.0, 0.0), Vec3::ONE),
+            Instance::at(Vec3::new(3.0, 1.0, 0.0), Vec3::ONE),
+        ];
+        let models = instances.map(|instance| instance.model);
+        assert_eq!(
+            instances_bounds(Some(mesh), models),
+            Some(Aabb {
+                min: Vec3::new(-2.5, -0.5, -0.5),
+                max: Vec3::new(3.5, 1.5, 0.5),
+            })
+        );
+        assert_eq!(instances_bounds(Some(mesh), []), None);
+        assert_eq!(instances_bounds(None, models), None);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Where the app looks for `.vert`/`.frag` files and their includes.
+pub const SHADERS_DIR: &str = "./assets/shaders";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// A shader stage source with its includes resolved.
+///
+/// `#line` directives in `code` use the index into `files` as source string
+/// number, so driver errors can be mapped back to the file they come from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedShader {
+    pub code: String,
+    /// Every file read, in source string number order; the first one is the root.
+    pub files: Vec<SourceFile>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// `#include` without a quoted path.
+    MalformedInclude { path: PathBuf, line: usize },
+    /// The include chain, starting and ending with the same file.
+    IncludeCycle { chain: Vec<PathBuf> },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, source } => write!(f, "Could not read {path:?}: {source}"),
+            LoadError::MalformedInclude { path, line } => {
+                write!(f, "{path:?}:{line}: expected `#include \"file\"`")
+            }
+            LoadError::IncludeCycle { chain } => {
+                write!(f, "Include cycle: ")?;
+                let chain: Vec<String> = chain.iter().map(|p| format!("{p:?}")).collect();
+                write!(f, "{}", chain.join(" -> "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Reads shader files from a directory, resolving `#include "file"` directives
+/// relative to the including file and injecting `#define`s after `#version`.
+///
+/// Each file is inlined once per stage, like with include guards: a later
+/// `#include` of a file already expanded is dropped.
+#[derive(Clone, Debug)]
+pub struct ShaderLoader {
+    root: PathBuf,
+    defines: Vec<(String, String)>,
+}
+
+impl ShaderLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            defines: vec![],
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn with_define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defines.push((name.into(), value.into()));
+        self
+    }
+
+    /// Loads `path`, relative to the loader root.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<LoadedShader, LoadError> {
+        let mut loaded = LoadedShader {
+            code: String::new(),
+            files: vec![],
+        };
+        self.expand(
+            &self.root.join(path),
+            &mut vec![],
+            &mut HashSet::new(),
+            &mut loaded,
+        )?;
+        Ok(loaded)
+    }
+
+    fn expand(
+        &self,
+        path: &Path,
+        stack: &mut Vec<PathBuf>,
+        expanded: &mut HashSet<PathBuf>,
+        loaded: &mut LoadedShader,
+    ) -> Result<(), LoadError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| LoadError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let canonical = canonical_path(path);
+        if let Some(start) = stack.iter().position(|p| *p == canonical) {
+            let mut chain = stack[start..].to_vec();
+            chain.push(canonical);
+            return Err(LoadError::IncludeCycle { chain });
+        }
+        stack.push(canonical);
+
+        let id = loaded.files.len();
+        let is_root = id == 0;
+        loaded.files.push(SourceFile {
+            path: path.to_owned(),
+            contents: contents.clone(),
+        });
+
+        if is_root && !contents.lines().any(is_version) {
+            self.write_defines(&mut loaded.code);
+            loaded.code.push_str(&format!("#line 1 {id}\n"));
+        }
+
+        for (i, line) in contents.lines().enumerate() {
+            let number = i + 1;
+            let trimmed = line.trim_start();
+
+            if let Some(rest) = trimmed.strip_prefix("#include") {
+                let included = rest
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|r| r.strip_suffix('"'))
+                    .ok_or_else(|| LoadError::MalformedInclude {
+                        path: path.to_owned(),
+                        line: number,
+                    })?;
+                let included = path.parent().unwrap_or(Path::new("")).join(included);
+                if expanded.contains(&canonical_path(&included)) {
+                    // Kept as an empty line, the following ones keep their numbers
+                    loaded.code.push('\n');
+                    continue;
+                }
+
+                loaded
+                    .code
+                    .push_str(&format!("#line 1 {}\n", loaded.files.len()));
+                self.expand(&included, stack, expanded, loaded)?;
+                // GLSL >= 3.30: the line after `#line N` is line N
+                loaded
+                    .code
+                    .push_str(&format!("#line {} {id}\n", number + 1));
+                continue;
+            }
+
+            loaded.code.push_str(line);
+            loaded.code.push('\n');
+
+            if is_root && is_version(line) {
+                self.write_defines(&mut loaded.code);
+                loaded
+                    .code
+                    .push_str(&format!("#line {} {id}\n", number + 1));
+            }
+        }
+
+        // Only once done, an include back into the stack is still a cycle
+        if let Some(canonical) = stack.pop() {
+            expanded.insert(canonical);
+        }
+        Ok(())
+    }
+
+    fn write_defines(&self, code: &mut String) {
+        for (name, value) in &self.defines {
+            code.push_str(&format!("#define {name} {value}\n"));
+        }
+    }
+}
+
+/// Canonical so `a/../b.glsl` and `b.glsl` are the same file.
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+fn is_version(line: &str) -> bool {
+    line.trim_start().starts_with("#version")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory with the given files in it.
+    fn shader_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join("glutin-hello-world-shader-loader")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn include_is_inlined_with_line_directives() {
+        let dir = shader_dir(
+            "include",
+            &[
+                (
+                    "main.frag",
+                    "#version 410 core\n#include \"common.glsl\"\nvoid main() {}\n",
+                ),
+                ("common.glsl", "float a;\nfloat b;\n"),
+            ],
+        );
+
+        let loaded = ShaderLoader::new(&dir).load("main.frag").unwrap();
+
+        assert_eq!(
+            loaded.code,
+            "#version 410 core\n#line 2 0\n#line 1 1\nfloat a;\nfloat b;\n#line 3 0\nvoid main() {}\n"
+        );
+        assert_eq!(loaded.files.len(), 2);
+        assert_eq!(loaded.files[1].path, dir.join("common.glsl"));
+    }
+
+    #[test]
+    fn nested_includes_resolve_relative_to_the_including_file() {
+        let dir = shader_dir(
+            "nested",
+            &[
+                ("main.vert", "#version 410 core\n#include \"lib/a.glsl\"\n"),
+                ("lib/a.glsl", "#include \"b.glsl\"\nfloat a;\n"),
+                ("lib/b.glsl", "float b;\n"),
+            ],
+        );
+
+        let loaded = ShaderLoader::new(&dir).load("main.vert").unwrap();
+
+        assert_eq!(
+            loaded.code,
+            "#version 410 core\n#line 2 0\n#line 1 1\n#line 1 2\nfloat b;\n#line 2 1\nfloat a;\n#line 3 0\n"
+        );
+    }
+
+    #[test]
+    fn defines_are_injected_after_version() {
+        let dir = shader_dir(
+            "defines",
+            &[("main.frag", "\n#version 410 core\nvoid main() {}\n")],
+        );
+
+        let loaded = ShaderLoader::new(&dir)
+            .with_define("FOG", "1")
+            .with_define("SHININESS", "32")
+            .load("main.frag")
+            .unwrap();
+
+        assert_eq!(
+            loaded.code,
+            "\n#version 410 core\n#define FOG 1\n#define SHININESS 32\n#line 3 0\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let dir = shader_dir(
+            "cycle",
+            &[
+                ("main.frag", "#include \"a.glsl\"\n"),
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "#include \"a.glsl\"\n"),
+            ],
+        );
+
+        let err = ShaderLoader::new(&dir).load("main.frag").unwrap_err();
+
+        let LoadError::IncludeCycle { chain } = err else {
+            panic!("Expected a cycle, got {err}");
+        };
+        let names: Vec<_> = chain.iter().map(|p| p.file_name().unwrap()).collect();
+        assert_eq!(names, ["a.glsl", "b.glsl", "a.glsl"]);
+    }
+
+    #[test]
+    fn files_reached_twice_are_included_once() {
+        let dir = shader_dir(
+            "diamond",
+            &[
+                (
+                    "main.frag",
+                    "#include \"lighting.glsl\"\n#include \"./globals.glsl\"\nvoid main() {}\n",
+                ),
+                ("lighting.glsl", "#include \"globals.glsl\"\nfloat light;\n"),
+                ("globals.glsl", "float globals;\n"),
+            ],
+        );
+
+        let loaded = ShaderLoader::new(&dir).load("main.frag").unwrap();
+
+        assert_eq!(loaded.code.matches("float globals;").count(), 1);
+        assert_eq!(
+            loaded.code,
+            "#line 1 0\n#line 1 1\n#line 1 2\nfloat globals;\n#line 2 1\nfloat light;\n#line 2 0\n\nvoid main() {}\n"
+        );
+        assert_eq!(loaded.files.len(), 3);
+    }
+
+    #[test]
+    fn missing_and_malformed_includes_are_errors() {
+        let dir = shader_dir(
+            "errors",
+            &[
+                ("missing.frag", "#include \"nope.glsl\"\n"),
+                ("malformed.frag", "float a;\n#include nope.glsl\n"),
+            ],
+        );
+        let loader = ShaderLoader::new(&dir);
+
+        assert!(matches!(
+            loader.load("missing.frag"),
+            Err(LoadError::Io { path, .. }) if path == dir.join("nope.glsl")
+        ));
+        assert!(matches!(
+            loader.load("malformed.frag"),
+            Err(LoadError::MalformedInclude { line: 2, .. })
+        ));
+    }
+}
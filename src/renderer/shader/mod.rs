@@ -1,10 +1,23 @@
 use crate::{
-    gl::{self, Gles2},
+    gl,
     helpers::Mat3DUpdate,
-    renderer::shader::uniform::Uniform,
+    renderer::{
+        backend::GlBackend,
+        culling::{Aabb, Frustum},
+        shader::{
+            hot_reload::ShaderFiles, program::ShaderError, registry::UniformRegistry,
+            uniform::Uniform,
+        },
+        stats::RenderStats,
+    },
 };
-use std::{ffi::CStr, rc::Rc};
+use std::rc::Rc;
 
+pub mod hot_reload;
+pub mod instancing;
+pub mod loader;
+pub mod program;
+pub mod registry;
 pub mod uniform;
 
 #[derive(Clone, Debug, Default)]
@@ -13,6 +26,8 @@ pub struct IndexedElements {
     pub vbo: gl::types::GLuint,
     pub ebo: gl::types::GLuint,
     pub index_count: usize,
+    /// Around the vertices, in model space. `None` is never culled.
+    pub bounds: Option<Aabb>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -26,12 +41,107 @@ pub struct Array {
     pub offset: usize,
     /// Count of vertices used per call to draw triangles
     pub count: usize,
+    /// Around the vertices, in model space. `None` is never culled.
+    pub bounds: Option<Aabb>,
+}
+
+/// `vertex_count` vertices drawn as `TRIANGLES` once per instance in a single
+/// call, the per instance attributes read from `instance_vbo`.
+#[derive(Clone, Debug, Default)]
+pub struct InstancedArrays {
+    pub vao: gl::types::GLuint,
+    pub vbo: gl::types::GLuint,
+    pub instance_vbo: gl::types::GLuint,
+    pub vertex_count: usize,
+    pub instance_count: usize,
+    /// Around every instance, in model space.
+    pub bounds: Option<Aabb>,
 }
 
 #[derive(Clone)]
 pub enum Drawable {
     Indexed(IndexedElements),
     Array(Array),
+    InstancedArrays(InstancedArrays),
+}
+
+impl Drawable {
+    /// What the drawable covers in model space, `None` if unknown.
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Drawable::Indexed(indexed_elements) => indexed_elements.bounds,
+            Drawable::Array(array) => array.bounds,
+            Drawable::InstancedArrays(instanced) => instanced.bounds,
+        }
+    }
+
+    /// Deletes the buffers and vertex array.
+    /// # Safety
+    /// FFI call
+    pub unsafe fn delete(&self, gl: &dyn GlBackend) {
+        match self {
+            Drawable::Indexed(indexed_elements) => {
+                gl.DeleteBuffers(1, &indexed_elements.ebo);
+                gl.DeleteBuffers(1, &indexed_elements.vbo);
+                gl.DeleteVertexArrays(1, &indexed_elements.vao);
+            }
+            Drawable::Array(array) => {
+                gl.DeleteBuffers(1, &array.vbo);
+                gl.DeleteVertexArrays(1, &array.vao);
+            }
+            Drawable::InstancedArrays(instanced) => {
+                gl.DeleteBuffers(1, &instanced.instance_vbo);
+                gl.DeleteBuffers(1, &instanced.vbo);
+                gl.DeleteVertexArrays(1, &instanced.vao);
+            }
+        }
+    }
+
+    /// Binds the vertex array and draws all of it as triangles.
+    /// # Safety
+    /// FFI calls, the program must be in use
+    pub unsafe fn draw(&self, gl: &dyn GlBackend) -> RenderStats {
+        match self {
+            Drawable::Indexed(indexed_elements) => {
+                gl.BindVertexArray(indexed_elements.vao);
+                gl.BindBuffer(gl::ARRAY_BUFFER, indexed_elements.vbo);
+                gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, indexed_elements.ebo);
+                gl.DrawElements(
+                    gl::TRIANGLES,
+                    indexed_elements.index_count as i32,
+                    gl::UNSIGNED_INT,
+                    std::ptr::null(),
+                );
+                RenderStats::call(indexed_elements.index_count / 3)
+            }
+            Drawable::Array(array) => {
+                gl.BindVertexArray(array.vao);
+                gl.BindBuffer(gl::ARRAY_BUFFER, array.vbo);
+                for i in 0..array.len {
+                    gl.DrawArrays(
+                        gl::TRIANGLE_STRIP,
+                        (i * array.offset) as i32,
+                        array.count as i32,
+                    );
+                }
+                RenderStats {
+                    draw_calls: array.len,
+                    triangles: array.len * array.count.saturating_sub(2),
+                    culled: 0,
+                }
+            }
+            Drawable::InstancedArrays(instanced) => {
+                gl.BindVertexArray(instanced.vao);
+                gl.DrawArraysInstanced(
+                    gl::TRIANGLES,
+                    0,
+                    instanced.vertex_count as i32,
+                    instanced.instance_count as i32,
+                );
+                RenderStats::call(instanced.vertex_count / 3 * instanced.instance_count)
+            }
+        }
+    }
 }
 
 #[derive(Default, Clone)]
@@ -43,13 +153,66 @@ pub struct Tex {
 #[derive(Clone)]
 pub struct Shader {
     pub program: gl::types::GLuint,
+    pub uniforms: UniformRegistry,
     pub drawables: Vec<Drawable>,
     pub tex: Option<Tex>,
     pub model_transform: glam::Mat4,
-    pub gl_fns: Rc<Gles2>,
+    pub gl_fns: Rc<dyn GlBackend>,
+    /// Where `program` was built from, for hot reloading. `None` for inline sources.
+    pub files: Option<ShaderFiles>,
+    /// The view to skip drawables outside of, in world space. `None` draws
+    /// everything.
+    pub cull: Option<Frustum>,
 }
 
 impl Shader {
+    /// Draws `drawables` with `program`, moved by `model_transform`. Reloaded
+    /// when `files` change, culling nothing until `cull` is set.
+    pub fn new(
+        gl_fns: Rc<dyn GlBackend>,
+        program: gl::types::GLuint,
+        uniforms: UniformRegistry,
+        files: ShaderFiles,
+        model_transform: glam::Mat4,
+        drawables: Vec<Drawable>,
+    ) -> Self {
+        Self {
+            program,
+            uniforms,
+            drawables,
+            tex: None,
+            model_transform,
+            gl_fns,
+            files: Some(files),
+            cull: None,
+        }
+    }
+
+    /// Binds `tex` before drawing.
+    pub fn with_tex(mut self, tex: Option<Tex>) -> Self {
+        self.tex = tex;
+        self
+    }
+
+    /// Whether something within `bounds`, in model space, may be in view of
+    /// `cull`.
+    pub fn sees(&self, bounds: Option<Aabb>) -> bool {
+        match (&self.cull, bounds) {
+            (Some(frustum), Some(bounds)) => {
+                frustum.intersects(&bounds.transformed(&self.model_transform))
+            }
+            _ => true,
+        }
+    }
+
+    /// Around all of `drawables` in model space, `None` if one of them isn't
+    /// bounded.
+    pub fn bounds(&self) -> Option<Aabb> {
+        let mut all = self.drawables.iter().map(Drawable::bounds);
+        let first = all.next()??;
+        all.try_fold(first, |union, bounds| Some(union.union(bounds?)))
+    }
+
     /// # Safety
     /// FFI call
     pub unsafe fn use_program(&self) {
@@ -64,17 +227,7 @@ impl Shader {
 
         // Delete buffers
         for drawable in &self.drawables {
-            match drawable {
-                Drawable::Indexed(indexed_elements) => {
-                    gl.DeleteBuffers(1, &indexed_elements.ebo);
-                    gl.DeleteBuffers(1, &indexed_elements.vbo);
-                    gl.DeleteBuffers(1, &indexed_elements.vao);
-                }
-                Drawable::Array(array) => {
-                    gl.DeleteBuffers(1, &array.vbo);
-                    gl.DeleteBuffers(1, &array.vao);
-                }
-            }
+            drawable.delete(gl.as_ref());
         }
 
         // Delete Texture
@@ -94,10 +247,10 @@ pub trait GlslPass {
     // Create GPU resources; should be idempotent or guarded by internal state.
     fn init(
         &mut self,
-        gl_fns: Rc<Gles2>,
+        gl_fns: Rc<dyn GlBackend>,
         mat3d: Mat3DUpdate,
         initial_uniforms: &[Box<dyn Uniform>],
-    );
+    ) -> Result<(), ShaderError>;
 
     // Per-frame updates (uniforms, buffers, animations). Caller ensures active shader
     fn update(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]);
@@ -105,10 +258,10 @@ pub trait GlslPass {
     /// Issue draw calls. Caller ensures active shader
     /// # Safety
     /// FFI calls
-    unsafe fn draw(&self) {
+    unsafe fn draw(&self) -> RenderStats {
         let Some(glsl_pass) = self.get_shader() else {
             log::warn!("Tried to render /TODO: name/ before init");
-            return;
+            return RenderStats::default();
         };
         let gl = &glsl_pass.gl_fns;
 
@@ -116,74 +269,316 @@ pub trait GlslPass {
             gl.BindTexture(tex.target, tex.tex);
         }
 
-        for drawable in &glsl_pass.drawables {
-            match drawable {
-                Drawable::Indexed(indexed_elements) => {
-                    gl.BindVertexArray(indexed_elements.vao);
-                    gl.BindBuffer(gl::ARRAY_BUFFER, indexed_elements.vbo);
-                    gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, indexed_elements.ebo);
-                    gl.DrawElements(
-                        gl::TRIANGLES,
-                        indexed_elements.index_count as i32,
-                        gl::UNSIGNED_INT,
-                        std::ptr::null(),
-                    );
-                }
-                Drawable::Array(array) => {
-                    gl.BindVertexArray(array.vao);
-                    gl.BindBuffer(gl::ARRAY_BUFFER, array.vbo);
-                    for i in 0..array.len {
-                        gl.DrawArrays(
-                            gl::TRIANGLE_STRIP,
-                            (i * array.offset) as i32,
-                            array.count as i32,
-                        );
-                    }
+        glsl_pass
+            .drawables
+            .iter()
+            .map(|drawable| {
+                if glsl_pass.sees(drawable.bounds()) {
+                    drawable.draw(gl.as_ref())
+                } else {
+                    RenderStats::culled(1)
                 }
-            }
-        }
+            })
+            .sum()
     }
 
     // gl FFI getter
     fn get_shader(&self) -> Option<&Shader>;
 
-    fn update_draw(&mut self, mat3d: Mat3DUpdate, to_set_uniforms: &[Box<dyn Uniform>]) {
+    fn get_shader_mut(&mut self) -> Option<&mut Shader>;
+
+    /// Sets the uniforms `init` puts on the program besides the model and the
+    /// init uniforms, e.g. a fixed color.
+    fn set_own_uniforms(&self, _gl: &dyn GlBackend, _uniforms: &UniformRegistry) {}
+
+    /// Gives a new program the model of `mat3d`, the identity without one, the
+    /// `init_uniforms` and [`GlslPass::set_own_uniforms`]. Returns the model.
+    fn set_init_uniforms(
+        &self,
+        gl: &dyn GlBackend,
+        uniforms: &UniformRegistry,
+        mat3d: Mat3DUpdate,
+        init_uniforms: &[Box<dyn Uniform>],
+    ) -> glam::Mat4 {
+        let mat3d = mat3d.as_init();
+        mat3d.set_uniforms(gl, uniforms);
+        for uniform in init_uniforms {
+            uniform.set(gl, uniforms);
+        }
+        self.set_own_uniforms(gl, uniforms);
+        mat3d
+            .model
+            .expect("mat3d as_init should be at least IDENTITY")
+    }
+
+    /// Rebuilds the program if one of its shader files changed since the last
+    /// build, and gives the new one the uniforms `init` set.
+    ///
+    /// The old program is only replaced once the new one links; on error it
+    /// stays in use and the build is retried after the next file change.
+    /// Returns whether the program was replaced.
+    fn reload_shader(&mut self, init_uniforms: &[Box<dyn Uniform>]) -> Result<bool, ShaderError> {
+        let Some(shader) = self.get_shader_mut() else {
+            return Ok(false);
+        };
+        let Some(files) = shader.files.as_mut().filter(|files| files.changed()) else {
+            return Ok(false);
+        };
+        let gl = shader.gl_fns.clone();
+        let program = files.build(gl.as_ref())?;
+
+        unsafe {
+            gl.DeleteProgram(shader.program);
+            gl.UseProgram(program);
+        }
+        shader.program = program;
+        shader.uniforms = UniformRegistry::reflect(gl.as_ref(), program);
+
+        if let Some(shader) = self.get_shader() {
+            let mat3d = Mat3DUpdate {
+                model: Some(shader.model_transform),
+            };
+            self.set_init_uniforms(gl.as_ref(), &shader.uniforms, mat3d, init_uniforms);
+        }
+
+        Ok(true)
+    }
+
+    fn update_draw(
+        &mut self,
+        mat3d: Mat3DUpdate,
+        to_set_uniforms: &[Box<dyn Uniform>],
+    ) -> RenderStats {
         let Some(shader) = self.get_shader() else {
             log::warn!("Called update_draw on unitialized GlslPass");
-            return;
+            return RenderStats::default();
         };
         unsafe { shader.use_program() }
         self.update(mat3d, to_set_uniforms);
-        unsafe { self.draw() };
+
+        // Skip the whole pass when nothing of it is in view
+        let Some(shader) = self.get_shader() else {
+            return RenderStats::default();
+        };
+        if !shader.sees(shader.bounds()) {
+            return RenderStats::culled(shader.drawables.len());
+        }
+        unsafe { self.draw() }
     }
 }
 
-/// # Safety
-/// its doing ffi
-pub unsafe fn create_shader(
-    gl: &gl::Gl,
-    shader: gl::types::GLenum,
-    source: &[u8],
-) -> gl::types::GLuint {
-    unsafe {
-        let shader = gl.CreateShader(shader);
-        gl.ShaderSource(
-            shader,
-            1,
-            [source.as_ptr().cast()].as_ptr(),
-            std::ptr::null(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{
+            block_highlight::BlockHighlight,
+            hello_triangle::HelloTriangle,
+            tex_cube::TexCube,
+            tex_square::{Square, TexSquare},
+            utah_teapot::UtahTeapot,
+        },
+        helpers::GlPosition,
+        renderer::{
+            atlas::UvRect,
+            backend::mock::{GlCall, MockGl},
+        },
+    };
+
+    fn square(x: f32) -> Square {
+        Square {
+            bottom_left: GlPosition::new(x, 0.0, 0.0),
+            top_right: GlPosition::new(x + 1.0, 0.0, 1.0),
+            uv_rect: UvRect::FULL,
+        }
+    }
+
+    fn assert_frees_everything(gl: &MockGl, pass: impl GlslPass) {
+        drop(pass);
+
+        let calls = gl.calls();
+        let generated = |f: fn(&GlCall) -> Option<&Vec<u32>>| -> Vec<u32> {
+            calls.iter().filter_map(f).flatten().copied().collect()
+        };
+
+        let vaos = generated(|c| match c {
+            GlCall::GenVertexArrays(n) => Some(n),
+            _ => None,
+        });
+        let buffers = generated(|c| match c {
+            GlCall::GenBuffers(n) => Some(n),
+            _ => None,
+        });
+        let deleted_vaos = generated(|c| match c {
+            GlCall::DeleteVertexArrays(n) => Some(n),
+            _ => None,
+        });
+        let deleted_buffers = generated(|c| match c {
+            GlCall::DeleteBuffers(n) => Some(n),
+            _ => None,
+        });
+
+        assert!(!vaos.is_empty());
+        for vao in &vaos {
+            assert!(deleted_vaos.contains(vao), "VAO {vao} leaked");
+        }
+        for buffer in &buffers {
+            assert!(deleted_buffers.contains(buffer), "Buffer {buffer} leaked");
+        }
+        for program in calls.iter().filter_map(|c| match c {
+            GlCall::CreateProgram(p) => Some(p),
+            _ => None,
+        }) {
+            assert!(calls.contains(&GlCall::DeleteProgram(*program)));
+        }
+    }
+
+    #[test]
+    fn delete_gl_frees_indexed_drawables() {
+        let gl = Rc::new(MockGl::default());
+        let mut squares = TexSquare::new(vec![square(0.0), square(1.0)], None);
+        squares
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+
+        assert_frees_everything(&gl, squares);
+    }
+
+    #[test]
+    fn delete_gl_frees_array_drawables() {
+        let gl = Rc::new(MockGl::default());
+        let mut triangle = HelloTriangle::new((GlPosition::ZERO, 1.0));
+        triangle
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        let drawables = &triangle.get_shader().unwrap().drawables;
+        assert!(matches!(drawables.as_slice(), [Drawable::Array(_)]));
+        assert_frees_everything(&gl, triangle);
+
+        let gl = Rc::new(MockGl::default());
+        let mut highlight = BlockHighlight::new(1.0);
+        highlight
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        assert_frees_everything(&gl, highlight);
+    }
+
+    #[test]
+    fn delete_gl_frees_instanced_arrays() {
+        let gl = Rc::new(MockGl::default());
+        let mut cubes = TexCube::new(vec![GlPosition::ZERO, GlPosition::X], 1.0, None);
+        cubes.init(gl.clone(), Mat3DUpdate::default(), &[]).unwrap();
+        assert_frees_everything(&gl, cubes);
+
+        let gl = Rc::new(MockGl::default());
+        let mut teapot = UtahTeapot::new(GlPosition::ZERO, glam::Vec3::X);
+        teapot
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        assert_frees_everything(&gl, teapot);
+    }
+
+    #[test]
+    fn draw_issues_one_draw_elements_for_all_squares() {
+        let gl = Rc::new(MockGl::default());
+        let mut squares = TexSquare::new((0..5).map(|i| square(i as f32)).collect(), None);
+        squares
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        gl.clear_calls();
+
+        let stats = unsafe { squares.draw() };
+
+        let draws: Vec<GlCall> = gl
+            .calls()
+            .into_iter()
+            .filter(|c| matches!(c, GlCall::DrawArrays { .. } | GlCall::DrawElements { .. }))
+            .collect();
+        assert_eq!(
+            draws,
+            [GlCall::DrawElements {
+                mode: gl::TRIANGLES,
+                count: 30,
+            }]
+        );
+        assert_eq!(
+            stats,
+            RenderStats {
+                draw_calls: 1,
+                triangles: 10,
+                culled: 0,
+            }
         );
-        gl.CompileShader(shader);
-        // print compile errors if any
-        let mut success: gl::types::GLint = 0;
-        gl.GetShaderiv(shader, gl::COMPILE_STATUS, &mut success as *mut i32);
-        if success == 0 {
-            let mut info_log = [0i8; 512];
-            gl.GetShaderInfoLog(shader, 512, std::ptr::null_mut(), &mut info_log as *mut i8);
-            let cstr = CStr::from_ptr(info_log.as_mut_ptr());
-            log::error!("Error compiling SHADER: {:?}", cstr.to_str());
-        }
-
-        shader
+    }
+
+    #[test]
+    fn update_draw_skips_passes_out_of_view() {
+        let gl = Rc::new(MockGl::default());
+        let mut squares = TexSquare::new((0..3).map(|i| square(i as f32)).collect(), None);
+        squares
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        let draws = |gl: &MockGl| gl.count(|c| matches!(c, GlCall::DrawElements { .. }));
+
+        // Looking down -z from above the squares, then away from them
+        let projection = glam::Mat4::perspective_rh_gl(1.0, 1.0, 0.1, 10.0);
+        let eye = glam::Vec3::new(1.0, 5.0, 0.0);
+        let down = glam::Mat4::look_at_rh(eye, glam::Vec3::new(1.0, 0.0, 0.5), glam::Vec3::NEG_Z);
+        let up = glam::Mat4::look_at_rh(eye, eye + glam::Vec3::Y, glam::Vec3::NEG_Z);
+
+        squares.get_shader_mut().unwrap().cull = Some(Frustum::from_matrix(projection * down));
+        gl.clear_calls();
+        let stats = squares.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!((draws(&gl), stats.culled), (1, 0));
+
+        squares.get_shader_mut().unwrap().cull = Some(Frustum::from_matrix(projection * up));
+        gl.clear_calls();
+        let stats = squares.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!((draws(&gl), stats.culled), (0, 1));
+        assert_eq!(stats.draw_calls, 0);
+    }
+
+    #[test]
+    fn update_draw_culls_where_the_model_moved_the_pass() {
+        let gl = Rc::new(MockGl::default());
+        let mut teapot = UtahTeapot::new(GlPosition::ZERO, glam::Vec3::X);
+        teapot
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        let draws = |gl: &MockGl| gl.count(|c| matches!(c, GlCall::DrawArraysInstanced { .. }));
+
+        // Looking at a spot far from the teapot, until the model moves it there
+        let spot = glam::Vec3::new(50.0, 0.0, 0.0);
+        let projection = glam::Mat4::perspective_rh_gl(1.0, 1.0, 0.1, 100.0);
+        let view = glam::Mat4::look_at_rh(spot + glam::Vec3::Z * 20.0, spot, glam::Vec3::Y);
+        teapot.get_shader_mut().unwrap().cull = Some(Frustum::from_matrix(projection * view));
+
+        gl.clear_calls();
+        let stats = teapot.update_draw(Mat3DUpdate::default(), &[]);
+        assert_eq!((draws(&gl), stats.culled), (0, 1));
+
+        let moved = Mat3DUpdate {
+            model: Some(glam::Mat4::from_translation(spot)),
+        };
+        gl.clear_calls();
+        let stats = teapot.update_draw(moved, &[]);
+        assert_eq!((draws(&gl), stats.culled), (1, 0));
+    }
+
+    #[test]
+    fn update_draw_uses_program_before_drawing() {
+        let gl = Rc::new(MockGl::default());
+        let mut squares = TexSquare::new(vec![square(0.0)], None);
+        squares
+            .init(gl.clone(), Mat3DUpdate::default(), &[])
+            .unwrap();
+        let program = squares.get_shader().unwrap().program;
+        gl.clear_calls();
+
+        squares.update_draw(Mat3DUpdate::default(), &[]);
+
+        let calls = gl.calls();
+        assert_eq!(calls.first(), Some(&GlCall::UseProgram(program)));
+        assert!(matches!(calls.last(), Some(GlCall::DrawElements { .. })));
     }
 }
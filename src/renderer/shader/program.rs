@@ -0,0 +1,471 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    gl,
+    renderer::{
+        backend::GlBackend,
+        shader::loader::{LoadError, LoadedShader, ShaderLoader, SourceFile},
+        uniform_buffer,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl ShaderStage {
+    pub fn as_gl_enum(self) -> gl::types::GLenum {
+        match self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+        }
+    }
+}
+
+/// A source line a driver error points at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLine {
+    /// `None` for sources not loaded from a file.
+    pub file: Option<PathBuf>,
+    pub number: usize,
+    pub text: String,
+}
+
+impl fmt::Display for SourceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{} | {}", file.display(), self.number, self.text),
+            None => write!(f, "{:>5} | {}", self.number, self.text),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Load(LoadError),
+    Compile {
+        stage: ShaderStage,
+        /// Full driver info log.
+        message: String,
+        /// The source lines the driver log points at.
+        lines: Vec<SourceLine>,
+    },
+    Link {
+        /// Full driver info log.
+        message: String,
+    },
+}
+
+impl From<LoadError> for ShaderError {
+    fn from(err: LoadError) -> Self {
+        ShaderError::Load(err)
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Load(err) => write!(f, "Could not load shader source: {err}"),
+            ShaderError::Compile {
+                stage,
+                message,
+                lines,
+            } => {
+                writeln!(f, "{stage} shader failed to compile:")?;
+                write!(f, "{message}")?;
+                for line in lines {
+                    write!(f, "\n{line}")?;
+                }
+                Ok(())
+            }
+            ShaderError::Link { message } => write!(f, "Shader program failed to link:\n{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Load(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct StageSource {
+    stage: ShaderStage,
+    code: Vec<u8>,
+    /// Files the code was loaded from, indexed by `#line` source string number.
+    /// Empty for inline sources.
+    files: Vec<SourceFile>,
+}
+
+/// Builder compiling and linking one GL program out of per-stage sources.
+///
+/// Inline sources may or may not end with a NUL byte.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderProgram {
+    sources: Vec<StageSource>,
+}
+
+impl ShaderProgram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a vertex and fragment shader pair through `loader`.
+    pub fn load(
+        loader: &ShaderLoader,
+        vertex: impl AsRef<Path>,
+        fragment: impl AsRef<Path>,
+    ) -> Result<Self, LoadError> {
+        Ok(Self::new()
+            .with_loaded(ShaderStage::Vertex, loader.load(vertex)?)
+            .with_loaded(ShaderStage::Fragment, loader.load(fragment)?))
+    }
+
+    /// Every file the stages were loaded from, includes too.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.sources
+            .iter()
+            .flat_map(|source| &source.files)
+            .map(|file| file.path.as_path())
+    }
+
+    pub fn with_stage(mut self, stage: ShaderStage, source: impl Into<Vec<u8>>) -> Self {
+        self.sources.push(StageSource {
+            stage,
+            code: source.into(),
+            files: vec![],
+        });
+        self
+    }
+
+    /// Adds a stage read through a [`ShaderLoader`];
+    /// compile errors then point at the original files and lines.
+    pub fn with_loaded(mut self, stage: ShaderStage, source: LoadedShader) -> Self {
+        self.sources.push(StageSource {
+            stage,
+            code: source.code.into_bytes(),
+            files: source.files,
+        });
+        self
+    }
+
+    pub fn with_vertex(self, source: impl Into<Vec<u8>>) -> Self {
+        self.with_stage(ShaderStage::Vertex, source)
+    }
+
+    pub fn with_fragment(self, source: impl Into<Vec<u8>>) -> Self {
+        self.with_stage(ShaderStage::Fragment, source)
+    }
+
+    /// Compiles every stage and links them, returning the program name with its
+    /// uniform blocks bound. On error every GL object created along the way is
+    /// deleted.
+    pub fn build(&self, gl: &dyn GlBackend) -> Result<gl::types::GLuint, ShaderError> {
+        let mut shaders = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            match compile_shader(gl, source) {
+                Ok(shader) => shaders.push(shader),
+                Err(err) => {
+                    for shader in shaders {
+                        unsafe { gl.DeleteShader(shader) };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        unsafe {
+            let program = gl.CreateProgram();
+            for shader in &shaders {
+                gl.AttachShader(program, *shader);
+            }
+            gl.LinkProgram(program);
+
+            // Flagged for deletion, freed along with the program
+            for shader in shaders {
+                gl.DeleteShader(shader);
+            }
+
+            let mut success: gl::types::GLint = 0;
+            gl.GetProgramiv(program, gl::LINK_STATUS, &mut success);
+            if success == 0 {
+                let message = read_info_log(
+                    |pname, value| gl.GetProgramiv(program, pname, value),
+                    |len, written, buf| gl.GetProgramInfoLog(program, len, written, buf),
+                );
+                gl.DeleteProgram(program);
+                return Err(ShaderError::Link { message });
+            }
+
+            uniform_buffer::bind_blocks(gl, program);
+            Ok(program)
+        }
+    }
+}
+
+fn compile_shader(
+    gl: &dyn GlBackend,
+    StageSource { stage, code, files }: &StageSource,
+) -> Result<gl::types::GLuint, ShaderError> {
+    let source = code.strip_suffix(b"\0").unwrap_or(code);
+
+    unsafe {
+        let shader = gl.CreateShader(stage.as_gl_enum());
+        gl.ShaderSource(
+            shader,
+            1,
+            [source.as_ptr().cast()].as_ptr(),
+            [source.len() as gl::types::GLint].as_ptr(),
+        );
+        gl.CompileShader(shader);
+
+        let mut success: gl::types::GLint = 0;
+        gl.GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+        if success != 0 {
+            return Ok(shader);
+        }
+
+        let message = read_info_log(
+            |pname, value| gl.GetShaderiv(shader, pname, value),
+            |len, written, buf| gl.GetShaderInfoLog(shader, len, written, buf),
+        );
+        gl.DeleteShader(shader);
+
+        let inline = String::from_utf8_lossy(source);
+        let lines = referenced_lines(&message)
+            .into_iter()
+            .filter_map(|(string, number)| {
+                let (file, text) = match files.get(string) {
+                    Some(SourceFile { path, contents }) => (Some(path.clone()), contents.as_str()),
+                    None if files.is_empty() && string == 0 => (None, inline.as_ref()),
+                    None => return None,
+                };
+                let text = text.lines().nth(number.checked_sub(1)?)?;
+                Some(SourceLine {
+                    file,
+                    number,
+                    text: text.to_string(),
+                })
+            })
+            .collect();
+
+        Err(ShaderError::Compile {
+            stage: *stage,
+            message,
+            lines,
+        })
+    }
+}
+
+/// Reads a whole shader or program info log, sized through `GL_INFO_LOG_LENGTH`.
+unsafe fn read_info_log(
+    get_iv: impl Fn(gl::types::GLenum, *mut gl::types::GLint),
+    get_log: impl Fn(gl::types::GLsizei, *mut gl::types::GLsizei, *mut gl::types::GLchar),
+) -> String {
+    let mut len: gl::types::GLint = 0;
+    get_iv(gl::INFO_LOG_LENGTH, &mut len);
+    if len <= 0 {
+        return String::new();
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    let mut written: gl::types::GLsizei = 0;
+    get_log(len, &mut written, buf.as_mut_ptr().cast());
+    buf.truncate(written.clamp(0, len) as usize);
+
+    String::from_utf8_lossy(&buf).trim_end().to_string()
+}
+
+/// Extracts the `(source string, line)` pairs from a driver info log, in order of
+/// appearance and without duplicates. Understands the Mesa/AMD style
+/// `0:12(5): error` and the NVIDIA style `0(12) : error`.
+pub fn referenced_lines(log: &str) -> Vec<(usize, usize)> {
+    let mut lines = vec![];
+    for entry in log.lines() {
+        let entry = entry.trim_start();
+        let entry = entry.strip_prefix("ERROR: ").unwrap_or(entry);
+        let digits_end = entry
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(entry.len());
+        let Ok(string) = entry[..digits_end].parse() else {
+            continue;
+        };
+        let rest = &entry[digits_end..];
+        let number = match rest.chars().next() {
+            Some(':') | Some('(') => &rest[1..],
+            _ => continue,
+        };
+        let number_end = number
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(number.len());
+        if let Ok(line) = number[..number_end].parse()
+            && !lines.contains(&(string, line))
+        {
+            lines.push((string, line));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    const VERTEX: &[u8] = b"#version 410 core\nvoid main() {\n    gl_Position = vec4(0.0);\n}\n\0";
+    const FRAGMENT: &[u8] =
+        b"#version 410 core\nout vec4 c;\nvoid main() {\n    c = vec4(1.0) oops;\n}\n";
+
+    fn program() -> ShaderProgram {
+        ShaderProgram::new()
+            .with_vertex(VERTEX)
+            .with_fragment(FRAGMENT)
+    }
+
+    #[test]
+    fn build_links_every_stage() {
+        let gl = MockGl::default();
+        let program = program().build(&gl).unwrap();
+
+        let calls = gl.calls();
+        let attached = calls
+            .iter()
+            .filter(|c| matches!(c, GlCall::AttachShader { program: p, .. } if *p == program))
+            .count();
+        assert_eq!(attached, 2);
+        assert!(calls.contains(&GlCall::LinkProgram(program)));
+        // The trailing NUL is not part of the source
+        assert!(calls.iter().any(|c| matches!(
+            c,
+            GlCall::ShaderSource { source, .. } if source.as_bytes() == &VERTEX[..VERTEX.len() - 1]
+        )));
+    }
+
+    #[test]
+    fn compile_error_reports_stage_lines_and_full_log() {
+        let gl = MockGl::default();
+        let log = format!(
+            "0:4(19): error: syntax error, unexpected IDENTIFIER\n{}",
+            "x".repeat(1000)
+        );
+        gl.fail_compile(&log);
+
+        let Err(ShaderError::Compile {
+            stage,
+            message,
+            lines,
+        }) = program().build(&gl)
+        else {
+            panic!("Expected a compile error");
+        };
+
+        assert_eq!(stage, ShaderStage::Vertex);
+        assert_eq!(message, log);
+        assert_eq!(
+            lines,
+            vec![SourceLine {
+                file: None,
+                number: 4,
+                text: "}".to_string()
+            }]
+        );
+        assert_eq!(gl.count(|c| matches!(c, GlCall::CreateProgram(_))), 0);
+        assert_eq!(gl.count(|c| matches!(c, GlCall::DeleteShader(_))), 1);
+    }
+
+    #[test]
+    fn link_error_deletes_the_program() {
+        let gl = MockGl::default();
+        gl.fail_link("error: fragment shader output `c` not written");
+
+        let Err(ShaderError::Link { message }) = program().build(&gl) else {
+            panic!("Expected a link error");
+        };
+
+        assert_eq!(message, "error: fragment shader output `c` not written");
+        let program = gl
+            .calls()
+            .into_iter()
+            .find_map(|c| match c {
+                GlCall::CreateProgram(p) => Some(p),
+                _ => None,
+            })
+            .unwrap();
+        assert!(gl.calls().contains(&GlCall::DeleteProgram(program)));
+    }
+
+    #[test]
+    fn compile_error_lines_point_into_loaded_files() {
+        let gl = MockGl::default();
+        gl.fail_compile("0:1(1): error: first\n1:2(7): error: second");
+        let loaded = LoadedShader {
+            code: String::new(),
+            files: vec![
+                SourceFile {
+                    path: "main.frag".into(),
+                    contents: "#version 410 core\n#include \"common.glsl\"\n".to_string(),
+                },
+                SourceFile {
+                    path: "common.glsl".into(),
+                    contents: "float a;\nfloat b oops;\n".to_string(),
+                },
+            ],
+        };
+
+        let Err(ShaderError::Compile { lines, .. }) = ShaderProgram::new()
+            .with_loaded(ShaderStage::Fragment, loaded)
+            .build(&gl)
+        else {
+            panic!("Expected a compile error");
+        };
+
+        assert_eq!(
+            lines,
+            vec![
+                SourceLine {
+                    file: Some("main.frag".into()),
+                    number: 1,
+                    text: "#version 410 core".to_string(),
+                },
+                SourceLine {
+                    file: Some("common.glsl".into()),
+                    number: 2,
+                    text: "float b oops;".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn referenced_lines_understands_common_driver_formats() {
+        let mesa = "0:4(19): error: syntax error\n0:12(1): error: undeclared";
+        let nvidia = "0(7) : error C0000: syntax error, unexpected '}'";
+        let amd = "ERROR: 0:3: 'oops' : syntax error\nERROR: 1 compilation errors.";
+
+        let with_string = "0:4(19): error: syntax error\n2:12(1): error: undeclared";
+
+        assert_eq!(referenced_lines(mesa), vec![(0, 4), (0, 12)]);
+        assert_eq!(referenced_lines(nvidia), vec![(0, 7)]);
+        assert_eq!(referenced_lines(amd), vec![(0, 3)]);
+        assert_eq!(referenced_lines(with_string), vec![(0, 4), (2, 12)]);
+        assert!(referenced_lines("linker error").is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    gl::{self, types::GLenum, types::GLint},
+    renderer::{backend::GlBackend, shader::uniform::ProgramId},
+};
+
+/// One active uniform as reported by `glGetActiveUniform`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActiveUniform {
+    pub location: GLint,
+    pub gl_type: GLenum,
+    /// Array length, 1 for non arrays.
+    pub size: GLint,
+}
+
+/// The GLSL types the typed setters write.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum UniformKind {
+    Float,
+    Vec3,
+    Mat4,
+    Bool,
+    Sampler,
+}
+
+impl UniformKind {
+    fn accepts(self, gl_type: GLenum) -> bool {
+        match self {
+            UniformKind::Float => gl_type == gl::FLOAT,
+            UniformKind::Vec3 => gl_type == gl::FLOAT_VEC3,
+            UniformKind::Mat4 => gl_type == gl::FLOAT_MAT4,
+            UniformKind::Bool => gl_type == gl::BOOL,
+            UniformKind::Sampler => matches!(
+                gl_type,
+                gl::SAMPLER_2D
+                    | gl::SAMPLER_3D
+                    | gl::SAMPLER_CUBE
+                    | gl::SAMPLER_2D_SHADOW
+                    | gl::SAMPLER_2D_ARRAY
+                    | gl::SAMPLER_2D_ARRAY_SHADOW
+                    | gl::SAMPLER_CUBE_SHADOW
+                    | gl::INT_SAMPLER_2D
+                    | gl::INT_SAMPLER_3D
+                    | gl::INT_SAMPLER_CUBE
+                    | gl::INT_SAMPLER_2D_ARRAY
+                    | gl::UNSIGNED_INT_SAMPLER_2D
+                    | gl::UNSIGNED_INT_SAMPLER_3D
+                    | gl::UNSIGNED_INT_SAMPLER_CUBE
+                    | gl::UNSIGNED_INT_SAMPLER_2D_ARRAY
+            ),
+        }
+    }
+}
+
+impl fmt::Display for UniformKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UniformKind::Float => "float",
+            UniformKind::Vec3 => "vec3",
+            UniformKind::Mat4 => "mat4",
+            UniformKind::Bool => "bool",
+            UniformKind::Sampler => "sampler",
+        })
+    }
+}
+
+/// Locations and types of the active uniforms of one linked program, read once
+/// through `GL_ACTIVE_UNIFORMS` reflection so setting a uniform is a map lookup
+/// instead of a `glGetUniformLocation` call.
+///
+/// The setters act on the program in use, like the `glUniform*` calls they
+/// wrap. Names the program doesn't have, or has with another GLSL type, are
+/// skipped with a warning logged once per name.
+#[derive(Clone, Debug, Default)]
+pub struct UniformRegistry {
+    program: ProgramId,
+    uniforms: HashMap<String, ActiveUniform>,
+    warned: RefCell<HashSet<String>>,
+}
+
+impl UniformRegistry {
+    /// Reflects the active uniforms of the linked `program`.
+    pub fn reflect(gl: &dyn GlBackend, program: ProgramId) -> Self {
+        let mut uniforms = HashMap::new();
+        unsafe {
+            let mut count = 0;
+            gl.GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+            let mut max_len = 0;
+            gl.GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+
+            let mut name = vec![0u8; max_len.max(1) as usize];
+            for index in 0..count.max(0) as u32 {
+                let mut len = 0;
+                let mut size = 0;
+                let mut gl_type = 0;
+                gl.GetActiveUniform(
+                    program,
+                    index,
+                    name.len() as i32,
+                    &mut len,
+                    &mut size,
+                    &mut gl_type,
+                    name.as_mut_ptr().cast(),
+                );
+                // Uniforms in blocks have no location, and need none
+                let location = gl.GetUniformLocation(program, name.as_ptr().cast());
+                if location < 0 {
+                    continue;
+                }
+
+                let name = String::from_utf8_lossy(&name[..len as usize]);
+                // Arrays are reported as `name[0]`
+                let name = name.strip_suffix("[0]").unwrap_or(&name).to_owned();
+                uniforms.insert(
+                    name,
+                    ActiveUniform {
+                        location,
+                        gl_type,
+                        size,
+                    },
+                );
+            }
+        }
+
+        Self {
+            program,
+            uniforms,
+            warned: Default::default(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ActiveUniform> {
+        self.uniforms.get(name)
+    }
+
+    pub fn set_f32(&self, gl: &dyn GlBackend, name: &str, value: f32) {
+        if let Some(location) = self.location(name, UniformKind::Float) {
+            unsafe { gl.Uniform1f(location, value) }
+        }
+    }
+
+    pub fn set_vec3(&self, gl: &dyn GlBackend, name: &str, value: glam::Vec3) {
+        if let Some(location) = self.location(name, UniformKind::Vec3) {
+            unsafe { gl.Uniform3f(location, value.x, value.y, value.z) }
+        }
+    }
+
+    pub fn set_mat4(&self, gl: &dyn GlBackend, name: &str, value: &glam::Mat4) {
+        if let Some(location) = self.location(name, UniformKind::Mat4) {
+            unsafe { gl.UniformMatrix4fv(location, 1, gl::FALSE, value.to_cols_array().as_ptr()) }
+        }
+    }
+
+    pub fn set_bool(&self, gl: &dyn GlBackend, name: &str, value: bool) {
+        if let Some(location) = self.location(name, UniformKind::Bool) {
+            unsafe { gl.Uniform1ui(location, value as u32) }
+        }
+    }
+
+    /// Points the sampler `name` at texture unit `unit`.
+    pub fn set_sampler(&self, gl: &dyn GlBackend, name: &str, unit: i32) {
+        if let Some(location) = self.location(name, UniformKind::Sampler) {
+            unsafe { gl.Uniform1i(location, unit) }
+        }
+    }
+
+    fn location(&self, name: &str, kind: UniformKind) -> Option<GLint> {
+        match self.uniforms.get(name) {
+            Some(uniform) if kind.accepts(uniform.gl_type) => Some(uniform.location),
+            Some(uniform) => {
+                self.warn_once(name, || {
+                    format!(
+                        "Uniform {name} of program {} is not a {kind} (GL type {:#x}), not setting it",
+                        self.program, uniform.gl_type
+                    )
+                });
+                None
+            }
+            None => {
+                self.warn_once(name, || {
+                    format!(
+                        "Uniform {name} is not active in program {}, not setting it",
+                        self.program
+                    )
+                });
+                None
+            }
+        }
+    }
+
+    fn warn_once(&self, name: &str, message: impl FnOnce() -> String) {
+        if self.warned.borrow_mut().insert(name.to_owned()) {
+            log::warn!("{}", message());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    fn registry(gl: &MockGl) -> UniformRegistry {
+        gl.declare_uniform("uFogNear", gl::FLOAT);
+        gl.declare_uniform("uLightPos", gl::FLOAT_VEC3);
+        gl.declare_uniform("model", gl::FLOAT_MAT4);
+        gl.declare_uniform("uEmissive", gl::BOOL);
+        gl.declare_uniform("tex", gl::SAMPLER_2D);
+        gl.declare_uniform("bones[0]", gl::FLOAT_MAT4);
+        UniformRegistry::reflect(gl, 1)
+    }
+
+    #[test]
+    fn reflect_reads_every_active_uniform() {
+        let gl = MockGl::default();
+        let registry = registry(&gl);
+
+        assert_eq!(
+            registry.get("uLightPos"),
+            Some(&ActiveUniform {
+                location: gl.uniform_location("uLightPos").unwrap(),
+                gl_type: gl::FLOAT_VEC3,
+                size: 1,
+            })
+        );
+        assert!(registry.get("bones").is_some(), "array suffix is stripped");
+        assert_eq!(registry.get("uFogFar"), None);
+    }
+
+    #[test]
+    fn setters_use_cached_locations() {
+        let gl = MockGl::default();
+        let registry = registry(&gl);
+        gl.clear_calls();
+
+        registry.set_f32(&gl, "uFogNear", 2.0);
+        registry.set_vec3(&gl, "uLightPos", glam::Vec3::new(1.0, 2.0, 3.0));
+        registry.set_mat4(&gl, "model", &glam::Mat4::IDENTITY);
+        registry.set_bool(&gl, "uEmissive", true);
+        registry.set_sampler(&gl, "tex", 3);
+
+        let location = |name| gl.uniform_location(name).unwrap();
+        assert_eq!(
+            gl.calls(),
+            [
+                GlCall::Uniform1f {
+                    location: location("uFogNear"),
+                    value: 2.0
+                },
+                GlCall::Uniform3f {
+                    location: location("uLightPos"),
+                    value: [1.0, 2.0, 3.0]
+                },
+                GlCall::UniformMatrix4fv {
+                    location: location("model"),
+                    value: glam::Mat4::IDENTITY.to_cols_array().to_vec()
+                },
+                GlCall::Uniform1ui {
+                    location: location("uEmissive"),
+                    value: 1
+                },
+                GlCall::Uniform1i {
+                    location: location("tex"),
+                    value: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_or_mistyped_uniforms_are_skipped_and_warned_once() {
+        let gl = MockGl::default();
+        let registry = registry(&gl);
+        gl.clear_calls();
+
+        registry.set_f32(&gl, "uFogFar", 1.0);
+        registry.set_f32(&gl, "uFogFar", 1.0);
+        registry.set_f32(&gl, "uLightPos", 1.0);
+        registry.set_bool(&gl, "tex", true);
+
+        assert!(gl.calls().is_empty());
+        assert_eq!(
+            *registry.warned.borrow(),
+            HashSet::from([
+                "uFogFar".to_owned(),
+                "uLightPos".to_owned(),
+                "tex".to_owned()
+            ])
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use std::{fmt, ops::AddAssign};
+
+/// What drawing cost, counted as the passes draw. [`Renderer`] adds up the
+/// stats of every pass it draws until they are taken, e.g. once per frame.
+///
+/// [`Renderer`]: crate::renderer::Renderer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// `glDraw*` calls issued
+    pub draw_calls: usize,
+    /// Triangles drawn, once per instance
+    pub triangles: usize,
+    /// Drawables skipped for being out of view
+    pub culled: usize,
+}
+
+impl RenderStats {
+    /// A single draw call of `triangles`.
+    pub fn call(triangles: usize) -> Self {
+        Self {
+            draw_calls: 1,
+            triangles,
+            culled: 0,
+        }
+    }
+
+    /// `drawables` skipped without drawing.
+    pub fn culled(drawables: usize) -> Self {
+        Self {
+            culled: drawables,
+            ..Self::default()
+        }
+    }
+}
+
+impl AddAssign for RenderStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.draw_calls += rhs.draw_calls;
+        self.triangles += rhs.triangles;
+        self.culled += rhs.culled;
+    }
+}
+
+impl std::iter::Sum for RenderStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        let mut total = Self::default();
+        for stats in iter {
+            total += stats;
+        }
+        total
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} draw calls, {} triangles, {} culled",
+            self.draw_calls, self.triangles, self.culled
+        )
+    }
+}
@@ -0,0 +1,338 @@
+use std::{ffi::CStr, marker::PhantomData, rc::Rc};
+
+use crate::{
+    gl,
+    renderer::{backend::GlBackend, shader::uniform::ProgramId},
+};
+
+/// Packs values following the `std140` layout rules: scalars take 4 bytes,
+/// `vec3` and every `mat4` column start on 16 bytes, and a block is padded to a
+/// multiple of 16.
+#[derive(Clone, Debug, Default)]
+pub struct Std140Writer {
+    bytes: Vec<u8>,
+}
+
+impl Std140Writer {
+    fn align(&mut self, to: usize) {
+        self.bytes.resize(self.bytes.len().next_multiple_of(to), 0);
+    }
+
+    fn push(&mut self, align: usize, values: &[f32]) -> &mut Self {
+        self.align(align);
+        self.bytes
+            .extend(values.iter().flat_map(|value| value.to_ne_bytes()));
+        self
+    }
+
+    pub fn f32(&mut self, value: f32) -> &mut Self {
+        self.push(4, &[value])
+    }
+
+    /// GLSL `bool`s are 4 bytes wide in blocks.
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.align(4);
+        self.bytes.extend((value as u32).to_ne_bytes());
+        self
+    }
+
+    pub fn vec3(&mut self, value: glam::Vec3) -> &mut Self {
+        self.push(16, &value.to_array())
+    }
+
+    pub fn mat4(&mut self, value: &glam::Mat4) -> &mut Self {
+        self.push(16, &value.to_cols_array())
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align(16);
+        self.bytes
+    }
+}
+
+/// A `std140` uniform block, shared by every program declaring it.
+pub trait UniformBlock {
+    /// Block name in GLSL.
+    const NAME: &'static CStr;
+    /// Uniform buffer binding point the block is read from.
+    const BINDING: gl::types::GLuint;
+
+    /// Writes the members in their GLSL declaration order.
+    fn write_std140(&self, writer: &mut Std140Writer);
+
+    fn to_std140(&self) -> Vec<u8> {
+        let mut writer = Std140Writer::default();
+        self.write_std140(&mut writer);
+        writer.finish()
+    }
+}
+
+/// Points the blocks of `program` at their binding points. Blocks the program
+/// doesn't declare are skipped.
+pub fn bind_blocks(gl: &dyn GlBackend, program: ProgramId) {
+    for (name, binding) in [
+        (FrameGlobals::NAME, FrameGlobals::BINDING),
+        (LightingGlobals::NAME, LightingGlobals::BINDING),
+    ] {
+        unsafe {
+            let index = gl.GetUniformBlockIndex(program, name.as_ptr());
+            if index != gl::INVALID_INDEX {
+                gl.UniformBlockBinding(program, index, binding);
+            }
+        }
+    }
+}
+
+/// GPU buffer backing one [`UniformBlock`].
+pub struct UniformBuffer<B: UniformBlock> {
+    buffer: gl::types::GLuint,
+    gl_fns: Rc<dyn GlBackend>,
+    block: PhantomData<B>,
+}
+
+impl<B: UniformBlock> UniformBuffer<B> {
+    pub fn new(gl_fns: Rc<dyn GlBackend>) -> Self {
+        let mut buffer = 0;
+        unsafe { gl_fns.GenBuffers(1, &mut buffer) };
+        Self {
+            buffer,
+            gl_fns,
+            block: PhantomData,
+        }
+    }
+
+    /// Replaces the buffer contents with `block` and binds it to `B::BINDING`.
+    pub fn upload(&self, block: &B) {
+        let data = block.to_std140();
+        let gl = &self.gl_fns;
+        unsafe {
+            gl.BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+            gl.BufferData(
+                gl::UNIFORM_BUFFER,
+                data.len() as gl::types::GLsizeiptr,
+                data.as_ptr() as *const _,
+                gl::DYNAMIC_DRAW,
+            );
+            gl.BindBufferBase(gl::UNIFORM_BUFFER, B::BINDING, self.buffer);
+        }
+    }
+}
+
+impl<B: UniformBlock> Drop for UniformBuffer<B> {
+    fn drop(&mut self) {
+        unsafe { self.gl_fns.DeleteBuffers(1, &self.buffer) };
+    }
+}
+
+/// The `FrameGlobals` block: camera state, the same for every program in a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameGlobals {
+    pub view: glam::Mat4,
+    pub projection: glam::Mat4,
+    pub eye_pos: glam::Vec3,
+}
+
+impl FrameGlobals {
+    pub fn new(view: glam::Mat4, dimensions: glam::Vec2, eye_pos: glam::Vec3) -> Self {
+        Self {
+            view,
+            projection: glam::Mat4::perspective_rh_gl(
+                90.0f32.to_radians(),
+                dimensions.x / dimensions.y,
+                0.1f32,
+                100.0f32,
+            ),
+            eye_pos,
+        }
+    }
+}
+
+impl UniformBlock for FrameGlobals {
+    const NAME: &'static CStr = c"FrameGlobals";
+    const BINDING: gl::types::GLuint = 0;
+
+    fn write_std140(&self, writer: &mut Std140Writer) {
+        writer
+            .mat4(&self.view)
+            .mat4(&self.projection)
+            .vec3(self.eye_pos);
+    }
+}
+
+pub struct Fog {
+    pub fog_near: f32,
+    pub fog_far: f32,
+    pub fog_color: glam::Vec3,
+}
+
+impl Fog {
+    pub fn new(clear_color: glam::Vec3) -> Self {
+        Self {
+            fog_near: 1.0,
+            fog_far: 20.0,
+            fog_color: clear_color,
+        }
+    }
+}
+
+pub struct Lighting {
+    pub ambient_strenght: f32,
+    pub specular_strenght: f32,
+}
+
+impl Lighting {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            ambient_strenght: 0.1,
+            specular_strenght: 0.5,
+        }
+    }
+}
+
+/// The `Lighting` block: the light, material strengths and fog.
+pub struct LightingGlobals {
+    pub light_pos: glam::Vec3,
+    pub lighting: Lighting,
+    pub fog: Fog,
+    pub enabled_lighting: bool,
+    pub enabled_fog: bool,
+}
+
+impl LightingGlobals {
+    /// Default lighting and fog fading into `clear_color`, both enabled.
+    pub fn new(light_pos: glam::Vec3, clear_color: glam::Vec3) -> Self {
+        Self {
+            light_pos,
+            lighting: Lighting::new(),
+            fog: Fog::new(clear_color),
+            enabled_lighting: true,
+            enabled_fog: true,
+        }
+    }
+}
+
+impl UniformBlock for LightingGlobals {
+    const NAME: &'static CStr = c"Lighting";
+    const BINDING: gl::types::GLuint = 1;
+
+    fn write_std140(&self, writer: &mut Std140Writer) {
+        writer
+            .vec3(self.light_pos)
+            .f32(self.lighting.ambient_strenght)
+            .vec3(self.fog.fog_color)
+            .f32(self.lighting.specular_strenght)
+            .f32(self.fog.fog_near)
+            .f32(self.fog.fog_far)
+            .bool(self.enabled_lighting)
+            .bool(self.enabled_fog);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::{GlCall, MockGl};
+
+    /// The f32 at byte `offset`.
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn std140_aligns_vec3_and_packs_scalars_after_it() {
+        let mut writer = Std140Writer::default();
+        writer
+            .f32(1.0)
+            .vec3(glam::Vec3::new(2.0, 3.0, 4.0))
+            .f32(5.0)
+            .bool(true);
+        let bytes = writer.finish();
+
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(f32_at(&bytes, 0), 1.0);
+        assert_eq!(f32_at(&bytes, 16), 2.0);
+        assert_eq!(f32_at(&bytes, 24), 4.0);
+        assert_eq!(f32_at(&bytes, 28), 5.0);
+        assert_eq!(bytes[32..36], 1u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn frame_globals_layout() {
+        let globals = FrameGlobals {
+            view: glam::Mat4::from_translation(glam::Vec3::new(1.0, 2.0, 3.0)),
+            projection: glam::Mat4::from_scale(glam::Vec3::splat(7.0)),
+            eye_pos: glam::Vec3::new(8.0, 9.0, 10.0),
+        };
+        let bytes = globals.to_std140();
+
+        assert_eq!(bytes.len(), 144);
+        // view translation is its last column
+        assert_eq!(f32_at(&bytes, 48), 1.0);
+        assert_eq!(f32_at(&bytes, 64), 7.0);
+        assert_eq!(f32_at(&bytes, 128), 8.0);
+        assert_eq!(f32_at(&bytes, 136), 10.0);
+    }
+
+    #[test]
+    fn lighting_globals_layout() {
+        let mut globals = LightingGlobals::new(glam::Vec3::new(1.0, 2.0, 3.0), glam::Vec3::ONE);
+        globals.enabled_fog = false;
+        let bytes = globals.to_std140();
+
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(f32_at(&bytes, 0), 1.0);
+        assert_eq!(f32_at(&bytes, 12), 0.1, "uAmbientStrength");
+        assert_eq!(f32_at(&bytes, 16), 1.0, "uFogColor");
+        assert_eq!(f32_at(&bytes, 28), 0.5, "uSpecularStrength");
+        assert_eq!(f32_at(&bytes, 32), 1.0, "uFogNear");
+        assert_eq!(f32_at(&bytes, 36), 20.0, "uFogFar");
+        assert_eq!(bytes[40..44], 1u32.to_ne_bytes(), "uEnabledLighting");
+        assert_eq!(bytes[44..48], 0u32.to_ne_bytes(), "uEnabledFog");
+    }
+
+    #[test]
+    fn upload_fills_and_binds_the_buffer() {
+        let gl = Rc::new(MockGl::default());
+        let ubo = UniformBuffer::<LightingGlobals>::new(gl.clone());
+        gl.clear_calls();
+
+        ubo.upload(&LightingGlobals::new(glam::Vec3::ZERO, glam::Vec3::ZERO));
+
+        assert_eq!(
+            gl.calls()[1..],
+            [
+                GlCall::BufferData {
+                    target: gl::UNIFORM_BUFFER,
+                    size: 48
+                },
+                GlCall::BindBufferBase {
+                    target: gl::UNIFORM_BUFFER,
+                    index: LightingGlobals::BINDING,
+                    buffer: ubo.buffer
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bind_blocks_binds_every_block() {
+        let gl = MockGl::default();
+        bind_blocks(&gl, 7);
+
+        let bindings: Vec<_> = gl
+            .calls()
+            .into_iter()
+            .filter_map(|c| match c {
+                GlCall::UniformBlockBinding {
+                    program, binding, ..
+                } => Some((program, binding)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            bindings,
+            [(7, FrameGlobals::BINDING), (7, LightingGlobals::BINDING)]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::f32::consts::TAU;
+
+use glam::{Mat4, Quat, Vec2, Vec3};
+use noise::{NoiseFn, Perlin};
+
+/// How far apart the heights a slope is measured from are, in world units.
+const SLOPE_STEP: f32 = 0.5;
+/// Candidates tried around a point before it stops spawning new ones.
+const CANDIDATES: usize = 30;
+
+/// Points at least `min_distance` apart filling the rectangle from `min` to
+/// `min + size`, Bridson's Poisson disk sampling. The same `seed` always gives
+/// the same points.
+pub fn poisson_disk(min: Vec2, size: Vec2, min_distance: f32, seed: u64) -> Vec<Vec2> {
+    if size.min_element() <= 0.0 || min_distance <= 0.0 {
+        return vec![];
+    }
+    // A cell holds at most one point
+    let cell = min_distance / std::f32::consts::SQRT_2;
+    let cells = (size / cell).ceil().as_uvec2().max(glam::UVec2::ONE);
+    let mut grid: Vec<Option<u32>> = vec![None; (cells.x * cells.y) as usize];
+    let cell_of = |p: Vec2| (p / cell).as_uvec2().min(cells - 1);
+
+    let mut rng = SplitMix64(seed);
+    let mut points = vec![Vec2::new(rng.next_f32(), rng.next_f32()) * size];
+    grid[(cell_of(points[0]).x + cell_of(points[0]).y * cells.x) as usize] = Some(0);
+    let mut active = vec![0];
+
+    while !active.is_empty() {
+        let slot = (rng.next_u64() % active.len() as u64) as usize;
+        let around = points[active[slot] as usize];
+
+        let found = (0..CANDIDATES).find_map(|_| {
+            // Uniform over the ring between one and two min distances
+            let angle = rng.next_f32() * TAU;
+            let radius = min_distance * (1.0 + 3.0 * rng.next_f32()).sqrt();
+            let candidate = around + Vec2::from_angle(angle) * radius;
+            if candidate.cmplt(Vec2::ZERO).any() || candidate.cmpge(size).any() {
+                return None;
+            }
+
+            let at = cell_of(candidate).as_ivec2();
+            let near =
+                (-2..=2).flat_map(|dz| (-2..=2).map(move |dx| at + glam::IVec2::new(dx, dz)));
+            let too_close = near
+                .filter(|c| c.cmpge(glam::IVec2::ZERO).all() && c.as_uvec2().cmplt(cells).all())
+                .filter_map(|c| grid[(c.x as u32 + c.y as u32 * cells.x) as usize])
+                .any(|other| points[other as usize].distance(candidate) < min_distance);
+            (!too_close).then_some(candidate)
+        });
+
+        match found {
+            Some(candidate) => {
+                let index = points.len() as u32;
+                let c = cell_of(candidate);
+                grid[(c.x + c.y * cells.x) as usize] = Some(index);
+                points.push(candidate);
+                active.push(index);
+            }
+            None => {
+                active.swap_remove(slot);
+            }
+        }
+    }
+
+    points.into_iter().map(|p| min + p).collect()
+}
+
+/// A noise field thinning out scattered instances into patches: where
+/// [`DensityNoise::density_fn`] is 0.3, about 30% of the instances are kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DensityNoise {
+    pub seed: u32,
+    /// Frequency of the noise, per world unit. Smaller is larger patches.
+    pub frequency: f64,
+    /// Average fraction of instances kept
+    pub coverage: f32,
+    /// How much the noise moves the density away from `coverage`, 0 for the
+    /// same density everywhere
+    pub contrast: f32,
+}
+
+impl DensityNoise {
+    /// The fraction of instances kept around `(x, z)`, between 0 and 1.
+    pub fn density_fn(&self) -> impl Fn(f32, f32) -> f32 + use<> {
+        let noise = Perlin::new(self.seed);
+        let (frequency, coverage, contrast) = (self.frequency, self.coverage, self.contrast);
+        move |x: f32, z: f32| {
+            let n = noise.get([x as f64 * frequency, z as f64 * frequency]) as f32;
+            (coverage + contrast * n).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Where and how densely one kind of instance is scattered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScatterRule {
+    /// Closest two instances can be, in world units
+    pub spacing: f32,
+    pub density: DensityNoise,
+    /// Steepest ground an instance stands on, rise over run
+    pub max_slope: f32,
+    /// Instances are scaled uniformly between these
+    pub min_scale: f32,
+    pub max_scale: f32,
+}
+
+/// One scattered instance, standing on the ground at `position`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub position: Vec3,
+    /// Rotation around the y axis, in radians
+    pub yaw: f32,
+    pub scale: f32,
+}
+
+impl Placement {
+    /// The model matrix of the instance.
+    pub fn transform(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            Vec3::splat(self.scale),
+            Quat::from_rotation_y(self.yaw),
+            self.position,
+        )
+    }
+}
+
+/// Scatters instances over the rectangle from `min` to `min + size` on the
+/// surface `y = height_at(x, z)`: Poisson disk points `rule.spacing` apart,
+/// thinned by the density noise, and dropped where the ground is steeper
+/// than `rule.max_slope`. The same `seed` always gives the same instances.
+pub fn scatter(
+    rule: &ScatterRule,
+    min: Vec2,
+    size: Vec2,
+    seed: u64,
+    height_at: impl Fn(f32, f32) -> f32,
+) -> Vec<Placement> {
+    let density = rule.density.density_fn();
+    // Separate from the sampling, so the kept points don't shift the others
+    let mut rng = SplitMix64(seed ^ 0x5ca7_7e12_5ca7_7e12);
+
+    poisson_disk(min, size, rule.spacing, seed)
+        .into_iter()
+        .filter_map(|p| {
+            let (keep, yaw, scale) = (rng.next_f32(), rng.next_f32(), rng.next_f32());
+            if keep >= density(p.x, p.y) {
+                return None;
+            }
+            let slope = Vec2::new(
+                height_at(p.x + SLOPE_STEP, p.y) - height_at(p.x - SLOPE_STEP, p.y),
+                height_at(p.x, p.y + SLOPE_STEP) - height_at(p.x, p.y - SLOPE_STEP),
+            ) / (2.0 * SLOPE_STEP);
+            if slope.length() > rule.max_slope {
+                return None;
+            }
+
+            Some(Placement {
+                position: Vec3::new(p.x, height_at(p.x, p.y), p.y),
+                yaw: yaw * TAU,
+                scale: rule.min_scale + scale * (rule.max_scale - rule.min_scale),
+            })
+        })
+        .collect()
+}
+
+/// The kinds of vegetation scattered on the terrain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Plant {
+    Tree,
+    Rock,
+    Grass,
+}
+
+impl Plant {
+    pub const ALL: [Plant; 3] = [Plant::Tree, Plant::Rock, Plant::Grass];
+
+    /// How the plant is scattered, in world units of one voxel, its density
+    /// noise seeded from `seed`.
+    pub fn rule(self, seed: u32) -> ScatterRule {
+        let density = |offset: u32, coverage: f32, contrast: f32| DensityNoise {
+            seed: seed.wrapping_add(offset),
+            frequency: 0.04,
+            coverage,
+            contrast,
+        };
+        match self {
+            // Woods and clearings
+            Plant::Tree => ScatterRule {
+                spacing: 4.0,
+                density: density(1, 0.3, 1.2),
+                max_slope: 0.6,
+                min_scale: 0.8,
+                max_scale: 1.4,
+            },
+            // Few, anywhere but cliffs
+            Plant::Rock => ScatterRule {
+                spacing: 6.0,
+                density: density(2, 0.25, 0.5),
+                max_slope: 1.5,
+                min_scale: 0.4,
+                max_scale: 1.0,
+            },
+            Plant::Grass => ScatterRule {
+                spacing: 1.2,
+                density: density(3, 0.55, 1.0),
+                max_slope: 0.8,
+                min_scale: 0.6,
+                max_scale: 1.2,
+            },
+        }
+    }
+}
+
+/// SplitMix64, a tiny generator giving the same numbers on every platform.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: Vec2 = Vec2::new(-10.0, 30.0);
+    const SIZE: Vec2 = Vec2::new(40.0, 25.0);
+
+    fn everywhere(spacing: f32, max_slope: f32) -> ScatterRule {
+        ScatterRule {
+            spacing,
+            density: DensityNoise {
+                seed: 0,
+                frequency: 0.1,
+                coverage: 1.0,
+                contrast: 0.0,
+            },
+            max_slope,
+            min_scale: 1.0,
+            max_scale: 2.0,
+        }
+    }
+
+    #[test]
+    fn poisson_points_are_spaced_and_fill_the_area() {
+        let points = poisson_disk(MIN, SIZE, 2.0, 7);
+        for (i, a) in points.iter().enumerate() {
+            assert!(a.cmpge(MIN).all() && a.cmplt(MIN + SIZE).all(), "{a}");
+            for b in &points[i + 1..] {
+                assert!(a.distance(*b) >= 2.0, "{a} and {b}");
+            }
+        }
+        // Maximal samplings are denser than one point per disk of the spacing
+        let disks = SIZE.element_product() / (std::f32::consts::PI * 2.0 * 2.0);
+        assert!(points.len() as f32 > disks, "{} points", points.len());
+    }
+
+    #[test]
+    fn placement_is_deterministic_per_seed() {
+        let hills = |x: f32, z: f32| (x * 0.2).sin() + (z * 0.3).cos();
+        let rule = Plant::Tree.rule(5);
+
+        let first = scatter(&rule, MIN, SIZE, 11, hills);
+        assert!(!first.is_empty());
+        assert_eq!(first, scatter(&rule, MIN, SIZE, 11, hills));
+        assert_ne!(first, scatter(&rule, MIN, SIZE, 12, hills));
+        assert_ne!(first, scatter(&Plant::Tree.rule(6), MIN, SIZE, 11, hills));
+    }
+
+    #[test]
+    fn instances_stand_on_the_ground() {
+        let hills = |x: f32, z: f32| 0.1 * x - 0.05 * z;
+        let placements = scatter(&everywhere(1.5, 1.0), MIN, SIZE, 3, hills);
+
+        assert_eq!(placements.len(), poisson_disk(MIN, SIZE, 1.5, 3).len());
+        for placement in &placements {
+            let p = placement.position;
+            assert_eq!(p.y, hills(p.x, p.z));
+            assert!((1.0..=2.0).contains(&placement.scale));
+            assert_eq!(placement.transform().transform_point3(Vec3::ZERO), p);
+        }
+    }
+
+    #[test]
+    fn nothing_grows_on_steep_ground() {
+        let steep = |x: f32, _: f32| 2.0 * x;
+        assert!(scatter(&everywhere(1.5, 1.0), MIN, SIZE, 3, steep).is_empty());
+
+        // Flat on one half, a cliff on the other
+        let terrace = |x: f32, _: f32| if x < 10.0 { 0.0 } else { 3.0 * (x - 10.0) };
+        let placements = scatter(&everywhere(1.5, 1.0), MIN, SIZE, 3, terrace);
+        assert!(!placements.is_empty());
+        assert!(placements.iter().all(|p| p.position.x < 10.0 + SLOPE_STEP));
+    }
+
+    #[test]
+    fn density_noise_thins_out_instances() {
+        let flat = |_: f32, _: f32| 0.0;
+        let mut rule = everywhere(1.5, 1.0);
+        let all = scatter(&rule, MIN, SIZE, 3, flat).len();
+
+        rule.density.coverage = 0.0;
+        assert!(scatter(&rule, MIN, SIZE, 3, flat).is_empty());
+
+        // Patches: kept only where the noise is high
+        rule.density.contrast = 2.0;
+        let density = rule.density.density_fn();
+        let patchy = scatter(&rule, MIN, SIZE, 3, flat);
+        assert!(
+            !patchy.is_empty() && patchy.len() < all / 2,
+            "{}",
+            patchy.len()
+        );
+        assert!(
+            patchy
+                .iter()
+                .all(|p| density(p.position.x, p.position.z) > 0.0)
+        );
+    }
+}
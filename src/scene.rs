@@ -0,0 +1,509 @@
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use glam::Vec3;
+
+use crate::camera::Camera;
+use crate::entities::Entity;
+use crate::entities::block_highlight::BlockHighlight;
+use crate::entities::chunked_terrain::ChunkedTerrain;
+use crate::entities::heightfield::{Heightfield, HeightfieldGrid};
+use crate::entities::hello_triangle::HelloTriangle;
+use crate::entities::lod_terrain::{DEFAULT_PATCH_CELLS, LodGrid, LodTerrain};
+use crate::entities::sun::Sun;
+use crate::entities::utah_teapot::UtahTeapot;
+use crate::entities::vegetation::{Vegetation, obj_path};
+use crate::entities::water::{Water, reflected, refracted};
+use crate::helpers::{GlPosition, Mat3DUpdate};
+use crate::renderer::Renderer;
+use crate::renderer::backend::GlBackend;
+use crate::renderer::framebuffer::RenderTexture;
+use crate::renderer::shader::GlslPass;
+use crate::renderer::shader::program::ShaderError;
+use crate::renderer::uniform_buffer::{FrameGlobals, LightingGlobals};
+use crate::scatter::{Placement, Plant, scatter};
+use crate::terrain_builder::{self, TerrainConfig};
+use crate::voxel::blocks::{BlockLayers, BlockRegistry, DIRT_TEXTURE, TextureSource};
+use crate::voxel::save::{SaveError, WorldSave};
+use crate::voxel::world::{ChunkWorld, GeneratorConfig, TerrainShape};
+use crate::voxel::{BlockId, DIRT};
+
+/// Where the world is saved to and loaded from.
+pub const WORLD_SAVE_PATH: &str = "./world.sav";
+
+/// Side of a voxel, in world units
+const CS: f32 = 1.0;
+/// Chunks kept loaded around the camera
+const VIEW_RADIUS: i32 = 3;
+/// Columns whose surface is below it are under water, in voxels
+const SEA_LEVEL: i32 = 1;
+/// Between the tops of the highest flooded voxels and of the beach above them
+const WATER_HEIGHT: f32 = SEA_LEVEL as f32 * CS;
+
+/// How far blocks can be picked from the camera, in world units.
+pub const REACH: f32 = 8.0;
+
+pub const CLEAR_COLOR: glam::Vec3 = glam::Vec3 {
+    x: 0.1,
+    y: 0.1,
+    z: 0.1,
+};
+
+/// What the scene stands on, see [`Scene::with_floor`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FloorKind {
+    /// Streamed voxel chunks, blocks can be picked and edited
+    #[default]
+    Voxels,
+    /// A smooth triangle mesh of the same terrain
+    Heightfield,
+    /// A smooth terrain reaching much further, coarser away from the camera
+    LodHeightfield,
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum Floor {
+    Voxels(ChunkedTerrain),
+    Heightfield(Heightfield),
+    Lod(LodTerrain),
+}
+
+impl Floor {
+    pub fn as_pass_mut(&mut self) -> &mut dyn GlslPass {
+        match self {
+            Floor::Voxels(terrain) => terrain,
+            Floor::Heightfield(field) => field,
+            Floor::Lod(terrain) => terrain,
+        }
+    }
+
+    /// The voxel terrain, `None` on other floors.
+    pub fn voxels(&self) -> Option<&ChunkedTerrain> {
+        match self {
+            Floor::Voxels(terrain) => Some(terrain),
+            Floor::Heightfield(_) | Floor::Lod(_) => None,
+        }
+    }
+
+    pub fn voxels_mut(&mut self) -> Option<&mut ChunkedTerrain> {
+        match self {
+            Floor::Voxels(terrain) => Some(terrain),
+            Floor::Heightfield(_) | Floor::Lod(_) => None,
+        }
+    }
+}
+
+/// Everything the app draws, independent of where the frames end up (window or
+/// offscreen framebuffer).
+pub struct Scene {
+    pub entities: Vec<Box<dyn Entity>>,
+    /// Voxels streamed around the camera every frame, or a heightfield.
+    pub floor: Floor,
+    /// What the voxel terrain is generated from, saved along with its edits.
+    pub world_config: GeneratorConfig,
+    /// Outlines the block the camera looks at.
+    pub highlight: BlockHighlight,
+    /// What [`Scene::place_block`] puts down.
+    pub held_block: BlockId,
+    pub sun: Sun,
+    pub camera: Camera,
+    /// Light and fog shared by every program, the light following the sun.
+    pub lighting: LightingGlobals,
+    /// Lakes at [`WATER_HEIGHT`], over the flooded columns.
+    pub water: Water,
+    /// Where the water reflection and refraction are rendered, at half the
+    /// window size. Created on the first frame and on resize.
+    water_targets: Option<(RenderTexture, RenderTexture)>,
+}
+
+impl Scene {
+    /// Builds the voxel terrain, teapots and sun, and inits their GL
+    /// resources. The context owning `gl_fns` must be current.
+    pub fn new(gl_fns: Rc<dyn GlBackend>) -> Result<Self, ShaderError> {
+        Self::with_floor(gl_fns, FloorKind::default())
+    }
+
+    /// Like [`Scene::new`], standing on a `floor` of the same terrain.
+    pub fn with_floor(gl_fns: Rc<dyn GlBackend>, floor: FloorKind) -> Result<Self, ShaderError> {
+        const HEIGHT: usize = 4;
+        /// World height in voxels, leaving room above the surface
+        const WORLD_HEIGHT: u32 = 4 * HEIGHT as u32;
+
+        let tb = terrain_builder::terrain_builder(123, HEIGHT);
+
+        let world_config = GeneratorConfig {
+            shape: TerrainShape::Heightmap(TerrainConfig::new(123, HEIGHT)),
+            height: WORLD_HEIGHT,
+            layers: Some(BlockLayers {
+                sea_level: SEA_LEVEL,
+                snow_line: HEIGHT as i32,
+                max_soil_slope: 2,
+                soil_depth: 2,
+            }),
+        };
+
+        const MIDDLE: f32 = 150.0 * CS;
+        let surface = tb(MIDDLE as usize, MIDDLE as usize) as f32;
+
+        let smooth_height = TerrainConfig::new(123, HEIGHT).smooth_height_fn();
+        let tint = TextureSource::Tinted {
+            path: DIRT_TEXTURE.into(),
+            color: [96, 150, 56],
+        };
+        let floor = match floor {
+            FloorKind::Voxels => Floor::Voxels(voxel_terrain(
+                ChunkWorld::new(world_config.generator(), VIEW_RADIUS),
+                gl_fns.clone(),
+            )?),
+            FloorKind::Heightfield => {
+                /// Cells along each side, centered on the middle
+                const CELLS: u32 = 256;
+                let mut field = Heightfield::new(
+                    HeightfieldGrid {
+                        origin: glam::Vec2::splat(MIDDLE - CELLS as f32 * CS / 2.0),
+                        cells: glam::UVec2::splat(CELLS),
+                        cell_size: CS,
+                    },
+                    move |x, z| smooth_height(x / CS, z / CS) * CS,
+                    tint,
+                )
+                .with_texture_tile(CS);
+                field.init(gl_fns.clone(), Mat3DUpdate::default(), &[])?;
+                Floor::Heightfield(field)
+            }
+            FloorKind::LodHeightfield => {
+                /// Patches along each side, centered on the middle
+                const PATCHES: u32 = 32;
+                let side = (PATCHES * DEFAULT_PATCH_CELLS) as f32 * CS;
+                let mut terrain = LodTerrain::new(
+                    LodGrid {
+                        origin: glam::Vec2::splat(MIDDLE - side / 2.0),
+                        patches: glam::UVec2::splat(PATCHES),
+                        patch_cells: DEFAULT_PATCH_CELLS,
+                        cell_size: CS,
+                    },
+                    move |x, z| smooth_height(x / CS, z / CS) * CS,
+                    tint,
+                )
+                .with_texture_tile(CS);
+                terrain.init(gl_fns.clone(), Mat3DUpdate::default(), &[])?;
+                Floor::Lod(terrain)
+            }
+        };
+        // Teapots stand on top of the voxels, or on the smooth surface
+        let ground = |x: f32, z: f32| match &floor {
+            Floor::Voxels(_) => tb(x.round() as usize, z.round() as usize) as f32 + 0.5,
+            Floor::Heightfield(field) => field.height(x, z),
+            Floor::Lod(terrain) => terrain.height(x, z),
+        };
+
+        let utahs = UtahTeapot::batch(
+            [
+                glam::Vec2::new(3.0 + MIDDLE, 5.0 + MIDDLE),
+                glam::Vec2::new(MIDDLE - 5.0, MIDDLE + 2.0),
+                glam::Vec2::new(MIDDLE, MIDDLE),
+            ]
+            .map(|utah| {
+                (
+                    GlPosition::new(utah.x, ground(utah.x, utah.y), utah.y),
+                    Vec3::new(1.0, 0.0, 0.0),
+                )
+            }),
+        );
+
+        let mut entities: Vec<Box<dyn Entity>> = vec![
+            Box::new(HelloTriangle::new((
+                GlPosition::new(MIDDLE + 3.0, surface + 2.0, MIDDLE + 3.0),
+                CS,
+            ))),
+            Box::new(utahs),
+        ];
+
+        let generator = world_config.generator();
+        let is_dry = |p: &Placement| match &floor {
+            Floor::Voxels(_) => {
+                !generator.is_underwater(p.position.x.round() as i32, p.position.z.round() as i32)
+            }
+            Floor::Heightfield(_) | Floor::Lod(_) => p.position.y > WATER_HEIGHT,
+        };
+
+        // Trees, rocks and grass around the middle out of the water, the same on
+        // every run
+        const SCATTERED: f32 = 96.0 * CS;
+        for plant in Plant::ALL {
+            let mut placements = scatter(
+                &plant.rule(123),
+                glam::Vec2::splat(MIDDLE - SCATTERED / 2.0),
+                glam::Vec2::splat(SCATTERED),
+                123 + plant as u64,
+                ground,
+            );
+            placements.retain(is_dry);
+            entities.push(Box::new(Vegetation::new(obj_path(plant), placements)));
+        }
+
+        let mut sun = Sun::new(GlPosition::new(MIDDLE, surface + 10.0, MIDDLE));
+
+        // Init Glsl for drawables
+        for entity in entities.iter_mut() {
+            entity.init(gl_fns.clone(), Mat3DUpdate::default(), &[])?;
+        }
+        let mut highlight = BlockHighlight::new(CS);
+        highlight.init(gl_fns.clone(), Mat3DUpdate::default(), &[])?;
+        /// Side of the water surface, well past the fog
+        const WATER_SIZE: f32 = 512.0 * CS;
+        let mut water = Water::new(glam::Vec2::splat(MIDDLE), WATER_SIZE, WATER_HEIGHT);
+        water.init(gl_fns.clone(), Mat3DUpdate::default(), &[])?;
+        sun.init(gl_fns, Mat3DUpdate::default(), &[])?;
+
+        Ok(Self {
+            entities,
+            floor,
+            world_config,
+            highlight,
+            held_block: DIRT,
+            lighting: LightingGlobals::new(sun.get_pos(), CLEAR_COLOR),
+            sun,
+            // Above the middle teapot
+            camera: Camera::from_pos(GlPosition::new(MIDDLE, surface + 4.5, MIDDLE)),
+            water,
+            water_targets: None,
+        })
+    }
+
+    /// Rebuilds the programs whose shader files changed on disk. A program that
+    /// fails to build is logged and the previous one is kept.
+    pub fn reload_shaders(&mut self) {
+        let entities = self
+            .entities
+            .iter_mut()
+            .map(|e| e.as_mut() as &mut dyn GlslPass);
+
+        let others = [
+            self.floor.as_pass_mut(),
+            &mut self.highlight as &mut dyn GlslPass,
+            &mut self.sun as &mut dyn GlslPass,
+            &mut self.water as &mut dyn GlslPass,
+        ];
+        for pass in entities.chain(others) {
+            match pass.reload_shader(&[]) {
+                Ok(true) => log::info!("Shader reloaded"),
+                Ok(false) => (),
+                Err(err) => {
+                    log::error!("Shader reload failed, keeping the previous program: {err}")
+                }
+            }
+        }
+    }
+
+    /// Removes the block the camera looks at, if any within [`REACH`]. Its
+    /// chunk is remeshed over the next frames.
+    pub fn break_block(&mut self) {
+        let Some(terrain) = self.floor.voxels_mut() else {
+            return;
+        };
+        if let Some(hit) = terrain.raycast(self.camera.pos, self.camera.front(), REACH) {
+            terrain.break_block(&hit);
+        }
+    }
+
+    /// Puts [`Scene::held_block`] against the face the camera looks at, unless
+    /// the camera is in the way.
+    pub fn place_block(&mut self) {
+        let Some(terrain) = self.floor.voxels_mut() else {
+            return;
+        };
+        let Some(hit) = terrain.raycast(self.camera.pos, self.camera.front(), REACH) else {
+            return;
+        };
+        if hit.voxel + hit.normal != terrain.voxel_at(self.camera.pos) {
+            terrain.place_block(&hit, self.held_block);
+        }
+    }
+
+    /// Streams the voxel terrain until everything in range around the camera
+    /// is meshed, so the next frame shows all of it.
+    pub fn finish_streaming(&mut self) {
+        if let Some(terrain) = self.floor.voxels_mut() {
+            terrain.finish_streaming(self.camera.pos);
+        }
+    }
+
+    /// Writes the world config and the edited chunks to `path`. Only voxel
+    /// floors are saved.
+    pub fn save_world(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let terrain = self.floor.voxels().ok_or_else(not_voxels)?;
+        WorldSave::of(self.world_config, terrain.world()).save(path)
+    }
+
+    /// Replaces the voxel terrain by the world saved at `path`. On error, the
+    /// current terrain is kept.
+    ///
+    /// Only saves of the world generated from [`Scene::world_config`] are
+    /// loaded: the teapots and plants stand on its surface, out of its water.
+    pub fn load_world(&mut self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let terrain = self.floor.voxels().ok_or_else(not_voxels)?;
+        let save = WorldSave::load(path)?;
+        if save.config != self.world_config {
+            return Err(SaveError::OtherWorld);
+        }
+        let Some(shader) = terrain.get_shader() else {
+            log::warn!("Tried to load a world before init");
+            return Ok(());
+        };
+        match voxel_terrain(save.into_world(VIEW_RADIUS), shader.gl_fns.clone()) {
+            Ok(terrain) => self.floor = Floor::Voxels(terrain),
+            Err(err) => log::error!("Could not build the loaded terrain: {err}"),
+        }
+        Ok(())
+    }
+
+    /// Advances the camera by `dt` and draws one frame into whatever framebuffer
+    /// is currently bound.
+    ///
+    /// The world is drawn three times: mirrored about the water for its
+    /// reflection, under the water for its refraction, then as seen.
+    pub fn render(&mut self, renderer: &mut Renderer, dt: &Duration) {
+        let Self {
+            entities,
+            floor,
+            world_config: _,
+            highlight,
+            held_block: _,
+            sun,
+            camera,
+            lighting,
+            water,
+            water_targets,
+        } = self;
+
+        camera.update(dt);
+        water.advance(dt);
+        if let Floor::Lod(terrain) = floor {
+            terrain.update_lod(camera.pos);
+        }
+        let target = floor.voxels_mut().and_then(|terrain| {
+            terrain.stream_around(camera.pos);
+            terrain.raycast(camera.pos, camera.front(), REACH)
+        });
+        highlight.set_target(target.map(|hit| hit.voxel));
+
+        let dimensions = renderer.get_window_dimensions();
+        let frame = FrameGlobals::new(
+            camera.as_view(),
+            glam::Vec2::new(dimensions.x as f32, dimensions.y as f32),
+            camera.pos,
+        );
+        lighting.light_pos = sun.get_pos();
+
+        // Half the resolution is plenty under the ripples
+        let size = (dimensions / 2).max(glam::USizeVec2::ONE);
+        if water_targets
+            .as_ref()
+            .is_none_or(|(reflection, _)| reflection.get_dimensions() != size)
+        {
+            *water_targets = match (renderer.render_texture(size), renderer.render_texture(size)) {
+                (Ok(reflection), Ok(refraction)) => Some((reflection, refraction)),
+                (Err(err), _) | (_, Err(err)) => {
+                    log::error!("Could not create the water render targets: {err}");
+                    None
+                }
+            };
+        }
+        if let Some((reflection, refraction)) = water_targets {
+            let height = water.height();
+            for (target, globals) in [
+                (&*reflection, reflected(&frame, height)),
+                (&*refraction, refracted(&frame, height)),
+            ] {
+                renderer.render_to(target, |renderer| {
+                    renderer.clear();
+                    renderer.bind_globals(&globals, lighting);
+                    draw_world(renderer, sun, entities, floor);
+                });
+            }
+            water.set_targets(reflection.texture(), refraction.texture());
+        }
+
+        renderer.clear();
+        renderer.bind_globals(&frame, lighting);
+        draw_world(renderer, sun, entities, floor);
+        // Without its targets, the water has nothing to show
+        let water = water_targets
+            .is_some()
+            .then_some(water as &mut dyn GlslPass);
+        renderer.draw(
+            [highlight as &mut dyn GlslPass].into_iter().chain(water),
+            Mat3DUpdate::default(),
+            &[],
+        );
+    }
+}
+
+/// Draws the sun, the entities and the floor with the bound globals, what
+/// the water reflects and shows under it.
+fn draw_world(
+    renderer: &mut Renderer,
+    sun: &mut Sun,
+    entities: &mut [Box<dyn Entity>],
+    floor: &mut Floor,
+) {
+    renderer.draw(
+        [sun as &mut dyn GlslPass].into_iter(),
+        Mat3DUpdate::default(),
+        &[],
+    );
+    let renderer_refs = entities
+        .iter_mut()
+        .map(|e| e.as_mut() as &mut dyn GlslPass)
+        .chain([floor.as_pass_mut()]);
+    renderer.draw(renderer_refs, Mat3DUpdate::default(), &[]);
+}
+
+/// A [`ChunkedTerrain`] of `world` textured with the standard blocks, its GL
+/// resources initialized.
+fn voxel_terrain(
+    world: ChunkWorld,
+    gl_fns: Rc<dyn GlBackend>,
+) -> Result<ChunkedTerrain, ShaderError> {
+    let mut terrain = ChunkedTerrain::new(world, CS, Arc::new(BlockRegistry::standard()));
+    terrain.init(gl_fns, Mat3DUpdate::default(), &[])?;
+    Ok(terrain)
+}
+
+fn not_voxels() -> SaveError {
+    SaveError::Io(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "only voxel floors are saved",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::backend::mock::MockGl;
+
+    #[test]
+    fn saves_of_other_worlds_are_not_loaded() {
+        let mut scene = Scene::new(Rc::new(MockGl::default())).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "glutin-hello-world-other-world-{}.sav",
+            std::process::id()
+        ));
+
+        let mut other = scene.world_config;
+        other.height += 1;
+        WorldSave::of(other, &ChunkWorld::new(other.generator(), 0))
+            .save(&path)
+            .unwrap();
+        let loaded = scene.load_world(&path);
+        assert!(matches!(loaded, Err(SaveError::OtherWorld)), "{loaded:?}");
+
+        scene.save_world(&path).unwrap();
+        let loaded = scene.load_world(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_ok(), "{loaded:?}");
+    }
+}
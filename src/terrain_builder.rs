@@ -1,19 +1,417 @@
-use noise::{NoiseFn, Perlin};
+use noise::{
+    Billow, Clamp, Fbm, MultiFractal, NoiseFn, Perlin, RidgedMulti, ScaleBias, Seedable, Turbulence,
+};
 
 pub fn terrain_builder(seed: u32, height: usize) -> impl Fn(usize, usize) -> usize {
-    let perlin = Perlin::new(seed);
+    let height_at = terrain_height(seed, height);
+    move |x: usize, z: usize| height_at(x as i32, z as i32)
+}
+
+/// The surface height of the column `(x, z)`, for any column including
+/// negative ones, as endless worlds need.
+pub fn terrain_height(seed: u32, height: usize) -> impl Fn(i32, i32) -> usize + Send + Sync {
+    TerrainConfig::new(seed, height).height_fn()
+}
+
+/// How the octaves of a [`TerrainConfig`] are combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NoiseMode {
+    /// Plain fractal Brownian motion, rolling hills
+    #[default]
+    Fbm,
+    /// Sharp crests, mountain ranges
+    Ridged,
+    /// Rounded bumps, dunes and clouds
+    Billow,
+}
+
+/// Displaces the sampled columns by another noise, bending the terrain
+/// features.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DomainWarp {
+    /// Frequency of the displacement noise, per column
+    pub frequency: f64,
+    /// How far columns are displaced, in noise space
+    pub power: f64,
+}
+
+/// A surface generator, turned into a height function with
+/// [`TerrainConfig::height_fn`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainConfig {
+    pub seed: u32,
+    /// Noise layers, each `lacunarity` times finer and `persistence` times
+    /// weaker than the previous one
+    pub octaves: usize,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    /// Frequency of the first octave, per column. Smaller is larger hills.
+    pub frequency: f64,
+    /// Heights stay within `base_height ± amplitude`
+    pub amplitude: f64,
+    pub base_height: f64,
+    pub mode: NoiseMode,
+    pub warp: Option<DomainWarp>,
+}
+
+type HeightNoise = Box<dyn NoiseFn<f64, 2> + Send + Sync>;
+
+impl TerrainConfig {
+    /// Smooth single-octave hills varying between 0 and `height`.
+    pub fn new(seed: u32, height: usize) -> Self {
+        let amplitude = height as f64 / 2.0;
+        Self {
+            seed,
+            octaves: 1,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            frequency: 0.01,
+            amplitude,
+            base_height: amplitude,
+            mode: NoiseMode::default(),
+            warp: None,
+        }
+    }
+
+    /// The noise of this config, in blocks: `get([x, z])` is the surface
+    /// height of column `(x, z)`.
+    pub fn noise(&self) -> HeightNoise {
+        let fractal: HeightNoise = match self.mode {
+            NoiseMode::Fbm => self.fractal::<Fbm<Perlin>>(),
+            NoiseMode::Ridged => self.fractal::<RidgedMulti<Perlin>>(),
+            NoiseMode::Billow => self.fractal::<Billow<Perlin>>(),
+        };
+        let warped: HeightNoise = match self.warp {
+            Some(warp) => Box::new(
+                Turbulence::<_, Perlin>::new(fractal)
+                    .set_seed(self.seed.wrapping_add(self.octaves as u32))
+                    .set_frequency(warp.frequency)
+                    .set_power(warp.power),
+            ),
+            None => fractal,
+        };
+
+        // Fractals can slightly overshoot [-1, 1]
+        let bounded = Clamp::new(warped).set_bounds(-1.0, 1.0);
+        Box::new(
+            ScaleBias::new(bounded)
+                .set_scale(self.amplitude)
+                .set_bias(self.base_height),
+        )
+    }
+
+    /// The surface height of the column `(x, z)`, never below 0.
+    pub fn height_fn(&self) -> impl Fn(i32, i32) -> usize + Send + Sync + use<> {
+        let noise = self.noise();
+        move |x: i32, z: i32| noise.get([x as f64, z as f64]).max(0.0) as usize
+    }
+
+    /// The surface height anywhere on the plane, not rounded to whole
+    /// voxels, for smooth terrains. Matches [`TerrainConfig::height_fn`] once
+    /// floored at the column positions.
+    pub fn smooth_height_fn(&self) -> impl Fn(f32, f32) -> f32 + Send + Sync + use<> {
+        let noise = self.noise();
+        move |x: f32, z: f32| noise.get([x as f64, z as f64]).max(0.0) as f32
+    }
+
+    /// The configured fractal, sampled in column units.
+    fn fractal<F>(&self) -> HeightNoise
+    where
+        F: MultiFractal + Seedable + Default + NoiseFn<f64, 2> + Send + Sync + 'static,
+    {
+        Box::new(
+            F::default()
+                .set_seed(self.seed)
+                .set_octaves(self.octaves.max(1))
+                .set_frequency(self.frequency)
+                .set_lacunarity(self.lacunarity)
+                .set_persistence(self.persistence),
+        )
+    }
+}
+
+/// Tunnels carved where two 3D noises are both near zero, the intersection
+/// of their zero surfaces being a winding line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CaveConfig {
+    pub seed: u32,
+    /// Frequency of the carving noises, per voxel. Smaller is longer tunnels.
+    pub frequency: f64,
+    /// How close to zero both noises must be for a voxel to be carved, wider
+    /// tunnels the higher. About 0.1 gives tunnels a few voxels across.
+    pub radius: f64,
+    /// Voxels under the surface left uncarved, 0 lets tunnels open on the
+    /// surface.
+    pub min_depth: usize,
+}
+
+impl CaveConfig {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            frequency: 0.04,
+            radius: 0.1,
+            min_depth: 0,
+        }
+    }
+}
+
+/// A 3D terrain: the [`TerrainConfig`] surface, bent by 3D noise into
+/// overhangs and cliffs, and carved by caves. Queried voxel by voxel with
+/// [`DensityConfig::solid_fn`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DensityConfig {
+    pub surface: TerrainConfig,
+    /// How far the 3D noise moves the surface up or down, in voxels. 0 keeps
+    /// the surface of the heightmap, overhangs need about 10 at the default
+    /// frequency.
+    pub overhang: f64,
+    /// Frequency of the overhang noise, per voxel
+    pub overhang_frequency: f64,
+    pub caves: Option<CaveConfig>,
+}
+
+impl DensityConfig {
+    /// The `surface` heightmap, without overhangs nor caves.
+    pub fn new(surface: TerrainConfig) -> Self {
+        Self {
+            surface,
+            overhang: 0.0,
+            overhang_frequency: 0.08,
+            caves: None,
+        }
+    }
+
+    /// Whether the voxel `(x, y, z)` is solid. The bottom layer, `y == 0`, is
+    /// never carved so the world has a floor; nothing is solid below it.
+    pub fn solid_fn(&self) -> impl Fn(i32, i32, i32) -> bool + Send + Sync + use<> {
+        let surface = self.surface.height_fn();
+        let overhang = self.overhang;
+        let bend = (overhang != 0.0).then(|| {
+            Fbm::<Perlin>::new(self.surface.seed.wrapping_add(1000))
+                .set_octaves(3)
+                .set_frequency(self.overhang_frequency)
+        });
+        let caves = self.caves.map(|caves| {
+            let tunnels = [caves.seed, caves.seed.wrapping_add(1)].map(Perlin::new);
+            (caves, tunnels)
+        });
+
+        move |x: i32, y: i32, z: i32| {
+            if y < 0 {
+                return false;
+            }
+            let surface = surface(x, z) as f64;
+            let point = [x as f64, y as f64, z as f64];
 
-    // Height is the approximate "maximum" elevation of terrain variation.
-    // We'll use it as the amplitude range.
-    move |x: usize, z: usize| {
-        let scale = 0.01; // smaller = larger hills / smoother terrain
-        let amplitude = height as f64 / 2.0; // vertical variation (half up, half down)
-        let base = amplitude; // midline, so terrain stays roughly within 0..height
+            // Positive inside the ground, in voxels above the surface
+            let mut density = surface - y as f64;
+            if let Some(bend) = &bend {
+                density += overhang * bend.get(point);
+            }
+            if density < 0.0 {
+                return false;
+            }
 
-        // Noise value is between -1.0 and +1.0
-        let height_value = perlin.get([x as f64 * scale, z as f64 * scale]) * amplitude + base;
+            match &caves {
+                Some((caves, tunnels)) if y > 0 && y as f64 + caves.min_depth as f64 <= surface => {
+                    let p = point.map(|c| c * caves.frequency);
+                    tunnels
+                        .iter()
+                        .any(|noise| noise.get(p).abs() >= caves.radius)
+                }
+                _ => true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> impl Iterator<Item = (i32, i32)> {
+        (-40..40).flat_map(|x| (-40..40).map(move |z| (x * 7, z * 5)))
+    }
+
+    fn heights(config: &TerrainConfig) -> Vec<usize> {
+        let height_at = config.height_fn();
+        columns().map(|(x, z)| height_at(x, z)).collect()
+    }
+
+    fn all_modes(seed: u32) -> Vec<TerrainConfig> {
+        [NoiseMode::Fbm, NoiseMode::Ridged, NoiseMode::Billow]
+            .into_iter()
+            .flat_map(|mode| {
+                [
+                    None,
+                    Some(DomainWarp {
+                        frequency: 0.02,
+                        power: 4.0,
+                    }),
+                ]
+                .map(|warp| TerrainConfig {
+                    octaves: 5,
+                    mode,
+                    warp,
+                    ..TerrainConfig::new(seed, 16)
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn default_config_matches_a_single_perlin_layer() {
+        let perlin = Perlin::new(123);
+        let expected: Vec<_> = columns()
+            .map(|(x, z)| (perlin.get([x as f64 * 0.01, z as f64 * 0.01]) * 4.0 + 4.0) as usize)
+            .collect();
+        assert_eq!(heights(&TerrainConfig::new(123, 8)), expected);
+    }
+
+    #[test]
+    fn same_seed_same_terrain() {
+        for (config, other_seed) in all_modes(123).iter().zip(all_modes(321)) {
+            assert_eq!(heights(config), heights(config), "{config:?}");
+            assert_ne!(heights(config), heights(&other_seed), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn heights_stay_within_the_amplitude() {
+        for config in all_modes(7) {
+            let noise = config.noise();
+            let values: Vec<_> = columns()
+                .map(|(x, z)| noise.get([x as f64, z as f64]))
+                .collect();
+            assert!(
+                values.iter().all(|v| (0.0..=16.0).contains(v)),
+                "{config:?}"
+            );
+
+            // And actually vary
+            let (min, max) = values
+                .iter()
+                .fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+            assert!(max - min > 2.0, "{config:?} is flat");
+        }
+    }
+
+    #[test]
+    fn modes_and_octaves_change_the_terrain() {
+        let configs = all_modes(123);
+        let unwarped: Vec<_> = configs.iter().step_by(2).map(heights).collect();
+        assert_ne!(unwarped[0], unwarped[1]);
+        assert_ne!(unwarped[0], unwarped[2]);
+        assert_ne!(unwarped[1], unwarped[2]);
+        assert_ne!(
+            heights(&configs[0]),
+            heights(&configs[1]),
+            "warp changes nothing"
+        );
+
+        let single = TerrainConfig::new(123, 16);
+        let detailed = TerrainConfig {
+            octaves: 5,
+            ..single
+        };
+        assert_ne!(heights(&single), heights(&detailed));
+    }
+
+    /// Carved voxels under the surface in a 64 by 64 area, bottom up.
+    fn carved(config: &DensityConfig) -> Vec<(i32, i32, i32)> {
+        let solid = config.solid_fn();
+        let height_at = config.surface.height_fn();
+        let mut carved = vec![];
+        for x in 0..64 {
+            for z in 0..64 {
+                for y in 0..height_at(x, z) as i32 {
+                    if !solid(x, y, z) {
+                        carved.push((x, y, z));
+                    }
+                }
+            }
+        }
+        carved
+    }
+
+    fn caves(seed: u32) -> DensityConfig {
+        DensityConfig {
+            caves: Some(CaveConfig::new(seed)),
+            ..DensityConfig::new(TerrainConfig::new(seed, 48))
+        }
+    }
+
+    #[test]
+    fn without_carving_the_surface_is_the_heightmap() {
+        let surface = TerrainConfig {
+            octaves: 4,
+            ..TerrainConfig::new(5, 24)
+        };
+        let height_at = surface.height_fn();
+        let solid = DensityConfig::new(surface).solid_fn();
+
+        for (x, z) in columns() {
+            let height = height_at(x, z) as i32;
+            for y in -1..30 {
+                assert_eq!(solid(x, y, z), (0..=height).contains(&y), "{x} {y} {z}");
+            }
+        }
+    }
+
+    #[test]
+    fn tunnels_run_under_the_surface() {
+        for seed in [1, 123] {
+            let config = caves(seed);
+            let carved = carved(&config);
+            assert!(carved.len() > 100, "seed {seed}: {} carved", carved.len());
+            assert!(carved.iter().all(|(_, y, _)| *y > 0), "the floor is kept");
+
+            // Along x under a solid ceiling, a tunnel rather than a pit
+            let solid = config.solid_fn();
+            let tunnel = carved
+                .iter()
+                .any(|&(x, y, z)| (0..5).all(|dx| !solid(x + dx, y, z) && solid(x + dx, y + 1, z)))
+                || carved.iter().any(|&(x, y, z)| {
+                    (0..5).all(|dz| !solid(x, y, z + dz) && solid(x, y + 1, z + dz))
+                });
+            assert!(tunnel, "seed {seed} has no tunnel");
+        }
+    }
+
+    #[test]
+    fn caves_depend_on_the_seed_only() {
+        assert_eq!(carved(&caves(123)), carved(&caves(123)));
+        let other_caves = DensityConfig {
+            caves: Some(CaveConfig::new(321)),
+            ..caves(123)
+        };
+        assert_ne!(carved(&caves(123)), carved(&other_caves));
+
+        let deep = DensityConfig {
+            caves: Some(CaveConfig {
+                min_depth: 6,
+                ..CaveConfig::new(123)
+            }),
+            ..caves(123)
+        };
+        let height_at = deep.surface.height_fn();
+        assert!(
+            carved(&deep)
+                .iter()
+                .all(|&(x, y, z)| y + 6 <= height_at(x, z) as i32)
+        );
+    }
 
-        // Return true if voxel is below the terrain surface
-        height_value as usize
+    #[test]
+    fn overhangs_put_ground_over_air() {
+        let solid = DensityConfig {
+            overhang: 12.0,
+            ..DensityConfig::new(TerrainConfig::new(9, 16))
+        }
+        .solid_fn();
+        let overhanging =
+            columns().any(|(x, z)| (1..40).any(|y| !solid(x, y, z) && solid(x, y + 1, z)));
+        assert!(overhanging);
     }
 }
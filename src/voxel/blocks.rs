@@ -0,0 +1,295 @@
+use std::path::PathBuf;
+
+use image::{ImageError, Rgb, RgbImage};
+
+use crate::{
+    renderer::atlas::{AtlasLayout, UvRect},
+    voxel::{AIR, BlockId, DIRT, Face, GRASS, SAND, SNOW, STONE, WATER},
+};
+
+/// The texture every default block is made from.
+pub const DIRT_TEXTURE: &str = "./assets/dirt.webp";
+/// Side of a block texture in the atlas, in pixels.
+pub const ATLAS_TILE_SIZE: u32 = 64;
+
+/// Where the pixels of a block face come from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureSource {
+    File(PathBuf),
+    /// The image at `path` in grayscale, tinted so its average color is
+    /// `color`: one texture for many blocks.
+    Tinted {
+        path: PathBuf,
+        color: [u8; 3],
+    },
+}
+
+impl TextureSource {
+    pub fn load(&self) -> Result<RgbImage, ImageError> {
+        match self {
+            TextureSource::File(path) => Ok(image::open(path)?.to_rgb8()),
+            TextureSource::Tinted { path, color } => {
+                let gray = image::open(path)?.to_luma8();
+                let mean = gray.pixels().map(|p| p.0[0] as f32).sum::<f32>()
+                    / gray.pixels().len().max(1) as f32;
+                let scale = 1.0 / mean.max(1.0);
+                Ok(RgbImage::from_fn(gray.width(), gray.height(), |x, y| {
+                    let lum = gray.get_pixel(x, y).0[0] as f32 * scale;
+                    Rgb(color.map(|c| (c as f32 * lum).min(255.0) as u8))
+                }))
+            }
+        }
+    }
+}
+
+/// Index of a texture in a [`BlockRegistry`], and of its tile in the atlas.
+pub type TextureId = u32;
+
+/// How a block looks, by face.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockType {
+    pub name: &'static str,
+    pub top: TextureId,
+    pub side: TextureId,
+    pub bottom: TextureId,
+}
+
+impl BlockType {
+    /// The same texture on every face.
+    pub fn uniform(name: &'static str, texture: TextureId) -> Self {
+        Self {
+            name,
+            top: texture,
+            side: texture,
+            bottom: texture,
+        }
+    }
+
+    pub fn texture(&self, face: Face) -> TextureId {
+        match face {
+            Face::Top => self.top,
+            Face::Bottom => self.bottom,
+            Face::Front | Face::Back | Face::Right | Face::Left => self.side,
+        }
+    }
+}
+
+/// The known blocks and their textures, all packed into one atlas.
+///
+/// The atlas layout only depends on the number of textures, so meshes can be
+/// built with [`BlockRegistry::uv_rect`] before the atlas is.
+#[derive(Clone, Debug, Default)]
+pub struct BlockRegistry {
+    /// By [`BlockId`], `None` for unregistered ids
+    blocks: Vec<Option<BlockType>>,
+    textures: Vec<TextureSource>,
+}
+
+impl BlockRegistry {
+    /// Grass, dirt, stone, sand, snow and water, all from [`DIRT_TEXTURE`].
+    pub fn standard() -> Self {
+        let mut registry = Self::default();
+        let tinted = |color| TextureSource::Tinted {
+            path: DIRT_TEXTURE.into(),
+            color,
+        };
+
+        let dirt = registry.add_texture(TextureSource::File(DIRT_TEXTURE.into()));
+        let grass = registry.add_texture(tinted([96, 150, 56]));
+        let stone = registry.add_texture(tinted([125, 125, 128]));
+        let sand = registry.add_texture(tinted([214, 196, 140]));
+        let snow = registry.add_texture(tinted([236, 240, 245]));
+        let water = registry.add_texture(tinted([52, 92, 170]));
+
+        registry.register(DIRT, BlockType::uniform("dirt", dirt));
+        registry.register(
+            GRASS,
+            BlockType {
+                name: "grass",
+                top: grass,
+                side: dirt,
+                bottom: dirt,
+            },
+        );
+        registry.register(STONE, BlockType::uniform("stone", stone));
+        registry.register(SAND, BlockType::uniform("sand", sand));
+        registry.register(SNOW, BlockType::uniform("snow", snow));
+        registry.register(WATER, BlockType::uniform("water", water));
+        registry
+    }
+
+    /// Adds `source` to the atlas, unless already there.
+    pub fn add_texture(&mut self, source: TextureSource) -> TextureId {
+        let index = match self.textures.iter().position(|known| *known == source) {
+            Some(index) => index,
+            None => {
+                self.textures.push(source);
+                self.textures.len() - 1
+            }
+        };
+        index as TextureId
+    }
+
+    /// Registers `block` as `id`, replacing the block registered before.
+    ///
+    /// # Panics
+    /// If `id` is [`AIR`], which is never drawn, or `block` uses a texture not
+    /// added.
+    pub fn register(&mut self, id: BlockId, block: BlockType) {
+        assert_ne!(id, AIR, "AIR can't be registered");
+        let textures = self.textures.len() as TextureId;
+        assert!(
+            [block.top, block.side, block.bottom]
+                .iter()
+                .all(|texture| *texture < textures),
+            "{} uses an unknown texture",
+            block.name
+        );
+
+        let index = id as usize;
+        if self.blocks.len() <= index {
+            self.blocks.resize(index + 1, None);
+        }
+        self.blocks[index] = Some(block);
+    }
+
+    pub fn block(&self, id: BlockId) -> Option<&BlockType> {
+        self.blocks.get(id as usize)?.as_ref()
+    }
+
+    pub fn layout(&self) -> AtlasLayout {
+        AtlasLayout::new(self.textures.len() as u32, ATLAS_TILE_SIZE)
+    }
+
+    /// Where `face` of `block` is in the atlas, the whole texture for unknown
+    /// blocks.
+    pub fn uv_rect(&self, block: BlockId, face: Face) -> UvRect {
+        match self.block(block) {
+            Some(block) => self.layout().rect(block.texture(face)),
+            None => UvRect::FULL,
+        }
+    }
+
+    /// Loads every texture and packs them, see [`BlockRegistry::layout`].
+    pub fn build_atlas(&self) -> Result<RgbImage, ImageError> {
+        let tiles = self
+            .textures
+            .iter()
+            .map(TextureSource::load)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.layout().pack(&tiles))
+    }
+}
+
+/// Picks the blocks of generated terrain from their height and slope.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockLayers {
+    /// Surfaces at or below it are sand, beaches and sea beds
+    pub sea_level: i32,
+    /// Surfaces at or above it are snow
+    pub snow_line: i32,
+    /// Columns rising more than this to a neighbouring column are bare stone
+    pub max_soil_slope: i32,
+    /// Voxels of dirt, or sand, under the surface before the stone
+    pub soil_depth: i32,
+}
+
+impl BlockLayers {
+    /// The block of a solid voxel at height `y`, `depth` solid voxels under
+    /// the surface of its column, where the column rises or falls at most
+    /// `slope` voxels to its neighbours.
+    pub fn block(&self, y: i32, depth: i32, slope: i32) -> BlockId {
+        let surface = y + depth;
+        if depth > self.soil_depth || slope > self.max_soil_slope {
+            STONE
+        } else if surface <= self.sea_level {
+            SAND
+        } else if depth > 0 {
+            DIRT
+        } else if y >= self.snow_line {
+            SNOW
+        } else {
+            GRASS
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faces_pick_their_texture() {
+        let registry = BlockRegistry::standard();
+        let rect = |block, face| registry.uv_rect(block, face);
+
+        assert_ne!(rect(GRASS, Face::Top), rect(GRASS, Face::Front));
+        assert_eq!(rect(GRASS, Face::Front), rect(GRASS, Face::Left));
+        assert_eq!(rect(GRASS, Face::Bottom), rect(DIRT, Face::Top), "shared");
+        for face in Face::ALL {
+            assert_eq!(rect(STONE, face), rect(STONE, Face::Top));
+        }
+        assert_eq!(rect(42, Face::Top), UvRect::FULL);
+        assert_eq!(registry.block(AIR), None);
+    }
+
+    #[test]
+    fn textures_are_added_once() {
+        let mut registry = BlockRegistry::default();
+        let dirt = TextureSource::File(DIRT_TEXTURE.into());
+        let first = registry.add_texture(dirt.clone());
+        assert_eq!(registry.add_texture(dirt), first);
+        assert_eq!(registry.layout().tiles, 1);
+    }
+
+    #[test]
+    fn atlas_tiles_match_their_blocks() {
+        let registry = BlockRegistry::standard();
+        let atlas = registry.build_atlas().unwrap();
+        let size = registry.layout().size();
+        assert_eq!((atlas.width(), atlas.height()), (size.x, size.y));
+
+        let mean = |block| {
+            let rect = registry.uv_rect(block, Face::Top);
+            let (min, max) = (
+                rect.min * size.as_vec2(),
+                (rect.min + rect.size) * size.as_vec2(),
+            );
+            let tile = image::imageops::crop_imm(
+                &atlas,
+                min.x as u32,
+                min.y as u32,
+                (max - min).x as u32,
+                (max - min).y as u32,
+            )
+            .to_image();
+            let sum = tile
+                .pixels()
+                .fold([0.0; 3], |sum, p| [0, 1, 2].map(|c| sum[c] + p.0[c] as f32));
+            sum.map(|c| c / tile.pixels().len() as f32)
+        };
+
+        let [r, g, b] = mean(GRASS);
+        assert!(g > r && g > b, "grass is green");
+        let [r, g, b] = mean(SNOW);
+        assert!(r.min(g).min(b) > 180.0, "snow is bright");
+    }
+
+    #[test]
+    fn layers_follow_height_and_slope() {
+        let layers = BlockLayers {
+            sea_level: 2,
+            snow_line: 10,
+            max_soil_slope: 2,
+            soil_depth: 3,
+        };
+
+        assert_eq!(layers.block(5, 0, 0), GRASS);
+        assert_eq!(layers.block(4, 1, 0), DIRT);
+        assert_eq!(layers.block(1, 4, 0), STONE, "under the soil");
+        assert_eq!(layers.block(12, 0, 1), SNOW);
+        assert_eq!(layers.block(2, 0, 0), SAND);
+        assert_eq!(layers.block(1, 1, 0), SAND, "under the beach");
+        assert_eq!(layers.block(5, 0, 3), STONE, "cliff");
+    }
+}
@@ -0,0 +1,248 @@
+use glam::IVec3;
+
+use crate::{
+    entities::tex_square::Square,
+    helpers::GlPosition,
+    renderer::atlas::UvRect,
+    voxel::{AIR, BlockId, Face, VoxelGrid},
+};
+
+/// The same surface as [`culled_faces`](crate::voxel::mesher::culled_faces),
+/// with the coplanar faces of neighbouring voxels of the same block merged into
+/// rectangles.
+///
+/// The squares span many voxels, draw them with a texture tile of `side_len` so
+/// the texture repeats once per voxel instead of stretching.
+pub fn greedy_faces(grid: &VoxelGrid, origin: GlPosition, side_len: f32) -> Vec<Square> {
+    greedy_faces_bordered(grid, origin, side_len, |_, _| UvRect::FULL, |_| false)
+}
+
+/// Like [`greedy_faces`], for a grid that is part of a larger world:
+/// `outside_solid` tells whether a voxel just outside the grid, in grid
+/// coordinates, is solid and hides the face against it. The squares show
+/// `uv_rect(block, face)` of the texture, like
+/// [`BlockRegistry::uv_rect`](crate::voxel::blocks::BlockRegistry::uv_rect).
+pub fn greedy_faces_bordered(
+    grid: &VoxelGrid,
+    origin: GlPosition,
+    side_len: f32,
+    uv_rect: impl Fn(BlockId, Face) -> UvRect,
+    outside_solid: impl Fn(IVec3) -> bool,
+) -> Vec<Square> {
+    let size = grid.size().as_ivec3();
+    let mut squares = vec![];
+
+    for face in Face::ALL {
+        // Slices are perpendicular to the face normal, u and v span them
+        let d = face.axis();
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        let (size_u, size_v) = (size[u], size[v]);
+        let at = |slice: i32, i: i32, j: i32| {
+            let mut pos = IVec3::ZERO;
+            pos[d] = slice;
+            pos[u] = i;
+            pos[v] = j;
+            pos
+        };
+        let index = |i: i32, j: i32| (i + j * size_u) as usize;
+
+        // The visible block of each voxel of a slice, AIR where there is no face
+        let mut mask = vec![AIR; (size_u * size_v) as usize];
+        for slice in 0..size[d] {
+            for j in 0..size_v {
+                for i in 0..size_u {
+                    let pos = at(slice, i, j);
+                    let neighbour = pos + face.normal();
+                    let hidden = if grid.contains(neighbour) {
+                        grid.is_solid(neighbour)
+                    } else {
+                        outside_solid(neighbour)
+                    };
+                    mask[index(i, j)] = if hidden { AIR } else { grid.get(pos) };
+                }
+            }
+
+            for j in 0..size_v {
+                let mut i = 0;
+                while i < size_u {
+                    let block = mask[index(i, j)];
+                    if block == AIR {
+                        i += 1;
+                        continue;
+                    }
+
+                    // Widen along u, then grow along v while whole rows match
+                    let mut width = 1;
+                    while i + width < size_u && mask[index(i + width, j)] == block {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    while j + height < size_v
+                        && (i..i + width).all(|k| mask[index(k, j + height)] == block)
+                    {
+                        height += 1;
+                    }
+
+                    for row in j..j + height {
+                        mask[index(i, row)..index(i + width, row)].fill(AIR);
+                    }
+
+                    let half_extents = at(1, width, height).as_vec3() * side_len / 2.0;
+                    // Voxels are centered on their position, the box starts half a voxel before
+                    let min_corner = origin + (at(slice, i, j).as_vec3() - 0.5) * side_len;
+                    squares.push(Square {
+                        uv_rect: uv_rect(block, face),
+                        ..face.box_square(&(min_corner + half_extents), half_extents)
+                    });
+
+                    i += width;
+                }
+            }
+        }
+    }
+
+    squares
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::{UVec3, Vec2, Vec3};
+
+    use super::*;
+    use crate::{
+        terrain_builder::terrain_builder,
+        voxel::{DIRT, STONE, blocks::BlockRegistry, mesher::culled_faces},
+    };
+
+    /// Total area of `squares`, by outward normal.
+    fn area_by_normal(squares: &[Square]) -> HashMap<IVec3, f32> {
+        let mut areas = HashMap::new();
+        for square in squares {
+            let normal = square.as_vertex_data()[0].normal.round().as_ivec3();
+            let extent = (square.top_right - square.bottom_left).abs();
+            let area = extent
+                .to_array()
+                .into_iter()
+                .filter(|e| *e != 0.0)
+                .product::<f32>();
+            *areas.entry(normal).or_default() += area;
+        }
+        areas
+    }
+
+    fn assert_same_surface(grid: &VoxelGrid) {
+        let origin = Vec3::new(-3.0, 1.0, 2.0);
+        let naive = culled_faces(grid, origin, 0.5);
+        let greedy = greedy_faces(grid, origin, 0.5);
+
+        assert!(greedy.len() <= naive.len());
+        assert_eq!(area_by_normal(&greedy), area_by_normal(&naive));
+    }
+
+    #[test]
+    fn covers_the_same_area_as_the_naive_mesher() {
+        assert_same_surface(&VoxelGrid::from_heightmap(
+            32,
+            DIRT,
+            terrain_builder(123, 8),
+        ));
+
+        // Scattered voxels of two blocks, with holes
+        let mut grid = VoxelGrid::new(UVec3::new(7, 5, 6));
+        for x in 0..7 {
+            for y in 0..5 {
+                for z in 0..6 {
+                    let block = [AIR, DIRT, STONE, DIRT][((x * 7 + y * 3 + z * 5) % 4) as usize];
+                    grid.set(UVec3::new(x, y, z), block);
+                }
+            }
+        }
+        assert_same_surface(&grid);
+    }
+
+    #[test]
+    fn flat_slab_is_one_square_per_side() {
+        let grid = VoxelGrid::from_heightmap(16, DIRT, |_, _| 2);
+        assert_eq!(greedy_faces(&grid, Vec3::ZERO, 1.0).len(), 6);
+    }
+
+    #[test]
+    fn different_blocks_are_not_merged() {
+        let mut grid = VoxelGrid::new(UVec3::new(4, 1, 1));
+        grid.set(UVec3::new(0, 0, 0), DIRT);
+        grid.set(UVec3::new(1, 0, 0), DIRT);
+        grid.set(UVec3::new(2, 0, 0), STONE);
+        grid.set(UVec3::new(3, 0, 0), STONE);
+
+        // 2 ends, and 2 squares for each of the 4 long sides
+        assert_eq!(greedy_faces(&grid, Vec3::ZERO, 1.0).len(), 2 + 4 * 2);
+    }
+
+    #[test]
+    fn solid_borders_hide_the_faces_against_them() {
+        let grid = VoxelGrid::from_heightmap(4, DIRT, |_, _| 1);
+        // Only the top is not against solid ground
+        let squares = greedy_faces_bordered(
+            &grid,
+            Vec3::ZERO,
+            1.0,
+            |_, _| UvRect::FULL,
+            |pos| pos.y <= 1,
+        );
+
+        assert_eq!(squares.len(), 1);
+        assert_eq!(squares[0].as_vertex_data()[0].normal, Vec3::Y);
+    }
+
+    #[test]
+    fn texture_repeats_once_per_voxel() {
+        let grid = VoxelGrid::from_heightmap(3, DIRT, |_, _| 0);
+        let top = greedy_faces(&grid, Vec3::ZERO, 2.0)
+            .into_iter()
+            .find(|square| square.as_vertex_data()[0].normal == Vec3::Y)
+            .unwrap();
+
+        let uvs = top.as_tiled_vertex_data(2.0).map(|vertex| vertex.tex_map);
+        assert_eq!(uvs[3], Vec2::new(3.0, 3.0));
+        assert!(uvs.iter().all(|uv| uv.min_element() >= 0.0));
+    }
+
+    #[test]
+    fn squares_show_the_tile_of_their_block_face() {
+        let mut grid = VoxelGrid::from_heightmap(4, STONE, |_, _| 1);
+        grid.set(UVec3::new(0, 1, 0), DIRT);
+        let blocks = BlockRegistry::standard();
+        let squares = greedy_faces_bordered(
+            &grid,
+            Vec3::ZERO,
+            1.0,
+            |b, f| blocks.uv_rect(b, f),
+            |_| false,
+        );
+
+        let face_of = |square: &Square| {
+            let normal = square.as_vertex_data()[0].normal.round().as_ivec3();
+            Face::ALL
+                .into_iter()
+                .find(|f| f.normal() == normal)
+                .unwrap()
+        };
+        let dirt = squares
+            .iter()
+            .filter(|square| square.uv_rect == blocks.uv_rect(DIRT, face_of(square)))
+            .count();
+        let stone = squares
+            .iter()
+            .filter(|square| square.uv_rect == blocks.uv_rect(STONE, face_of(square)))
+            .count();
+        assert_eq!(dirt + stone, squares.len());
+        assert_eq!(dirt, 3, "the top and outer sides of the dirt corner");
+        assert!(
+            squares
+                .iter()
+                .all(|square| square.as_vertex_data()[0].uv_rect == square.uv_rect)
+        );
+    }
+}
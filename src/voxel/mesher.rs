@@ -0,0 +1,92 @@
+use crate::{
+    entities::tex_square::Square,
+    helpers::GlPosition,
+    voxel::{Face, VoxelGrid},
+};
+
+/// The faces of the solid voxels of `grid` that touch an empty voxel, the only
+/// ones that can ever be seen. Voxel `(x, y, z)` is the cube of side `side_len`
+/// centered on `origin + (x, y, z) * side_len`.
+pub fn culled_faces(grid: &VoxelGrid, origin: GlPosition, side_len: f32) -> Vec<Square> {
+    grid.solid_voxels()
+        .flat_map(|voxel| {
+            let voxel = voxel.as_ivec3();
+            let center = origin + voxel.as_vec3() * side_len;
+            Face::ALL
+                .into_iter()
+                .filter(move |face| !grid.is_solid(voxel + face.normal()))
+                .map(move |face| face.square(&center, side_len))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec3, Vec3};
+
+    use super::*;
+    use crate::voxel::DIRT;
+
+    fn grid(size: UVec3, solid: &[UVec3]) -> VoxelGrid {
+        let mut grid = VoxelGrid::new(size);
+        for pos in solid {
+            grid.set(*pos, DIRT);
+        }
+        grid
+    }
+
+    fn filled(size: UVec3) -> VoxelGrid {
+        let mut grid = VoxelGrid::new(size);
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    grid.set(UVec3::new(x, y, z), DIRT);
+                }
+            }
+        }
+        grid
+    }
+
+    fn face_count(grid: &VoxelGrid) -> usize {
+        culled_faces(grid, Vec3::ZERO, 1.0).len()
+    }
+
+    #[test]
+    fn lone_voxel_keeps_all_six_faces() {
+        assert_eq!(face_count(&grid(UVec3::splat(3), &[UVec3::ONE])), 6);
+    }
+
+    #[test]
+    fn touching_voxels_drop_the_shared_faces() {
+        let pair = grid(UVec3::new(2, 1, 1), &[UVec3::ZERO, UVec3::X]);
+        assert_eq!(face_count(&pair), 10);
+        assert_eq!(face_count(&filled(UVec3::splat(2))), 24);
+        // 9 faces on each side of the cube, none for the buried center
+        assert_eq!(face_count(&filled(UVec3::splat(3))), 54);
+    }
+
+    #[test]
+    fn heightmap_columns_only_show_their_surface() {
+        // A flat 4x4 slab two voxels thick, with one column a voxel taller
+        let grid = VoxelGrid::from_heightmap(4, DIRT, |x, z| if (x, z) == (1, 1) { 2 } else { 1 });
+
+        assert_eq!(grid.solid_voxels().count(), 33);
+        // top and bottom, 4 sides of 4x2, the bump's 4 sides (its top replaces
+        // the slab's top under it)
+        assert_eq!(face_count(&grid), 16 * 2 + 4 * 8 + 4);
+    }
+
+    #[test]
+    fn faces_are_wound_toward_the_empty_neighbour() {
+        let voxel = UVec3::new(1, 2, 3);
+        let grid = grid(UVec3::splat(4), &[voxel]);
+        let center = voxel.as_vec3() * 2.0;
+
+        for square in culled_faces(&grid, Vec3::ZERO, 2.0) {
+            let vertices = square.as_vertex_data();
+            let face_center =
+                vertices.iter().map(|v| v.position).sum::<Vec3>() / vertices.len() as f32;
+            assert_eq!(vertices[0].normal, (face_center - center).normalize());
+        }
+    }
+}
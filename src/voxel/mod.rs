@@ -0,0 +1,179 @@
+use glam::{IVec3, UVec3};
+
+use crate::{entities::tex_square::Square, helpers::GlPosition, renderer::atlas::UvRect};
+
+pub mod blocks;
+pub mod greedy;
+pub mod mesher;
+pub mod raycast;
+pub mod save;
+pub mod workers;
+pub mod world;
+
+/// What fills a voxel, [`AIR`] for nothing.
+pub type BlockId = u8;
+
+pub const AIR: BlockId = 0;
+pub const DIRT: BlockId = 1;
+pub const GRASS: BlockId = 2;
+pub const STONE: BlockId = 3;
+pub const SAND: BlockId = 4;
+pub const SNOW: BlockId = 5;
+pub const WATER: BlockId = 6;
+
+/// One of the six axis aligned faces of a voxel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    Front,
+    Back,
+    Right,
+    Left,
+    Top,
+    Bottom,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::Front,
+        Face::Back,
+        Face::Right,
+        Face::Left,
+        Face::Top,
+        Face::Bottom,
+    ];
+
+    /// Outward direction of the face, also the offset to the neighbouring voxel
+    /// it touches.
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Face::Front => IVec3::Z,
+            Face::Back => IVec3::NEG_Z,
+            Face::Right => IVec3::X,
+            Face::Left => IVec3::NEG_X,
+            Face::Top => IVec3::Y,
+            Face::Bottom => IVec3::NEG_Y,
+        }
+    }
+
+    /// Index of the axis the face is perpendicular to.
+    pub fn axis(self) -> usize {
+        match self {
+            Face::Right | Face::Left => 0,
+            Face::Top | Face::Bottom => 1,
+            Face::Front | Face::Back => 2,
+        }
+    }
+
+    /// The face of the cube centered on `pos`, with corners ordered so
+    /// [`Square::as_vertex_data`] winds it outward.
+    pub fn square(self, pos: &GlPosition, side_len: f32) -> Square {
+        self.box_square(pos, glam::Vec3::splat(side_len / 2.0))
+    }
+
+    /// The face of the box centered on `center` reaching `half_extents` away
+    /// from it on each axis.
+    pub fn box_square(self, center: &GlPosition, half_extents: glam::Vec3) -> Square {
+        let (bottom_left, top_right) = match self {
+            Face::Front => ((1.0, 1.0, 1.0), (-1.0, -1.0, 1.0)),
+            Face::Back => ((1.0, -1.0, -1.0), (-1.0, 1.0, -1.0)),
+            Face::Right => ((1.0, -1.0, 1.0), (1.0, 1.0, -1.0)),
+            Face::Left => ((-1.0, -1.0, -1.0), (-1.0, 1.0, 1.0)),
+            Face::Top => ((-1.0, 1.0, 1.0), (1.0, 1.0, -1.0)),
+            Face::Bottom => ((-1.0, -1.0, -1.0), (1.0, -1.0, 1.0)),
+        };
+        Square {
+            bottom_left: center + GlPosition::from(bottom_left) * half_extents,
+            top_right: center + GlPosition::from(top_right) * half_extents,
+            uv_rect: UvRect::FULL,
+        }
+    }
+}
+
+/// Dense block ids of a box of voxels, indexed by `(x, y, z)` from the origin
+/// corner. Everything outside the box is [`AIR`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    size: UVec3,
+    blocks: Vec<BlockId>,
+}
+
+impl VoxelGrid {
+    /// A grid of `size` voxels of air.
+    pub fn new(size: UVec3) -> Self {
+        Self {
+            size,
+            blocks: vec![AIR; size.element_product() as usize],
+        }
+    }
+
+    /// Fills every column `(x, z)` of a `side` x `side` grid with `block` from
+    /// `y = 0` up to and including `height(x, z)`.
+    pub fn from_heightmap(
+        side: usize,
+        block: BlockId,
+        height: impl Fn(usize, usize) -> usize,
+    ) -> Self {
+        let heights: Vec<usize> = (0..side)
+            .flat_map(|x| (0..side).map(move |z| (x, z)))
+            .map(|(x, z)| height(x, z))
+            .collect();
+        let max_height = heights.iter().copied().max().unwrap_or(0);
+
+        let mut grid = Self::new(UVec3::new(side as u32, max_height as u32 + 1, side as u32));
+        for (i, column_height) in heights.into_iter().enumerate() {
+            let (x, z) = (i / side, i % side);
+            for y in 0..=column_height {
+                grid.set(UVec3::new(x as u32, y as u32, z as u32), block);
+            }
+        }
+        grid
+    }
+
+    pub fn size(&self) -> UVec3 {
+        self.size
+    }
+
+    pub fn contains(&self, pos: IVec3) -> bool {
+        self.index(pos).is_some()
+    }
+
+    /// The block at `pos`, [`AIR`] outside the grid.
+    pub fn get(&self, pos: IVec3) -> BlockId {
+        self.index(pos).map_or(AIR, |i| self.blocks[i])
+    }
+
+    /// Whether the voxel at `pos` is not air.
+    pub fn is_solid(&self, pos: IVec3) -> bool {
+        self.get(pos) != AIR
+    }
+
+    /// # Panics
+    /// If `pos` is outside the grid.
+    pub fn set(&mut self, pos: UVec3, block: BlockId) {
+        let i = self
+            .index(pos.as_ivec3())
+            .unwrap_or_else(|| panic!("{pos} is outside a grid of size {}", self.size));
+        self.blocks[i] = block;
+    }
+
+    /// Positions of the solid voxels, in index order.
+    pub fn solid_voxels(&self) -> impl Iterator<Item = UVec3> + '_ {
+        let size = self.size;
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| **block != AIR)
+            .map(move |(i, _)| {
+                let i = i as u32;
+                UVec3::new(i % size.x, (i / size.x) % size.y, i / (size.x * size.y))
+            })
+    }
+
+    fn index(&self, pos: IVec3) -> Option<usize> {
+        let in_bounds = pos.cmpge(IVec3::ZERO).all() && pos.as_uvec3().cmplt(self.size).all();
+        in_bounds.then(|| {
+            let pos = pos.as_uvec3();
+            (pos.x + self.size.x * (pos.y + self.size.y * pos.z)) as usize
+        })
+    }
+}
@@ -0,0 +1,164 @@
+use glam::{IVec3, Vec3};
+
+use crate::voxel::{AIR, BlockId};
+
+/// The first solid voxel along a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub voxel: IVec3,
+    pub block: BlockId,
+    /// Outward normal of the face the ray entered through, zero when the ray
+    /// starts inside the voxel. `voxel + normal` is where a block placed
+    /// against the face goes.
+    pub normal: IVec3,
+    /// From the ray origin to the face, in voxels.
+    pub distance: f32,
+}
+
+/// Walks the voxels crossed by the ray from `origin` along `direction`, in
+/// voxel units with voxel `(x, y, z)` centered on `(x, y, z)`, and returns the
+/// first one `block_at` doesn't say is [`AIR`], at most `max_distance` away.
+///
+/// Every voxel the ray crosses is visited exactly once, in order (Amanatides
+/// and Woo's DDA).
+pub fn raycast(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    block_at: impl Fn(IVec3) -> BlockId,
+) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    // Voxel v spans [v, v + 1) once shifted by half a voxel
+    let start = origin + 0.5;
+    let mut voxel = start.floor().as_ivec3();
+    let step = IVec3::from_array(direction.to_array().map(|d| {
+        if d > 0.0 {
+            1
+        } else if d < 0.0 {
+            -1
+        } else {
+            0
+        }
+    }));
+    // Distance along the ray to cross a whole voxel, and to the next boundary
+    let t_delta = direction.abs().recip();
+    let mut t_max = Vec3::from_array(std::array::from_fn(|axis| match step[axis] {
+        1 => (voxel[axis] as f32 + 1.0 - start[axis]) * t_delta[axis],
+        -1 => (start[axis] - voxel[axis] as f32) * t_delta[axis],
+        _ => f32::INFINITY,
+    }));
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+    loop {
+        let block = block_at(voxel);
+        if block != AIR {
+            return Some(RayHit {
+                voxel,
+                block,
+                normal,
+                distance,
+            });
+        }
+
+        let axis = t_max.min_position();
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+        voxel[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{DIRT, STONE};
+
+    /// Solid up to and including y = 0, and a stone pillar at x = z = 3.
+    fn floor(pos: IVec3) -> BlockId {
+        if pos.y <= 0 {
+            DIRT
+        } else if pos.x == 3 && pos.z == 3 && pos.y <= 4 {
+            STONE
+        } else {
+            AIR
+        }
+    }
+
+    #[test]
+    fn looking_down_hits_the_top_face() {
+        let hit = raycast(Vec3::new(1.2, 5.0, -7.4), Vec3::NEG_Y, 10.0, floor).unwrap();
+        assert_eq!(hit.voxel, IVec3::new(1, 0, -7));
+        assert_eq!(hit.block, DIRT);
+        assert_eq!(hit.normal, IVec3::Y);
+        assert!((hit.distance - 4.5).abs() < 1e-5, "{}", hit.distance);
+    }
+
+    #[test]
+    fn sideways_rays_hit_the_facing_side() {
+        let hit = raycast(Vec3::new(-2.0, 2.0, 3.0), Vec3::X, 10.0, floor).unwrap();
+        assert_eq!((hit.voxel, hit.block), (IVec3::new(3, 2, 3), STONE));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+
+        let hit = raycast(Vec3::new(3.0, 3.0, 9.0), Vec3::NEG_Z, 10.0, floor).unwrap();
+        assert_eq!(hit.normal, IVec3::Z);
+    }
+
+    #[test]
+    fn stops_at_the_max_distance() {
+        assert_eq!(
+            raycast(Vec3::new(0.0, 5.0, 0.0), Vec3::NEG_Y, 4.4, floor),
+            None
+        );
+        assert!(raycast(Vec3::new(0.0, 5.0, 0.0), Vec3::NEG_Y, 4.6, floor).is_some());
+        assert_eq!(
+            raycast(Vec3::new(0.0, 5.0, 0.0), Vec3::Y, 100.0, floor),
+            None
+        );
+        assert_eq!(
+            raycast(Vec3::new(0.0, 5.0, 0.0), Vec3::ZERO, 100.0, floor),
+            None
+        );
+    }
+
+    #[test]
+    fn starting_inside_a_block_hits_it() {
+        let hit = raycast(Vec3::new(0.0, -0.2, 0.0), Vec3::Y, 10.0, floor).unwrap();
+        assert_eq!(
+            (hit.voxel, hit.normal, hit.distance),
+            (IVec3::ZERO, IVec3::ZERO, 0.0)
+        );
+    }
+
+    #[test]
+    fn matches_small_steps_along_the_ray() {
+        let origin = Vec3::new(0.3, 6.1, -0.7);
+        for i in 0..64 {
+            let angle = i as f32 * 0.37;
+            let direction = Vec3::new(angle.cos(), -0.3 - (i % 5) as f32 * 0.2, angle.sin());
+            let hit = raycast(origin, direction, 40.0, floor).unwrap();
+
+            // Marching in tiny steps finds the same first voxel, and where the
+            // ray enters it
+            let direction = direction.normalize();
+            let marched = (0..40_000)
+                .map(|step| origin + direction * step as f32 * 0.001)
+                .map(|p| (p + 0.5).floor().as_ivec3())
+                .find(|voxel| floor(*voxel) != AIR)
+                .unwrap();
+            assert_eq!(hit.voxel, marched, "{direction}");
+            let entry = origin + direction * hit.distance;
+            let face = (entry - hit.voxel.as_vec3()).abs().max_element();
+            assert!((face - 0.5).abs() < 1e-3, "{direction} enters at {entry}");
+        }
+    }
+}
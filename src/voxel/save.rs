@@ -0,0 +1,617 @@
+//! World saves: the [`GeneratorConfig`] the world is generated from, then the
+//! edited chunks as run-length encoded differences from their generated
+//! voxels.
+//!
+//! Everything is little endian:
+//!
+//! ```text
+//! magic    b"VXWS"
+//! version  u32
+//! config   see write_config
+//! chunks   u32 count, then per chunk:
+//!          x i32, z i32, runs u32, then per run: length u32, block u16
+//! ```
+//!
+//! The runs cover the voxels of a chunk in [`VoxelGrid`] index order, a run of
+//! block `u16::MAX` keeping the generated voxels.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use glam::{IVec2, UVec3};
+
+use crate::{
+    terrain_builder::{CaveConfig, DensityConfig, DomainWarp, NoiseMode, TerrainConfig},
+    voxel::{
+        BlockId, VoxelGrid,
+        blocks::BlockLayers,
+        world::{ChunkCoord, ChunkWorld, GeneratorConfig, TerrainShape},
+    },
+};
+
+const MAGIC: &[u8; 4] = b"VXWS";
+
+/// Version written by [`WorldSave::write`], the only one read back.
+pub const SAVE_VERSION: u32 = 1;
+
+/// The block of a run keeping the generated voxels.
+const UNCHANGED: u16 = u16::MAX;
+
+/// Worlds taller than this are taken for corrupted, rather than allocating
+/// their chunks.
+const MAX_HEIGHT: u32 = 4096;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The file doesn't start like a world save.
+    NotASave,
+    /// Written by another version of the format.
+    UnsupportedVersion(u32),
+    /// Ends early, or holds values no save has.
+    Corrupted(String),
+    /// Generated from another config than the world it should replace.
+    OtherWorld,
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => SaveError::Corrupted("unexpected end of file".into()),
+            _ => SaveError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "Could not access the world save: {err}"),
+            SaveError::NotASave => write!(f, "Not a world save"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "World save version {version} is not supported, expected {SAVE_VERSION}"
+            ),
+            SaveError::Corrupted(reason) => write!(f, "Corrupted world save: {reason}"),
+            SaveError::OtherWorld => write!(
+                f,
+                "World save was generated from another config than the current world"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+fn corrupted(reason: impl Into<String>) -> SaveError {
+    SaveError::Corrupted(reason.into())
+}
+
+/// A world as saved: how to generate it, and the chunks edited since.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldSave {
+    pub config: GeneratorConfig,
+    /// Ordered by coordinates
+    pub chunks: Vec<(ChunkCoord, VoxelGrid)>,
+}
+
+impl WorldSave {
+    /// The edited chunks of `world`, which should be generated from `config`.
+    pub fn of(config: GeneratorConfig, world: &ChunkWorld) -> Self {
+        let chunks = world
+            .edited_chunks()
+            .into_iter()
+            .map(|(chunk, grid)| (chunk, grid.clone()))
+            .collect();
+        Self { config, chunks }
+    }
+
+    /// A world generated from the config, with the edited chunks restored.
+    pub fn into_world(self, radius: i32) -> ChunkWorld {
+        let mut world = ChunkWorld::new(self.config.generator(), radius);
+        for (chunk, grid) in self.chunks {
+            world.restore(chunk, grid);
+        }
+        world
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// # Panics
+    /// If a chunk is not the size of the generated ones.
+    pub fn write(&self, mut w: impl Write) -> Result<(), SaveError> {
+        w.write_all(MAGIC)?;
+        write_u32(&mut w, SAVE_VERSION)?;
+        write_config(&mut w, &self.config)?;
+
+        let generator = self.config.generator();
+        write_u32(&mut w, self.chunks.len() as u32)?;
+        for (chunk, grid) in &self.chunks {
+            let generated = generator.generate(*chunk);
+            assert_eq!(grid.size(), generated.size(), "size of chunk {chunk}");
+            let runs = delta_runs(&generated, grid);
+            write_i32(&mut w, chunk.x)?;
+            write_i32(&mut w, chunk.y)?;
+            write_u32(&mut w, runs.len() as u32)?;
+            for (length, block) in runs {
+                write_u32(&mut w, length)?;
+                write_u16(&mut w, block)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a save written by [`WorldSave::write`], failing on anything else
+    /// rather than loading a broken world.
+    pub fn read(mut r: impl Read) -> Result<Self, SaveError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => SaveError::NotASave,
+            _ => SaveError::Io(err),
+        })?;
+        if &magic != MAGIC {
+            return Err(SaveError::NotASave);
+        }
+        let version = read_u32(&mut r)?;
+        if version != SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        let config = read_config(&mut r)?;
+
+        let generator = config.generator();
+        let count = read_u32(&mut r)?;
+        let mut chunks: Vec<(ChunkCoord, VoxelGrid)> = vec![];
+        for _ in 0..count {
+            let chunk = IVec2::new(read_i32(&mut r)?, read_i32(&mut r)?);
+            if chunks.iter().any(|(saved, _)| *saved == chunk) {
+                return Err(corrupted(format!("chunk {chunk} is saved twice")));
+            }
+            let mut grid = generator.generate(chunk);
+            apply_runs(&mut r, &mut grid).map_err(|err| match err {
+                SaveError::Corrupted(reason) => corrupted(format!("chunk {chunk}: {reason}")),
+                err => err,
+            })?;
+            chunks.push((chunk, grid));
+        }
+
+        if r.read(&mut [0])? != 0 {
+            return Err(corrupted("trailing data after the last chunk"));
+        }
+        chunks.sort_by_key(|(chunk, _)| (chunk.x, chunk.y));
+        Ok(Self { config, chunks })
+    }
+}
+
+/// The voxels of `edited` as runs of the same block, [`UNCHANGED`] where they
+/// are still those of `generated`.
+fn delta_runs(generated: &VoxelGrid, edited: &VoxelGrid) -> Vec<(u32, u16)> {
+    let mut runs: Vec<(u32, u16)> = vec![];
+    for pos in grid_positions(edited.size()) {
+        let pos = pos.as_ivec3();
+        let block = edited.get(pos);
+        let block = if block == generated.get(pos) {
+            UNCHANGED
+        } else {
+            block as u16
+        };
+        match runs.last_mut() {
+            Some((length, last)) if *last == block => *length += 1,
+            _ => runs.push((1, block)),
+        }
+    }
+    runs
+}
+
+/// Reads the runs of a chunk over its generated voxels `grid`.
+fn apply_runs(r: &mut impl Read, grid: &mut VoxelGrid) -> Result<(), SaveError> {
+    let mut positions = grid_positions(grid.size());
+    let total = grid.size().element_product();
+    let mut covered = 0u32;
+    for _ in 0..read_u32(r)? {
+        let length = read_u32(r)?;
+        let block = read_u16(r)?;
+        if length == 0 || length > total - covered {
+            return Err(corrupted(format!(
+                "a run of {length} voxels with {covered} of {total} covered"
+            )));
+        }
+        covered += length;
+        if block == UNCHANGED {
+            positions.nth(length as usize - 1);
+            continue;
+        }
+        let block = BlockId::try_from(block)
+            .map_err(|_| corrupted(format!("{block} is not a block id")))?;
+        for pos in positions.by_ref().take(length as usize) {
+            grid.set(pos, block);
+        }
+    }
+    if covered != total {
+        return Err(corrupted(format!("runs cover {covered} of {total} voxels")));
+    }
+    Ok(())
+}
+
+/// The voxels of a grid of `size`, in [`VoxelGrid`] index order.
+fn grid_positions(size: UVec3) -> impl Iterator<Item = UVec3> {
+    (0..size.z).flat_map(move |z| {
+        (0..size.y).flat_map(move |y| (0..size.x).map(move |x| UVec3::new(x, y, z)))
+    })
+}
+
+fn write_config(w: &mut impl Write, config: &GeneratorConfig) -> io::Result<()> {
+    match &config.shape {
+        TerrainShape::Heightmap(terrain) => {
+            write_u8(w, 0)?;
+            write_terrain(w, terrain)?;
+        }
+        TerrainShape::Density(density) => {
+            write_u8(w, 1)?;
+            write_terrain(w, &density.surface)?;
+            write_f64(w, density.overhang)?;
+            write_f64(w, density.overhang_frequency)?;
+            write_u8(w, density.caves.is_some() as u8)?;
+            if let Some(caves) = &density.caves {
+                write_u32(w, caves.seed)?;
+                write_f64(w, caves.frequency)?;
+                write_f64(w, caves.radius)?;
+                write_u32(w, caves.min_depth as u32)?;
+            }
+        }
+    }
+    write_u32(w, config.height)?;
+    write_u8(w, config.layers.is_some() as u8)?;
+    if let Some(layers) = &config.layers {
+        for value in [
+            layers.sea_level,
+            layers.snow_line,
+            layers.max_soil_slope,
+            layers.soil_depth,
+        ] {
+            write_i32(w, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_config(r: &mut impl Read) -> Result<GeneratorConfig, SaveError> {
+    let shape = match read_u8(r)? {
+        0 => TerrainShape::Heightmap(read_terrain(r)?),
+        1 => {
+            let surface = read_terrain(r)?;
+            let overhang = read_f64(r)?;
+            let overhang_frequency = read_f64(r)?;
+            let caves = if read_flag(r)? {
+                Some(CaveConfig {
+                    seed: read_u32(r)?,
+                    frequency: read_f64(r)?,
+                    radius: read_f64(r)?,
+                    min_depth: read_u32(r)? as usize,
+                })
+            } else {
+                None
+            };
+            TerrainShape::Density(DensityConfig {
+                surface,
+                overhang,
+                overhang_frequency,
+                caves,
+            })
+        }
+        tag => return Err(corrupted(format!("unknown terrain shape {tag}"))),
+    };
+
+    let height = read_u32(r)?;
+    if !(1..=MAX_HEIGHT).contains(&height) {
+        return Err(corrupted(format!("world height {height}")));
+    }
+    let layers = if read_flag(r)? {
+        Some(BlockLayers {
+            sea_level: read_i32(r)?,
+            snow_line: read_i32(r)?,
+            max_soil_slope: read_i32(r)?,
+            soil_depth: read_i32(r)?,
+        })
+    } else {
+        None
+    };
+    Ok(GeneratorConfig {
+        shape,
+        height,
+        layers,
+    })
+}
+
+fn write_terrain(w: &mut impl Write, terrain: &TerrainConfig) -> io::Result<()> {
+    write_u32(w, terrain.seed)?;
+    write_u32(w, terrain.octaves as u32)?;
+    for value in [
+        terrain.lacunarity,
+        terrain.persistence,
+        terrain.frequency,
+        terrain.amplitude,
+        terrain.base_height,
+    ] {
+        write_f64(w, value)?;
+    }
+    write_u8(
+        w,
+        match terrain.mode {
+            NoiseMode::Fbm => 0,
+            NoiseMode::Ridged => 1,
+            NoiseMode::Billow => 2,
+        },
+    )?;
+    write_u8(w, terrain.warp.is_some() as u8)?;
+    if let Some(warp) = &terrain.warp {
+        write_f64(w, warp.frequency)?;
+        write_f64(w, warp.power)?;
+    }
+    Ok(())
+}
+
+fn read_terrain(r: &mut impl Read) -> Result<TerrainConfig, SaveError> {
+    let seed = read_u32(r)?;
+    let octaves = read_u32(r)? as usize;
+    // The noise library supports up to 32
+    if !(1..=32).contains(&octaves) {
+        return Err(corrupted(format!("{octaves} octaves")));
+    }
+    let lacunarity = read_f64(r)?;
+    let persistence = read_f64(r)?;
+    let frequency = read_f64(r)?;
+    let amplitude = read_f64(r)?;
+    let base_height = read_f64(r)?;
+    let mode = match read_u8(r)? {
+        0 => NoiseMode::Fbm,
+        1 => NoiseMode::Ridged,
+        2 => NoiseMode::Billow,
+        mode => return Err(corrupted(format!("unknown noise mode {mode}"))),
+    };
+    let warp = if read_flag(r)? {
+        Some(DomainWarp {
+            frequency: read_f64(r)?,
+            power: read_f64(r)?,
+        })
+    } else {
+        None
+    };
+    Ok(TerrainConfig {
+        seed,
+        octaves,
+        lacunarity,
+        persistence,
+        frequency,
+        amplitude,
+        base_height,
+        mode,
+        warp,
+    })
+}
+
+fn read_flag(r: &mut impl Read) -> Result<bool, SaveError> {
+    match read_u8(r)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        flag => Err(corrupted(format!("{flag} is not a flag"))),
+    }
+}
+
+fn write_u8(w: &mut impl Write, value: u8) -> io::Result<()> {
+    w.write_all(&[value])
+}
+
+fn write_u16(w: &mut impl Write, value: u16) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u32(w: &mut impl Write, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_i32(w: &mut impl Write, value: i32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_f64(w: &mut impl Write, value: f64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn read_bytes<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    read_bytes::<1>(r).map(|[value]| value)
+}
+
+fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    read_bytes(r).map(u16::from_le_bytes)
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    read_bytes(r).map(u32::from_le_bytes)
+}
+
+fn read_i32(r: &mut impl Read) -> io::Result<i32> {
+    read_bytes(r).map(i32::from_le_bytes)
+}
+
+fn read_f64(r: &mut impl Read) -> io::Result<f64> {
+    read_bytes(r).map(f64::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::*;
+    use crate::voxel::{AIR, DIRT, SNOW, STONE, world::CHUNK_SIDE};
+
+    fn config() -> GeneratorConfig {
+        GeneratorConfig {
+            shape: TerrainShape::Heightmap(TerrainConfig::new(7, 8)),
+            height: 16,
+            layers: Some(BlockLayers {
+                sea_level: 2,
+                snow_line: 7,
+                max_soil_slope: 2,
+                soil_depth: 2,
+            }),
+        }
+    }
+
+    /// A world around chunk `(0, 0)` with a few edits, some of them in a
+    /// chunk unloaded since.
+    fn edited_world() -> ChunkWorld {
+        let mut world = ChunkWorld::new(config().generator(), 1);
+        for chunk in world.stream_around(IVec2::ZERO).requested {
+            world.insert(chunk, world.generator().generate(chunk));
+        }
+        assert!(world.set(IVec3::new(3, 15, 4), STONE));
+        assert!(world.set(IVec3::new(3, 0, 4), AIR));
+        assert!(world.set(IVec3::new(-CHUNK_SIDE, 12, 5), SNOW));
+        for x in 0..10 {
+            assert!(world.set(IVec3::new(x, 14, -1), DIRT));
+        }
+        world.stream_around(IVec2::new(1, 0));
+        assert!(!world.is_loaded(IVec2::new(-1, 0)));
+        world
+    }
+
+    fn written(save: &WorldSave) -> Vec<u8> {
+        let mut bytes = vec![];
+        save.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips_the_config_and_the_edited_chunks() {
+        let world = edited_world();
+        let save = WorldSave::of(config(), &world);
+        let chunks: Vec<_> = save.chunks.iter().map(|(chunk, _)| *chunk).collect();
+        assert_eq!(
+            chunks,
+            [IVec2::new(-1, 0), IVec2::new(0, -1), IVec2::new(0, 0)]
+        );
+
+        let read = WorldSave::read(written(&save).as_slice()).unwrap();
+        assert_eq!(read, save);
+
+        let mut loaded = read.into_world(1);
+        for chunk in loaded.stream_around(IVec2::ZERO).requested {
+            loaded.insert(chunk, loaded.generator().generate(chunk));
+        }
+        for pos in [
+            IVec3::new(3, 15, 4),
+            IVec3::new(3, 0, 4),
+            IVec3::new(-CHUNK_SIDE, 12, 5),
+            IVec3::new(9, 14, -1),
+            IVec3::new(20, 3, 20),
+        ] {
+            assert_eq!(loaded.get(pos), world.get(pos), "{pos}");
+        }
+        assert_eq!(loaded.get(IVec3::new(3, 15, 4)), STONE);
+    }
+
+    #[test]
+    fn only_the_changed_voxels_are_stored() {
+        let save = WorldSave::of(config(), &edited_world());
+        let empty = WorldSave {
+            config: config(),
+            chunks: vec![],
+        };
+        // A few runs per chunk, rather than a byte per voxel
+        let per_chunk = (written(&save).len() - written(&empty).len()) / save.chunks.len();
+        assert!(per_chunk < 64, "{per_chunk} bytes per chunk");
+    }
+
+    #[test]
+    fn round_trips_density_configs() {
+        let config = GeneratorConfig {
+            shape: TerrainShape::Density(DensityConfig {
+                overhang: 12.0,
+                caves: Some(CaveConfig::new(3)),
+                ..DensityConfig::new(TerrainConfig {
+                    octaves: 4,
+                    mode: NoiseMode::Ridged,
+                    warp: Some(DomainWarp {
+                        frequency: 0.05,
+                        power: 2.0,
+                    }),
+                    ..TerrainConfig::new(11, 8)
+                })
+            }),
+            height: 24,
+            layers: None,
+        };
+        let save = WorldSave {
+            config,
+            chunks: vec![],
+        };
+        assert_eq!(WorldSave::read(written(&save).as_slice()).unwrap(), save);
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let bytes = written(&WorldSave::of(config(), &edited_world()));
+
+        let not_a_save = WorldSave::read(&b"P6\n16 16\n255\n"[..]);
+        assert!(matches!(not_a_save, Err(SaveError::NotASave)));
+        assert!(matches!(WorldSave::read(&[][..]), Err(SaveError::NotASave)));
+
+        let mut newer = bytes.clone();
+        newer[4..8].copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            WorldSave::read(newer.as_slice()),
+            Err(SaveError::UnsupportedVersion(v)) if v == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn corrupted_saves_are_errors() {
+        let bytes = written(&WorldSave::of(config(), &edited_world()));
+
+        // Cut anywhere after the magic
+        for len in (4..bytes.len()).step_by(7) {
+            let read = WorldSave::read(&bytes[..len]);
+            assert!(matches!(read, Err(SaveError::Corrupted(_))), "{len}");
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            WorldSave::read(trailing.as_slice()),
+            Err(SaveError::Corrupted(_))
+        ));
+
+        // The last run, made longer than the chunk
+        let mut overflowing = bytes.clone();
+        let length = overflowing.len() - 6;
+        overflowing[length..length + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = WorldSave::read(overflowing.as_slice()).unwrap_err();
+        assert!(err.to_string().contains("chunk [0, 0]"), "{err}");
+    }
+}
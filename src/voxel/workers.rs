@@ -0,0 +1,482 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    panic::AssertUnwindSafe,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    entities::tex_square::{Square, squares_vertex_data},
+    voxel::{
+        AIR, VoxelGrid,
+        blocks::BlockRegistry,
+        greedy::greedy_faces_bordered,
+        world::{ChunkCoord, ChunkWorld, Generator},
+    },
+};
+
+/// A chunk to mesh on a worker thread.
+pub struct MeshJob {
+    pub chunk: ChunkCoord,
+    /// The voxels to mesh, `None` to generate them first.
+    pub grid: Option<VoxelGrid>,
+    /// Loaded chunks around, see [`ChunkWorld::neighbours`]. Voxels of the
+    /// others are generated.
+    pub neighbours: HashMap<ChunkCoord, VoxelGrid>,
+}
+
+/// The result of a [`MeshJob`], ready to upload.
+pub struct MeshedChunk {
+    pub chunk: ChunkCoord,
+    /// The voxels the worker generated, `None` if the job came with them.
+    pub generated: Option<VoxelGrid>,
+    /// From [`squares_vertex_data`], tiled once per voxel.
+    pub vertex_data: Vec<f32>,
+}
+
+/// Requests are numbered, a chunk only accepts the result of its latest one.
+type Generation = u64;
+
+/// What a worker sends back: the mesh, or the chunk whose job panicked.
+type JobResult = (Generation, Result<MeshedChunk, ChunkCoord>);
+
+/// Why [`MeshWorkers::recv_timeout`] has no mesh to return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvError {
+    /// Nothing finished in time.
+    Timeout,
+    /// The job of the chunk panicked, it is no longer pending.
+    Failed(ChunkCoord),
+    /// Every worker is gone, nothing pending will come back.
+    Disconnected,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Timeout => write!(f, "No chunk meshed in time"),
+            RecvError::Failed(chunk) => write!(f, "Meshing chunk {chunk} panicked"),
+            RecvError::Disconnected => write!(f, "Every mesh worker stopped"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+/// Threads generating and meshing chunks off the render thread. Jobs run
+/// roughly in submission order, and results come back over a channel for the
+/// render thread to upload at its own pace.
+///
+/// Submitting a job for a chunk supersedes its previous one, and a chunk can
+/// be cancelled: outdated jobs are skipped if not started yet, and their
+/// results dropped otherwise.
+///
+/// A job panicking while meshing is reported as [`RecvError::Failed`] and
+/// dropped, its chunk left unmeshed; the worker goes on with the next one.
+pub struct MeshWorkers {
+    jobs: Option<Sender<(Generation, MeshJob)>>,
+    results: Receiver<JobResult>,
+    /// Latest generation of every chunk with a job in flight
+    latest: Arc<Mutex<HashMap<ChunkCoord, Generation>>>,
+    next_generation: Generation,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl MeshWorkers {
+    /// `threads` workers generating with `generator`, for voxels of side
+    /// `side_len` textured from the atlas of `blocks`.
+    pub fn new(
+        threads: usize,
+        generator: Generator,
+        blocks: Arc<BlockRegistry>,
+        side_len: f32,
+    ) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<(Generation, MeshJob)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let latest: Arc<Mutex<HashMap<ChunkCoord, Generation>>> = Default::default();
+
+        let threads = (0..threads.max(1))
+            .map(|_| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                let latest = latest.clone();
+                let generator = generator.clone();
+                let blocks = blocks.clone();
+                std::thread::spawn(move || {
+                    loop {
+                        // The lock is only held while waiting for a job
+                        let Ok((generation, job)) = lock(&job_receiver).recv() else {
+                            // The pool was dropped
+                            return;
+                        };
+                        let chunk = job.chunk;
+                        if lock(&latest).get(&chunk) != Some(&generation) {
+                            continue;
+                        }
+                        let meshed = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            run(job, &generator, &blocks, side_len)
+                        }))
+                        .map_err(|_| chunk);
+                        if result_sender.send((generation, meshed)).is_err() {
+                            return;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            results,
+            latest,
+            next_generation: 0,
+            threads,
+        }
+    }
+
+    /// As many workers as the machine has cores to spare, leaving one to the
+    /// render thread.
+    pub fn with_available_parallelism(
+        generator: Generator,
+        blocks: Arc<BlockRegistry>,
+        side_len: f32,
+    ) -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        Self::new(cores.saturating_sub(1), generator, blocks, side_len)
+    }
+
+    pub fn submit(&mut self, job: MeshJob) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        let chunk = job.chunk;
+        lock(&self.latest).insert(chunk, generation);
+        let sent = self
+            .jobs
+            .as_ref()
+            .is_some_and(|jobs| jobs.send((generation, job)).is_ok());
+        if !sent {
+            log::error!("No mesh worker left for chunk {chunk}");
+            lock(&self.latest).remove(&chunk);
+        }
+    }
+
+    /// Drops the pending job of `chunk`, if any.
+    pub fn cancel(&mut self, chunk: ChunkCoord) {
+        lock(&self.latest).remove(&chunk);
+    }
+
+    /// Chunks with a job in flight.
+    pub fn pending(&self) -> usize {
+        lock(&self.latest).len()
+    }
+
+    /// The next finished job, without blocking.
+    pub fn try_recv(&mut self) -> Result<MeshedChunk, RecvError> {
+        self.recv_timeout(Duration::ZERO)
+    }
+
+    /// The next finished job, waiting at most `timeout` for it. Failed jobs
+    /// and lost workers are no longer [`MeshWorkers::pending`].
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<MeshedChunk, RecvError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (generation, result) = match self.results.recv_timeout(remaining) {
+                Ok(result) => result,
+                Err(RecvTimeoutError::Timeout) => return Err(RecvError::Timeout),
+                Err(RecvTimeoutError::Disconnected) => {
+                    lock(&self.latest).clear();
+                    return Err(RecvError::Disconnected);
+                }
+            };
+
+            let chunk = match &result {
+                Ok(meshed) => meshed.chunk,
+                Err(chunk) => *chunk,
+            };
+            let mut latest = lock(&self.latest);
+            if latest.get(&chunk) == Some(&generation) {
+                latest.remove(&chunk);
+                return result.map_err(RecvError::Failed);
+            }
+            // Superseded or cancelled while running
+        }
+    }
+}
+
+impl Drop for MeshWorkers {
+    fn drop(&mut self) {
+        lock(&self.latest).clear();
+        // Closing the channel stops the workers once the queue is drained
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Locks `mutex` even if a thread panicked holding it: the job queue and the
+/// generations only change in single calls, they can't be left half updated.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn run(job: MeshJob, generator: &Generator, blocks: &BlockRegistry, side_len: f32) -> MeshedChunk {
+    let generated = job.grid.is_none().then(|| generator.generate(job.chunk));
+    let grid = job.grid.as_ref().or(generated.as_ref()).unwrap();
+
+    let squares = mesh_chunk(
+        job.chunk,
+        grid,
+        &job.neighbours,
+        generator,
+        blocks,
+        side_len,
+    );
+    MeshedChunk {
+        chunk: job.chunk,
+        vertex_data: squares_vertex_data(&squares, Some(side_len)),
+        generated,
+    }
+}
+
+/// The visible faces of `chunk`, hiding the ones against solid voxels of the
+/// neighbouring chunks and the ground under the world.
+pub fn mesh_chunk(
+    chunk: ChunkCoord,
+    grid: &VoxelGrid,
+    neighbours: &HashMap<ChunkCoord, VoxelGrid>,
+    generator: &Generator,
+    blocks: &BlockRegistry,
+    side_len: f32,
+) -> Vec<Square> {
+    let origin = ChunkWorld::chunk_origin(chunk);
+    let uv_rect = |block, face| blocks.uv_rect(block, face);
+    greedy_faces_bordered(
+        grid,
+        origin.as_vec3() * side_len,
+        side_len,
+        uv_rect,
+        |local| {
+            let pos = origin + local;
+            let neighbour = ChunkWorld::chunk_of(pos);
+            let block = match neighbours.get(&neighbour) {
+                Some(grid) => grid.get(pos - ChunkWorld::chunk_origin(neighbour)),
+                None => generator.get(pos),
+            };
+            local.y < 0 || block != AIR
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Condvar;
+
+    use glam::IVec2;
+
+    use super::*;
+    use crate::{terrain_builder::terrain_height, voxel::DIRT};
+
+    const WAIT: Duration = Duration::from_secs(10);
+
+    fn generate(chunk: ChunkCoord) -> MeshJob {
+        MeshJob {
+            chunk,
+            grid: None,
+            neighbours: HashMap::new(),
+        }
+    }
+
+    /// Every result, until the workers are idle.
+    fn drain(workers: &mut MeshWorkers) -> Vec<MeshedChunk> {
+        let mut results = vec![];
+        while workers.pending() > 0 {
+            match workers.recv_timeout(WAIT) {
+                Ok(meshed) => results.push(meshed),
+                Err(RecvError::Failed(_)) => {}
+                Err(err) => panic!("A worker is stuck: {err}"),
+            }
+        }
+        results
+    }
+
+    fn generator(seed: u32) -> Generator {
+        Generator::new(terrain_height(seed, 8), 16)
+    }
+
+    fn blocks() -> Arc<BlockRegistry> {
+        Arc::new(BlockRegistry::standard())
+    }
+
+    #[test]
+    fn output_only_depends_on_the_seed() {
+        let chunks: Vec<_> = (-2..2)
+            .flat_map(|x| (-2..2).map(move |z| IVec2::new(x, z)))
+            .collect();
+        let mesh_with = |threads, seed| {
+            let mut workers = MeshWorkers::new(threads, generator(seed), blocks(), 0.5);
+            for chunk in &chunks {
+                workers.submit(generate(*chunk));
+            }
+            let mut results: Vec<_> = drain(&mut workers)
+                .into_iter()
+                .map(|meshed| ((meshed.chunk.x, meshed.chunk.y), meshed.vertex_data))
+                .collect();
+            results.sort_by_key(|(chunk, _)| *chunk);
+            results
+        };
+
+        let single = mesh_with(1, 123);
+        assert_eq!(single.len(), chunks.len());
+        assert_eq!(mesh_with(4, 123), single);
+        assert_ne!(mesh_with(4, 321), single);
+
+        // The same as meshing on this thread
+        let generator = generator(123);
+        let chunk = IVec2::new(-2, -2);
+        let squares = mesh_chunk(
+            chunk,
+            &generator.generate(chunk),
+            &HashMap::new(),
+            &generator,
+            &blocks(),
+            0.5,
+        );
+        assert_eq!(single[0].1, squares_vertex_data(&squares, Some(0.5)));
+    }
+
+    #[test]
+    fn a_single_worker_keeps_the_submission_order() {
+        let mut workers = MeshWorkers::new(1, generator(123), blocks(), 1.0);
+        let chunks = [IVec2::new(3, 0), IVec2::ZERO, IVec2::new(-1, 2)];
+        for chunk in chunks {
+            workers.submit(generate(chunk));
+        }
+
+        let order: Vec<_> = drain(&mut workers).iter().map(|m| m.chunk).collect();
+        assert_eq!(order, chunks);
+    }
+
+    #[test]
+    fn only_the_latest_job_of_a_chunk_is_returned() {
+        let mut workers = MeshWorkers::new(4, generator(123), blocks(), 1.0);
+        let mut edited = generator(123).generate(IVec2::ZERO);
+        edited.set(glam::UVec3::new(3, 15, 3), DIRT);
+
+        workers.submit(generate(IVec2::ZERO));
+        workers.submit(MeshJob {
+            grid: Some(edited),
+            ..generate(IVec2::ZERO)
+        });
+
+        let results = drain(&mut workers);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].generated.is_none(), "the remesh came last");
+    }
+
+    /// A height function blocking until opened, telling when it is entered.
+    #[derive(Default)]
+    struct Gate {
+        /// (open, entered)
+        state: Mutex<(bool, bool)>,
+        changed: Condvar,
+    }
+
+    impl Gate {
+        fn pass(&self) {
+            let mut state = self.state.lock().unwrap();
+            state.1 = true;
+            self.changed.notify_all();
+            let _open = self.changed.wait_while(state, |(open, _)| !*open).unwrap();
+        }
+
+        fn set_open(&self, open: bool) {
+            let mut state = self.state.lock().unwrap();
+            *state = (open, false);
+            self.changed.notify_all();
+        }
+
+        fn wait_entered(&self) {
+            let state = self.state.lock().unwrap();
+            let _entered = self
+                .changed
+                .wait_while(state, |(_, entered)| !*entered)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn cancelled_chunks_are_skipped() {
+        let gate = Arc::new(Gate::default());
+        let generator = {
+            let gate = gate.clone();
+            Generator::new(
+                move |_, _| {
+                    gate.pass();
+                    2
+                },
+                8,
+            )
+        };
+        let mut workers = MeshWorkers::new(1, generator, blocks(), 1.0);
+
+        let [running, cancelled, kept] = [IVec2::ZERO, IVec2::X, IVec2::Y];
+        for chunk in [running, cancelled, kept] {
+            workers.submit(generate(chunk));
+        }
+        gate.wait_entered();
+        workers.cancel(cancelled);
+        gate.set_open(true);
+
+        let order: Vec<_> = drain(&mut workers).iter().map(|m| m.chunk).collect();
+        assert_eq!(order, [running, kept]);
+
+        // Cancelled while running, the result is dropped
+        gate.set_open(false);
+        workers.submit(generate(running));
+        gate.wait_entered();
+        workers.cancel(running);
+        gate.set_open(true);
+        assert_eq!(
+            workers
+                .recv_timeout(Duration::from_millis(200))
+                .map(|m| m.chunk),
+            Err(RecvError::Timeout)
+        );
+    }
+
+    #[test]
+    fn panicking_jobs_are_reported_and_the_workers_go_on() {
+        // Fails in the chunks from x = 3 on, 32 voxels wide
+        let generator = Generator::new(
+            |x, _| {
+                assert!(x < 100, "broken generator");
+                2
+            },
+            8,
+        );
+        let mut workers = MeshWorkers::new(1, generator, blocks(), 1.0);
+        let [kept, broken] = [IVec2::ZERO, IVec2::new(4, 0)];
+        workers.submit(generate(broken));
+        workers.submit(generate(kept));
+
+        assert_eq!(
+            workers.recv_timeout(WAIT).err(),
+            Some(RecvError::Failed(broken))
+        );
+        assert_eq!(workers.recv_timeout(WAIT).map(|m| m.chunk), Ok(kept));
+        assert_eq!(workers.pending(), 0);
+
+        workers.submit(generate(broken));
+        workers.submit(generate(kept));
+        let order: Vec<_> = drain(&mut workers).iter().map(|m| m.chunk).collect();
+        assert_eq!(order, [kept]);
+    }
+}
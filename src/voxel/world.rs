@@ -0,0 +1,738 @@
+use std::{collections::HashMap, sync::Arc};
+
+use glam::{IVec2, IVec3, UVec3};
+
+use crate::{
+    terrain_builder::{DensityConfig, TerrainConfig},
+    voxel::{AIR, BlockId, DIRT, VoxelGrid, blocks::BlockLayers},
+};
+
+/// Voxels per chunk along x and z. Chunks span the whole world height.
+pub const CHUNK_SIDE: i32 = 32;
+
+/// Position of a chunk on the x, z plane, in chunks: chunk `(x, z)` starts at
+/// voxel `(x, z) * CHUNK_SIDE`.
+pub type ChunkCoord = IVec2;
+
+#[derive(Clone)]
+enum Shape {
+    /// Surface height of every column
+    Heightmap(Arc<dyn Fn(i32, i32) -> usize + Send + Sync>),
+    /// Whether every voxel is solid
+    Density(Arc<dyn Fn(i32, i32, i32) -> bool + Send + Sync>),
+}
+
+/// What the terrain of a [`GeneratorConfig`] is built from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TerrainShape {
+    Heightmap(TerrainConfig),
+    Density(DensityConfig),
+}
+
+/// Everything a [`Generator`] is built from, so the same world can be
+/// generated again, e.g. when loading a save.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeneratorConfig {
+    pub shape: TerrainShape,
+    /// World height, in voxels
+    pub height: u32,
+    pub layers: Option<BlockLayers>,
+}
+
+impl GeneratorConfig {
+    pub fn generator(&self) -> Generator {
+        let generator = match self.shape {
+            TerrainShape::Heightmap(config) => Generator::new(config.height_fn(), self.height),
+            TerrainShape::Density(config) => Generator::from_solid(config.solid_fn(), self.height),
+        };
+        match self.layers {
+            Some(layers) => generator.with_layers(layers),
+            None => generator,
+        }
+    }
+}
+
+/// Builds chunks from a height function or a solid query. Cheap to clone, and
+/// shared with the threads generating chunks.
+#[derive(Clone)]
+pub struct Generator {
+    shape: Shape,
+    height: u32,
+    /// `None` fills the world with [`DIRT`]
+    layers: Option<BlockLayers>,
+}
+
+impl Generator {
+    /// A world `height` voxels tall whose column `(x, z)` is solid up to and
+    /// including `height_at(x, z)`.
+    pub fn new(height_at: impl Fn(i32, i32) -> usize + Send + Sync + 'static, height: u32) -> Self {
+        Self {
+            shape: Shape::Heightmap(Arc::new(height_at)),
+            height,
+            layers: None,
+        }
+    }
+
+    /// A world `height` voxels tall whose voxel `(x, y, z)` is solid when
+    /// `solid(x, y, z)`, for terrains with caves and overhangs like
+    /// [`DensityConfig::solid_fn`](crate::terrain_builder::DensityConfig::solid_fn).
+    pub fn from_solid(
+        solid: impl Fn(i32, i32, i32) -> bool + Send + Sync + 'static,
+        height: u32,
+    ) -> Self {
+        Self {
+            shape: Shape::Density(Arc::new(solid)),
+            height,
+            layers: None,
+        }
+    }
+
+    /// Picks the solid blocks with `layers` instead of filling with dirt.
+    /// Density worlds have no slope, their surfaces are never bare stone.
+    pub fn with_layers(mut self, layers: BlockLayers) -> Self {
+        self.layers = Some(layers);
+        self
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn generate(&self, chunk: ChunkCoord) -> VoxelGrid {
+        let origin = ChunkWorld::chunk_origin(chunk);
+        let side = CHUNK_SIDE as u32;
+        let mut grid = VoxelGrid::new(UVec3::new(side, self.height, side));
+        for x in 0..side {
+            for z in 0..side {
+                let (wx, wz) = (origin.x + x as i32, origin.z + z as i32);
+                match &self.shape {
+                    Shape::Heightmap(height_at) => {
+                        let surface = height_at(wx, wz);
+                        let slope = self.slope(height_at.as_ref(), wx, wz);
+                        for y in 0..=surface.min(self.height as usize - 1) {
+                            let block = self.block(y as i32, (surface - y) as i32, slope);
+                            grid.set(UVec3::new(x, y as u32, z), block);
+                        }
+                    }
+                    Shape::Density(solid) => {
+                        // Top down, counting the solid voxels above
+                        let mut depth = None;
+                        for y in (0..self.height).rev() {
+                            depth = solid(wx, y as i32, wz).then(|| depth.map_or(0, |d| d + 1));
+                            if let Some(depth) = depth {
+                                grid.set(UVec3::new(x, y, z), self.block(y as i32, depth, 0));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// The generated block at the voxel `pos`, [`AIR`] above and below the
+    /// world.
+    pub fn get(&self, pos: IVec3) -> BlockId {
+        if !(0..self.height as i32).contains(&pos.y) {
+            return AIR;
+        }
+        match &self.shape {
+            Shape::Heightmap(height_at) => {
+                let surface = height_at(pos.x, pos.z) as i32;
+                if pos.y > surface {
+                    return AIR;
+                }
+                let slope = self.slope(height_at.as_ref(), pos.x, pos.z);
+                self.block(pos.y, surface - pos.y, slope)
+            }
+            Shape::Density(solid) => {
+                if !solid(pos.x, pos.y, pos.z) {
+                    return AIR;
+                }
+                // Past the soil, the exact depth doesn't matter
+                let max_depth = self.layers.map_or(0, |layers| layers.soil_depth + 1);
+                let depth = (1..=max_depth)
+                    .take_while(|d| {
+                        pos.y + d < self.height as i32 && solid(pos.x, pos.y + d, pos.z)
+                    })
+                    .count();
+                self.block(pos.y, depth as i32, 0)
+            }
+        }
+    }
+
+    /// Height of the highest solid voxel of column `(x, z)`, `None` when the
+    /// whole column is air.
+    pub fn surface(&self, x: i32, z: i32) -> Option<i32> {
+        match &self.shape {
+            Shape::Heightmap(height_at) => {
+                Some((height_at(x, z) as i32).min(self.height as i32 - 1))
+            }
+            Shape::Density(solid) => (0..self.height as i32).rev().find(|&y| solid(x, y, z)),
+        }
+    }
+
+    /// The sea level of the layers, `None` for worlds without layers.
+    pub fn sea_level(&self) -> Option<i32> {
+        self.layers.map(|layers| layers.sea_level)
+    }
+
+    /// Whether column `(x, z)` is under water: its surface is below the
+    /// [`BlockLayers::sea_level`], so the water covers it. Worlds without
+    /// layers have no sea.
+    pub fn is_underwater(&self, x: i32, z: i32) -> bool {
+        self.sea_level()
+            .is_some_and(|sea_level| self.surface(x, z).is_none_or(|surface| surface < sea_level))
+    }
+
+    fn block(&self, y: i32, depth: i32, slope: i32) -> BlockId {
+        match &self.layers {
+            Some(layers) => layers.block(y, depth, slope),
+            None => DIRT,
+        }
+    }
+
+    /// Steepest height difference from column `(x, z)` to its neighbours, 0
+    /// when unused.
+    fn slope(&self, height_at: &(dyn Fn(i32, i32) -> usize + Send + Sync), x: i32, z: i32) -> i32 {
+        if self.layers.is_none() {
+            return 0;
+        }
+        let surface = height_at(x, z) as i32;
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .map(|(dx, dz)| (height_at(x + dx, z + dz) as i32 - surface).abs())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The chunks a [`ChunkWorld::stream_around`] call requested and unloaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Streamed {
+    /// Newly in range, nearest first. They load once generated chunks are
+    /// given to [`ChunkWorld::insert`].
+    pub requested: Vec<ChunkCoord>,
+    /// Out of range, loaded or still requested.
+    pub unloaded: Vec<ChunkCoord>,
+}
+
+struct Chunk {
+    grid: VoxelGrid,
+    /// Changed since its mesh was last built
+    dirty: bool,
+    /// Changed since it was generated
+    edited: bool,
+}
+
+/// An endless voxel world kept in memory only around a point, as chunks
+/// generated on demand.
+pub struct ChunkWorld {
+    generator: Generator,
+    /// Chunks further than this from the streaming center, in chunks, are
+    /// unloaded.
+    pub radius: i32,
+    chunks: HashMap<ChunkCoord, Chunk>,
+    /// In range chunks waiting for their voxels, with whether a neighbour
+    /// changed since they were requested.
+    requested: HashMap<ChunkCoord, bool>,
+    /// Edited chunks out of range, loaded back as they were instead of being
+    /// generated again.
+    stashed: HashMap<ChunkCoord, VoxelGrid>,
+}
+
+impl ChunkWorld {
+    pub fn new(generator: Generator, radius: i32) -> Self {
+        Self {
+            generator,
+            radius,
+            chunks: HashMap::new(),
+            requested: HashMap::new(),
+            stashed: HashMap::new(),
+        }
+    }
+
+    pub fn generator(&self) -> &Generator {
+        &self.generator
+    }
+
+    /// The chunk containing the voxel `pos`.
+    pub fn chunk_of(pos: IVec3) -> ChunkCoord {
+        IVec2::new(pos.x.div_euclid(CHUNK_SIDE), pos.z.div_euclid(CHUNK_SIDE))
+    }
+
+    /// The voxel at the origin corner of `chunk`.
+    pub fn chunk_origin(chunk: ChunkCoord) -> IVec3 {
+        IVec3::new(chunk.x, 0, chunk.y) * CHUNK_SIDE
+    }
+
+    /// Requests the chunks within `radius` of `center` not loaded or requested
+    /// yet, and unloads the others. Edited chunks coming back in range are
+    /// loaded right away, dirty, instead of being requested.
+    ///
+    /// Edits survive unloading: the edited chunks are kept aside.
+    pub fn stream_around(&mut self, center: ChunkCoord) -> Streamed {
+        let radius = self.radius;
+        let distance = |chunk: ChunkCoord| (chunk - center).length_squared();
+        let in_range = |chunk: ChunkCoord| distance(chunk) <= radius * radius;
+
+        let mut unloaded: Vec<_> = self
+            .chunks
+            .keys()
+            .chain(self.requested.keys())
+            .copied()
+            .filter(|chunk| !in_range(*chunk))
+            .collect();
+        for chunk in &unloaded {
+            if let Some(removed) = self.chunks.remove(chunk)
+                && removed.edited
+            {
+                self.stashed.insert(*chunk, removed.grid);
+            }
+            self.requested.remove(chunk);
+        }
+        unloaded.sort_by_key(|chunk| (chunk.x, chunk.y));
+
+        let mut requested = vec![];
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                let chunk = center + IVec2::new(x, z);
+                let known = self.chunks.contains_key(&chunk) || self.requested.contains_key(&chunk);
+                if !in_range(chunk) || known {
+                    continue;
+                }
+                if let Some(grid) = self.stashed.remove(&chunk) {
+                    self.load_edited(chunk, grid);
+                } else {
+                    self.requested.insert(chunk, false);
+                    requested.push(chunk);
+                }
+            }
+        }
+        requested.sort_by_key(|chunk| (distance(*chunk), chunk.x, chunk.y));
+
+        Streamed {
+            requested,
+            unloaded,
+        }
+    }
+
+    /// Loads the voxels of a requested chunk. Returns `false`, dropping them,
+    /// when the chunk went out of range since. The chunk starts dirty only if a
+    /// neighbour changed after it was requested, or if it was
+    /// [restored](ChunkWorld::restore) meanwhile, the restored voxels replacing
+    /// `grid`.
+    pub fn insert(&mut self, chunk: ChunkCoord, grid: VoxelGrid) -> bool {
+        let Some(dirty) = self.requested.remove(&chunk) else {
+            return false;
+        };
+        match self.stashed.remove(&chunk) {
+            Some(edited) => self.load_edited(chunk, edited),
+            None => {
+                let edited = false;
+                self.chunks.insert(
+                    chunk,
+                    Chunk {
+                        grid,
+                        dirty,
+                        edited,
+                    },
+                );
+            }
+        }
+        true
+    }
+
+    /// Replaces the voxels of `chunk` by `grid`, edited voxels from a save,
+    /// whether it is loaded now or once in range.
+    pub fn restore(&mut self, chunk: ChunkCoord, grid: VoxelGrid) {
+        if self.chunks.contains_key(&chunk) {
+            self.load_edited(chunk, grid);
+        } else {
+            self.stashed.insert(chunk, grid);
+        }
+    }
+
+    /// The chunks changed since they were generated, loaded or not, with their
+    /// voxels, ordered by coordinates.
+    pub fn edited_chunks(&self) -> Vec<(ChunkCoord, &VoxelGrid)> {
+        let loaded = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.edited)
+            .map(|(coord, chunk)| (*coord, &chunk.grid));
+        let stashed = self.stashed.iter().map(|(coord, grid)| (*coord, grid));
+        let mut edited: Vec<_> = loaded.chain(stashed).collect();
+        edited.sort_by_key(|(chunk, _)| (chunk.x, chunk.y));
+        edited
+    }
+
+    pub fn is_loaded(&self, chunk: ChunkCoord) -> bool {
+        self.chunks.contains_key(&chunk)
+    }
+
+    /// Whether `chunk` is in range but not loaded yet.
+    pub fn is_requested(&self, chunk: ChunkCoord) -> bool {
+        self.requested.contains_key(&chunk)
+    }
+
+    /// The voxels of `chunk`, if loaded.
+    pub fn chunk(&self, chunk: ChunkCoord) -> Option<&VoxelGrid> {
+        self.chunks.get(&chunk).map(|chunk| &chunk.grid)
+    }
+
+    /// Copies of the loaded chunks sharing a side with `chunk`, what meshing it
+    /// needs besides its own voxels.
+    pub fn neighbours(&self, chunk: ChunkCoord) -> HashMap<ChunkCoord, VoxelGrid> {
+        [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+            .into_iter()
+            .map(|offset| chunk + offset)
+            .filter_map(|neighbour| Some((neighbour, self.chunk(neighbour)?.clone())))
+            .collect()
+    }
+
+    /// The block at the voxel `pos`, as generated when its chunk is not loaded
+    /// nor edited. Everything above and below the world is [`AIR`].
+    pub fn get(&self, pos: IVec3) -> BlockId {
+        let chunk = Self::chunk_of(pos);
+        let local = pos - Self::chunk_origin(chunk);
+        match (self.chunks.get(&chunk), self.stashed.get(&chunk)) {
+            (Some(loaded), _) => loaded.grid.get(local),
+            (None, Some(stashed)) => stashed.get(local),
+            (None, None) => self.generator.get(pos),
+        }
+    }
+
+    /// Sets the voxel `pos` to `block`, marking its chunk dirty, along with the
+    /// chunks whose faces it may hide or reveal. Returns `false`, doing nothing,
+    /// when `pos` is not in a loaded chunk.
+    pub fn set(&mut self, pos: IVec3, block: BlockId) -> bool {
+        let chunk = Self::chunk_of(pos);
+        let local = pos - Self::chunk_origin(chunk);
+        let Some(loaded) = self.chunks.get_mut(&chunk) else {
+            return false;
+        };
+        if !loaded.grid.contains(local) {
+            return false;
+        }
+        loaded.grid.set(local.as_uvec3(), block);
+        loaded.dirty = true;
+        loaded.edited = true;
+
+        for offset in [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z] {
+            let neighbour = Self::chunk_of(pos + offset);
+            if let Some(neighbour) = self.chunks.get_mut(&neighbour) {
+                neighbour.dirty = true;
+            } else if let Some(changed) = self.requested.get_mut(&neighbour) {
+                // Its voxels may be meshed against the old ones
+                *changed = true;
+            }
+        }
+        true
+    }
+
+    /// The loaded chunks changed since the last call, clearing their dirty flag.
+    pub fn take_dirty(&mut self) -> Vec<ChunkCoord> {
+        let mut dirty: Vec<_> = self
+            .chunks
+            .iter_mut()
+            .filter(|(_, chunk)| chunk.dirty)
+            .map(|(coord, chunk)| {
+                chunk.dirty = false;
+                *coord
+            })
+            .collect();
+        dirty.sort_by_key(|chunk| (chunk.x, chunk.y));
+        dirty
+    }
+
+    /// Loads `grid` as the edited voxels of `chunk`, dirtying the chunks meshed
+    /// against its previous voxels.
+    fn load_edited(&mut self, chunk: ChunkCoord, grid: VoxelGrid) {
+        self.requested.remove(&chunk);
+        let (dirty, edited) = (true, true);
+        self.chunks.insert(
+            chunk,
+            Chunk {
+                grid,
+                dirty,
+                edited,
+            },
+        );
+
+        for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            if let Some(neighbour) = self.chunks.get_mut(&(chunk + offset)) {
+                neighbour.dirty = true;
+            } else if let Some(changed) = self.requested.get_mut(&(chunk + offset)) {
+                *changed = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        terrain_builder::{CaveConfig, DensityConfig, TerrainConfig},
+        voxel::{GRASS, SAND, SNOW, STONE},
+    };
+
+    /// Surface at `x + z` clamped to `0..8`, to tell columns apart.
+    fn world(radius: i32) -> ChunkWorld {
+        ChunkWorld::new(
+            Generator::new(|x, z| (x + z).clamp(0, 8) as usize, 16),
+            radius,
+        )
+    }
+
+    /// Streams around `center`, loading the requested chunks right away.
+    fn load_around(world: &mut ChunkWorld, center: ChunkCoord) -> Streamed {
+        let streamed = world.stream_around(center);
+        for chunk in &streamed.requested {
+            assert!(world.insert(*chunk, world.generator().generate(*chunk)));
+        }
+        streamed
+    }
+
+    #[test]
+    fn edits_survive_unloading() {
+        let mut world = world(0);
+        load_around(&mut world, IVec2::ZERO);
+        assert!(world.set(IVec3::new(4, 12, 4), DIRT));
+        world.take_dirty();
+
+        let streamed = load_around(&mut world, IVec2::new(5, 0));
+        assert_eq!(streamed.unloaded, [IVec2::ZERO]);
+        assert_eq!(world.edited_chunks().len(), 1);
+
+        // Loaded back right away, to be meshed again
+        let streamed = load_around(&mut world, IVec2::ZERO);
+        assert!(streamed.requested.is_empty());
+        assert_eq!(world.get(IVec3::new(4, 12, 4)), DIRT);
+        assert_eq!(world.take_dirty(), [IVec2::ZERO]);
+    }
+
+    #[test]
+    fn restored_chunks_replace_the_generated_ones() {
+        let mut world = world(1);
+        world.stream_around(IVec2::ZERO);
+        let mut grid = world.generator().generate(IVec2::X);
+        grid.set(UVec3::new(0, 15, 0), DIRT);
+        world.restore(IVec2::X, grid.clone());
+        world.restore(IVec2::new(9, 9), grid.clone());
+
+        // Generated before the restore, loaded after
+        assert!(world.insert(IVec2::X, world.generator().generate(IVec2::X)));
+        assert_eq!(world.chunk(IVec2::X), Some(&grid));
+        assert_eq!(world.take_dirty(), [IVec2::X]);
+
+        let edited: Vec<_> = world.edited_chunks().into_iter().map(|(c, _)| c).collect();
+        assert_eq!(edited, [IVec2::X, IVec2::new(9, 9)]);
+    }
+
+    #[test]
+    fn streams_the_chunks_within_the_radius_nearest_first() {
+        let mut world = world(1);
+
+        let streamed = load_around(&mut world, IVec2::ZERO);
+        assert_eq!(
+            streamed.requested.len(),
+            5,
+            "the center and its 4 neighbours"
+        );
+        assert_eq!(streamed.requested[0], IVec2::ZERO);
+        assert!(streamed.unloaded.is_empty());
+        assert_eq!(world.stream_around(IVec2::ZERO), Streamed::default());
+
+        let streamed = world.stream_around(IVec2::new(1, 0));
+        assert_eq!(
+            streamed.unloaded,
+            [IVec2::new(-1, 0), IVec2::new(0, -1), IVec2::new(0, 1)]
+        );
+        assert_eq!(
+            streamed.requested,
+            [IVec2::new(1, -1), IVec2::new(1, 1), IVec2::new(2, 0)]
+        );
+        assert!(world.is_requested(IVec2::new(2, 0)));
+        assert!(!world.is_loaded(IVec2::new(2, 0)));
+    }
+
+    #[test]
+    fn chunks_out_of_range_before_loading_are_dropped() {
+        let mut world = world(0);
+        let streamed = world.stream_around(IVec2::ZERO);
+        let grid = world.generator().generate(IVec2::ZERO);
+
+        let streamed_away = world.stream_around(IVec2::new(5, 5));
+        assert_eq!(streamed_away.unloaded, streamed.requested);
+        assert!(!world.insert(IVec2::ZERO, grid));
+        assert!(!world.is_loaded(IVec2::ZERO));
+    }
+
+    #[test]
+    fn chunks_follow_the_height_function_on_both_sides_of_the_origin() {
+        let mut world = world(1);
+        let columns = [IVec2::new(3, 2), IVec2::new(-1, -40), IVec2::new(-20, 27)];
+
+        let generated: Vec<_> = columns
+            .iter()
+            .map(|c| {
+                (
+                    world.get(IVec3::new(c.x, 5, c.y)),
+                    world.get(IVec3::new(c.x, 6, c.y)),
+                )
+            })
+            .collect();
+        assert_eq!(generated, [(DIRT, AIR), (AIR, AIR), (DIRT, DIRT)]);
+
+        for column in columns {
+            load_around(
+                &mut world,
+                ChunkWorld::chunk_of(IVec3::new(column.x, 0, column.y)),
+            );
+            for y in -1..=16 {
+                let pos = IVec3::new(column.x, y, column.y);
+                let expected = if (0..=(column.x + column.y).clamp(0, 8)).contains(&y) {
+                    DIRT
+                } else {
+                    AIR
+                };
+                assert_eq!(world.get(pos), expected, "{pos}");
+            }
+        }
+    }
+
+    #[test]
+    fn edits_dirty_their_chunk_and_the_ones_they_border() {
+        let mut world = world(1);
+        load_around(&mut world, IVec2::ZERO);
+        assert!(world.take_dirty().is_empty(), "loaded chunks come meshed");
+
+        assert!(world.set(IVec3::new(5, 10, 5), DIRT));
+        assert_eq!(world.get(IVec3::new(5, 10, 5)), DIRT);
+        assert_eq!(world.take_dirty(), [IVec2::ZERO]);
+
+        // On the -x edge of the center chunk
+        world.set(IVec3::new(0, 10, 5), AIR);
+        assert_eq!(world.take_dirty(), [IVec2::new(-1, 0), IVec2::ZERO]);
+
+        assert!(!world.set(IVec3::new(500, 1, 0), DIRT), "not loaded");
+        assert!(!world.set(IVec3::new(0, 16, 0), DIRT), "above the world");
+        assert!(world.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn edits_next_to_requested_chunks_dirty_them_once_loaded() {
+        let mut world = world(1);
+        load_around(&mut world, IVec2::ZERO);
+        let streamed = world.stream_around(IVec2::new(1, 0));
+        let requested = IVec2::new(2, 0);
+        assert!(streamed.requested.contains(&requested));
+
+        // On the +x edge of chunk (1, 0)
+        world.set(IVec3::new(2 * CHUNK_SIDE - 1, 10, 5), DIRT);
+        world.take_dirty();
+        world.insert(requested, world.generator().generate(requested));
+
+        assert_eq!(world.take_dirty(), [requested]);
+    }
+
+    #[test]
+    fn density_generators_match_their_solid_query() {
+        let config = DensityConfig {
+            overhang: 12.0,
+            caves: Some(CaveConfig::new(4)),
+            ..DensityConfig::new(TerrainConfig::new(4, 24))
+        };
+        let solid = config.solid_fn();
+        let generator = Generator::from_solid(config.solid_fn(), 32);
+
+        let chunk = IVec2::new(-1, 2);
+        let grid = generator.generate(chunk);
+        let origin = ChunkWorld::chunk_origin(chunk);
+        let mut holes = 0;
+        for x in 0..CHUNK_SIDE {
+            for y in -1..=32 {
+                for z in 0..CHUNK_SIDE {
+                    let pos = origin + IVec3::new(x, y, z);
+                    let expected = (0..32).contains(&y) && solid(pos.x, pos.y, pos.z);
+                    assert_eq!(grid.is_solid(IVec3::new(x, y, z)), expected, "{pos}");
+                    assert_eq!(generator.get(pos) != AIR, expected, "{pos}");
+                    holes += (!expected && solid(pos.x, pos.y + 1, pos.z)) as usize;
+                }
+            }
+        }
+        assert!(holes > 0, "no caves nor overhangs in the chunk");
+    }
+
+    #[test]
+    fn layers_pick_the_generated_blocks() {
+        let layers = BlockLayers {
+            sea_level: 2,
+            snow_line: 9,
+            max_soil_slope: 2,
+            soil_depth: 2,
+        };
+        // A ramp along x, steep past x = 8
+        let ramp = |x: i32, _| {
+            if x < 8 {
+                x.clamp(0, 8) as usize
+            } else {
+                8 + 4 * (x - 8) as usize
+            }
+        };
+        let generators = [
+            Generator::new(ramp, 32).with_layers(layers),
+            Generator::from_solid(move |x, y, z| y >= 0 && y as usize <= ramp(x, z), 32)
+                .with_layers(layers),
+        ];
+
+        for generator in &generators {
+            let grid = generator.generate(IVec2::ZERO);
+            for x in 0..CHUNK_SIDE {
+                for y in 0..32 {
+                    let pos = IVec3::new(x, y, 3);
+                    assert_eq!(grid.get(pos), generator.get(pos), "{pos}");
+                }
+            }
+            let block = |x, y| generator.get(IVec3::new(x, y, 0));
+            assert_eq!(block(1, 1), SAND, "beach");
+            assert_eq!(block(5, 5), GRASS);
+            assert_eq!(block(5, 4), DIRT);
+            assert_eq!(block(5, 2), STONE);
+        }
+
+        let [heightmap, density] = &generators;
+        assert_eq!(heightmap.get(IVec3::new(10, 16, 0)), STONE, "cliff");
+        assert_eq!(density.get(IVec3::new(10, 16, 0)), SNOW, "no slope");
+    }
+
+    #[test]
+    fn columns_below_the_sea_level_are_underwater() {
+        let layers = BlockLayers {
+            sea_level: 3,
+            snow_line: 9,
+            max_soil_slope: 2,
+            soil_depth: 2,
+        };
+        let ramp = |x: i32, _| x.clamp(0, 8) as usize;
+        let generators = [
+            Generator::new(ramp, 16).with_layers(layers),
+            Generator::from_solid(move |x, y, z| y >= 0 && y as usize <= ramp(x, z), 16)
+                .with_layers(layers),
+        ];
+
+        for generator in &generators {
+            assert_eq!(generator.surface(5, 0), Some(5));
+            let underwater: Vec<_> = (-2..8).filter(|&x| generator.is_underwater(x, 7)).collect();
+            assert_eq!(underwater, [-2, -1, 0, 1, 2], "the beach at 3 is dry");
+        }
+
+        let hole = Generator::from_solid(|x, y, _| x != 0 && y == 5, 16).with_layers(layers);
+        assert_eq!(hole.surface(0, 0), None);
+        assert!(hole.is_underwater(0, 0), "flooded to the bottom");
+        assert!(!hole.is_underwater(1, 0));
+        assert!(!Generator::new(ramp, 16).is_underwater(0, 0), "no sea");
+    }
+}
@@ -0,0 +1,188 @@
+//! Renders every entity alone from a fixed camera into an offscreen framebuffer
+//! and compares the result with the reference PNGs in `tests/golden/`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to (re)write the references after an intended
+//! visual change. On mismatch a diff image is written under the cargo target
+//! tmpdir and its path is printed in the failure message.
+
+use std::path::PathBuf;
+
+use glam::Vec3;
+use glutin_hello_world::{
+    entities::{
+        Entity,
+        hello_triangle::HelloTriangle,
+        sun::Sun,
+        tex_cube::TexCube,
+        tex_square::{Square, TexSquare},
+        utah_teapot::UtahTeapot,
+    },
+    headless::HeadlessContext,
+    helpers::{GlPosition, Mat3DUpdate},
+    renderer::{
+        Renderer,
+        atlas::UvRect,
+        framebuffer::Framebuffer,
+        shader::GlslPass,
+        uniform_buffer::{FrameGlobals, LightingGlobals},
+    },
+    scene::CLEAR_COLOR,
+};
+
+const DIMENSIONS: glam::USizeVec2 = glam::USizeVec2::new(200, 150);
+const EYE: Vec3 = Vec3::new(0.0, 2.5, 4.5);
+const TARGET: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+const LIGHT: Vec3 = Vec3::new(3.0, 6.0, 4.0);
+
+/// Max difference allowed on any channel of a pixel before it counts as a mismatch.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of mismatched pixels tolerated, to absorb rasterization differences
+/// on triangle edges between drivers.
+const MAX_MISMATCH_RATIO: f64 = 0.005;
+
+fn render(mut entity: impl Entity) -> image::RgbaImage {
+    let context = HeadlessContext::new().expect("Headless GL context should be creatable");
+    let gl_fns = context.gl_fns.clone();
+
+    let framebuffer = Framebuffer::new(gl_fns.clone(), DIMENSIONS).unwrap();
+    framebuffer.bind();
+
+    let mut renderer = Renderer::new(gl_fns.clone(), DIMENSIONS, CLEAR_COLOR);
+    renderer.resize(DIMENSIONS.x as i32, DIMENSIONS.y as i32);
+
+    entity.init(gl_fns, Mat3DUpdate::default(), &[]).unwrap();
+
+    let frame = FrameGlobals::new(
+        glam::Mat4::look_at_rh(EYE, TARGET, Vec3::Y),
+        glam::Vec2::new(DIMENSIONS.x as f32, DIMENSIONS.y as f32),
+        EYE,
+    );
+
+    renderer.clear();
+    renderer.bind_globals(&frame, &LightingGlobals::new(LIGHT, CLEAR_COLOR));
+    renderer.draw(
+        [&mut entity as &mut dyn GlslPass].into_iter(),
+        Mat3DUpdate {
+            // A fixed model freezes the time based animations (triangle spin, sun orbit)
+            model: Some(glam::Mat4::IDENTITY),
+        },
+        &[],
+    );
+
+    let image = framebuffer.read_rgba8();
+
+    // GL objects must be freed while the context is still alive.
+    drop(entity);
+    image
+}
+
+/// Returns the amount of mismatched pixels and an image highlighting them in red
+/// over a dimmed copy of `expected`.
+fn diff(actual: &image::RgbaImage, expected: &image::RgbaImage) -> (usize, image::RgbaImage) {
+    let mut mismatched = 0;
+    let diff = image::RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let e = expected.get_pixel(x, y);
+        let differs =
+            a.0.iter()
+                .zip(e.0)
+                .any(|(a, e)| a.abs_diff(e) > CHANNEL_TOLERANCE);
+        if differs {
+            mismatched += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            image::Rgba([e[0] / 4, e[1] / 4, e[2] / 4, 255])
+        }
+    });
+    (mismatched, diff)
+}
+
+fn assert_golden(name: &str, entity: impl Entity) {
+    let actual = render(entity);
+
+    let reference = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        actual.save(&reference).unwrap();
+        return;
+    }
+
+    let expected = image::open(&reference)
+        .unwrap_or_else(|e| panic!("{reference:?} should exist (UPDATE_GOLDEN=1 writes it): {e}"))
+        .to_rgba8();
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "{name}: rendered size differs from the reference"
+    );
+
+    let (mismatched, diff) = diff(&actual, &expected);
+    let ratio = mismatched as f64 / (expected.width() * expected.height()) as f64;
+    if ratio > MAX_MISMATCH_RATIO {
+        let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden-diff");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let actual_path = out_dir.join(format!("{name}.actual.png"));
+        let diff_path = out_dir.join(format!("{name}.diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{name}: {mismatched} pixels ({:.2}%) differ from {reference:?}, see {actual_path:?} and {diff_path:?}",
+            ratio * 100.0
+        );
+    }
+}
+
+#[test]
+fn golden_hello_triangle() {
+    assert_golden(
+        "hello_triangle",
+        HelloTriangle::new((GlPosition::new(0.0, 1.0, 0.0), 2.0)),
+    );
+}
+
+#[test]
+fn golden_tex_square() {
+    assert_golden(
+        "tex_square",
+        TexSquare::new(
+            vec![Square {
+                bottom_left: GlPosition::new(-2.0, 0.0, -2.0),
+                top_right: GlPosition::new(2.0, 0.0, 2.0),
+                uv_rect: UvRect::FULL,
+            }],
+            Some("./assets/dirt.webp".into()),
+        ),
+    );
+}
+
+#[test]
+fn golden_tex_cube() {
+    assert_golden(
+        "tex_cube",
+        TexCube::new(
+            vec![
+                Vec3::new(-1.0, 0.5, 0.0),
+                Vec3::new(1.0, 0.5, 0.0),
+                Vec3::new(0.0, 1.5, 0.0),
+            ],
+            1.0,
+            Some("./assets/dirt.webp".into()),
+        ),
+    );
+}
+
+#[test]
+fn golden_sun() {
+    assert_golden("sun", Sun::new(GlPosition::new(0.0, 0.0, 0.0)));
+}
+
+#[test]
+fn golden_utah_teapot() {
+    assert_golden(
+        "utah_teapot",
+        UtahTeapot::new(GlPosition::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+    );
+}